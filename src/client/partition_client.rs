@@ -15,7 +15,7 @@ use crate::pserverpb::{rpc_client::RpcClient, *};
 use crate::util::error::*;
 use crate::*;
 use tonic::transport::{Channel, Endpoint};
-use tonic::Request;
+use tonic::{Request, Streaming};
 #[derive(Default)]
 pub struct PartitionClient {
     pub addr: String,
@@ -48,6 +48,40 @@ impl PartitionClient {
         result_obj_code!(resp)
     }
 
+    pub async fn batch_write(
+        &self,
+        mut rpc_client: RpcClient<Channel>,
+        req: BatchWriteRequest,
+    ) -> ASResult<BatchWriteResponse> {
+        let resp = conver(rpc_client.batch_write(Request::new(req)).await)?.into_inner();
+        result_obj_code!(resp)
+    }
+
+    pub async fn scan(
+        &self,
+        mut rpc_client: RpcClient<Channel>,
+        req: ScanRequest,
+    ) -> ASResult<ScanResponse> {
+        let resp = conver(rpc_client.scan(Request::new(req)).await)?.into_inner();
+        result_obj_code!(resp)
+    }
+
+    pub async fn export(
+        &self,
+        mut rpc_client: RpcClient<Channel>,
+        req: ExportRequest,
+    ) -> ASResult<Streaming<ExportResponse>> {
+        Ok(conver(rpc_client.export(Request::new(req)).await)?.into_inner())
+    }
+
+    pub async fn subscribe(
+        &self,
+        mut rpc_client: RpcClient<Channel>,
+        req: SubscribeRequest,
+    ) -> ASResult<Streaming<SubscribeResponse>> {
+        Ok(conver(rpc_client.subscribe(Request::new(req)).await)?.into_inner())
+    }
+
     pub async fn get(
         &self,
         mut rpc_client: RpcClient<Channel>,
@@ -78,6 +112,39 @@ impl PartitionClient {
         result_obj_code!(resp)
     }
 
+    pub async fn change_member(&self, req: MemberChangeRequest) -> ASResult<GeneralResponse> {
+        let mut rpc_client = RpcClient::new(Endpoint::from_shared(self.addr())?.connect().await?);
+        let resp = rpc_client
+            .change_member(Request::new(req))
+            .await?
+            .into_inner();
+        result_obj_code!(resp)
+    }
+
+    pub async fn split_partition(&self, req: SplitPartitionRequest) -> ASResult<GeneralResponse> {
+        let mut rpc_client = RpcClient::new(Endpoint::from_shared(self.addr())?.connect().await?);
+        let resp = rpc_client
+            .split_partition(Request::new(req))
+            .await?
+            .into_inner();
+        result_obj_code!(resp)
+    }
+
+    pub async fn reindex(&self, req: ReindexRequest) -> ASResult<ReindexResponse> {
+        let mut rpc_client = RpcClient::new(Endpoint::from_shared(self.addr())?.connect().await?);
+        let resp = rpc_client.reindex(Request::new(req)).await?.into_inner();
+        result_obj_code!(resp)
+    }
+
+    pub async fn update_schema(&self, req: UpdateSchemaRequest) -> ASResult<GeneralResponse> {
+        let mut rpc_client = RpcClient::new(Endpoint::from_shared(self.addr())?.connect().await?);
+        let resp = rpc_client
+            .update_schema(Request::new(req))
+            .await?
+            .into_inner();
+        result_obj_code!(resp)
+    }
+
     //offload partition , if partition not exist it not return err
     pub async fn offload_partition(&self, req: PartitionRequest) -> ASResult<GeneralResponse> {
         let mut rpc_client = RpcClient::new(Endpoint::from_shared(self.addr())?.connect().await?);
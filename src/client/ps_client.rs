@@ -16,20 +16,30 @@ use crate::client::partition_client::*;
 use crate::pserver::simba::aggregation;
 use crate::pserverpb::rpc_client::RpcClient;
 use crate::pserverpb::*;
-use crate::util::{coding, config, entity::*, error::*};
+use crate::util::{
+    coding, config, entity::*, error::*, time::current_millis, try_send_until_closed,
+};
 use crate::*;
-use async_std::{sync::channel, task};
+use async_std::{
+    sync::{channel, Sender},
+    task,
+};
 use log::{error, info, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use tonic::transport::{Channel, Endpoint};
 
 const RETRY: usize = 5;
 
+//the cache of collection will be expired, so split and failover can be found
+const CACHE_TTL_MS: u64 = 10000;
+
 pub struct CollectionInfo {
     pub collection: Collection,
     pub partitions: Vec<Partition>,
     pub fields: HashMap<String, Field>,
+    pub cache_time: u64,
 }
 
 pub struct PsClient {
@@ -56,7 +66,10 @@ impl PsClient {
         collection_name: String,
         id: String,
         sort_key: String,
-        version: i64,
+        if_version: i64,
+        if_absent: bool,
+        ttl: u64,
+        expire_at: u64,
         source: Vec<u8>,
         wt: i32,
     ) -> ASResult<GeneralResponse> {
@@ -66,7 +79,10 @@ impl PsClient {
                     collection_name.as_str(),
                     id.as_str(),
                     sort_key.as_str(),
-                    version,
+                    if_version,
+                    if_absent,
+                    ttl,
+                    expire_at,
                     &source,
                     wt,
                 )
@@ -76,7 +92,7 @@ impl PsClient {
                     return Ok(r);
                 }
                 Err(e) => {
-                    if self.check_err_cache(i, collection_name.as_str(), &e) {
+                    if self.check_err_cache(i, collection_name.as_str(), &e).await {
                         continue 'outer;
                     } else {
                         return Err(e);
@@ -92,7 +108,10 @@ impl PsClient {
         collection_name: &str,
         id: &str,
         sort_key: &str,
-        version: i64,
+        if_version: i64,
+        if_absent: bool,
+        ttl: u64,
+        expire_at: u64,
         source: &Vec<u8>,
         wt: i32,
     ) -> ASResult<GeneralResponse> {
@@ -109,31 +128,256 @@ impl PsClient {
                     source: source.to_owned(),
                     slot: ps.slot,
                     partition_id: ps.partition_id,
-                    version: version,
+                    version: 0,
                     vectors: Vec::default(),
+                    expire_at: expire_at,
                 }),
                 write_type: wt,
+                if_version: if_version,
+                if_absent: if_absent,
+                ttl: ttl,
             },
         )
         .await
     }
 
+    // bulk groups the writes by partition and sends one batch to each of them,
+    // the result is one to one with writes, one failed document not fails the others.
+    // the documents which slot has been moved by split are routed again
+    pub async fn bulk(
+        &self,
+        collection_name: String,
+        writes: Vec<WriteDocumentRequest>,
+    ) -> ASResult<Vec<GeneralResponse>> {
+        let mut results = vec![GeneralResponse::default(); writes.len()];
+        let mut writes: Vec<(usize, WriteDocumentRequest)> =
+            writes.into_iter().enumerate().collect();
+
+        for i in 0..RETRY {
+            let moved = self
+                ._bulk(collection_name.as_str(), &writes, &mut results)
+                .await;
+            if moved.len() == 0 {
+                break;
+            }
+            let e = err!(
+                Code::SlotMoved,
+                "the slots of {} documents have been moved",
+                moved.len()
+            );
+            if !self.check_err_cache(i, collection_name.as_str(), &e).await {
+                break;
+            }
+            writes.retain(|(index, _)| moved.contains(index));
+        }
+
+        Ok(results)
+    }
+
+    //it returns the indexes of writes which slot has been moved
+    async fn _bulk(
+        &self,
+        collection_name: &str,
+        writes: &[(usize, WriteDocumentRequest)],
+        results: &mut [GeneralResponse],
+    ) -> HashSet<usize> {
+        let mut groups: HashMap<u32, (PartitionClient, Vec<usize>, Vec<WriteDocumentRequest>)> =
+            HashMap::new();
+
+        for (i, req) in writes {
+            let mut req = req.clone();
+            let doc = match req.doc.as_mut() {
+                Some(doc) => doc,
+                None => {
+                    results[*i] = err!(Code::ParamError, "write request not has document").into();
+                    continue;
+                }
+            };
+            let ps = match self
+                .select_partition(collection_name, doc.id.as_str())
+                .await
+            {
+                Ok(ps) => ps,
+                Err(e) => {
+                    results[*i] = e.into();
+                    continue;
+                }
+            };
+
+            doc.slot = ps.slot;
+            doc.partition_id = ps.partition_id;
+            req.collection_id = ps.collection_id;
+            req.partition_id = ps.partition_id;
+
+            let group = groups
+                .entry(ps.partition_id)
+                .or_insert((ps, Vec::new(), Vec::new()));
+            group.1.push(*i);
+            group.2.push(req);
+        }
+
+        let (tx, rx) = channel(10);
+
+        for (_, (ps, indexs, writes)) in groups {
+            let rpc_client = match self.channel_cache(ps.addr.as_str()).await {
+                Ok(rpc_client) => rpc_client,
+                Err(e) => {
+                    for i in indexs {
+                        results[i] = ASError::Error(e.code(), e.message()).into();
+                    }
+                    continue;
+                }
+            };
+            let tx = tx.clone();
+            task::spawn(async move {
+                let req = BatchWriteRequest {
+                    collection_id: ps.collection_id,
+                    partition_id: ps.partition_id,
+                    writes: writes,
+                    atomic: false,
+                };
+                tx.send((indexs, ps.batch_write(rpc_client, req).await))
+                    .await;
+            });
+        }
+
+        drop(tx);
+
+        let mut moved = HashSet::new();
+        while let Ok((indexs, result)) = rx.recv().await {
+            match result {
+                Ok(resp) => {
+                    for (i, gr) in indexs.into_iter().zip(resp.results) {
+                        if gr.code == Code::SlotMoved as i32 {
+                            moved.insert(i);
+                        }
+                        results[i] = gr;
+                    }
+                }
+                Err(e) => {
+                    if e.code() == Code::RocksDBNotFound {
+                        warn!("to remove cache by collection:{}", collection_name);
+                        self.collection_cache
+                            .write()
+                            .unwrap()
+                            .remove(collection_name);
+                    }
+                    for i in indexs {
+                        if e.code() == Code::SlotMoved {
+                            moved.insert(i);
+                        }
+                        results[i] = ASError::Error(e.code(), e.message()).into();
+                    }
+                }
+            }
+        }
+
+        moved
+    }
+
+    // transaction sends the writes to their partition as one atomic batch, so all of them
+    // are applied or none. the documents must be in the same partition
+    pub async fn transaction(
+        &self,
+        collection_name: String,
+        writes: Vec<WriteDocumentRequest>,
+    ) -> ASResult<Vec<GeneralResponse>> {
+        'outer: for i in 0..RETRY {
+            match self
+                ._transaction(collection_name.as_str(), writes.clone())
+                .await
+            {
+                Ok(r) => {
+                    return Ok(r);
+                }
+                Err(e) => {
+                    if self.check_err_cache(i, collection_name.as_str(), &e).await {
+                        continue 'outer;
+                    } else {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        panic!("out of range")
+    }
+
+    async fn _transaction(
+        &self,
+        collection_name: &str,
+        mut writes: Vec<WriteDocumentRequest>,
+    ) -> ASResult<Vec<GeneralResponse>> {
+        let mut partition: Option<PartitionClient> = None;
+
+        for req in writes.iter_mut() {
+            let doc = match req.doc.as_mut() {
+                Some(doc) => doc,
+                None => return result!(Code::ParamError, "write request not has document"),
+            };
+            let ps = self
+                .select_partition(collection_name, doc.id.as_str())
+                .await?;
+            if let Some(p) = partition.as_ref() {
+                if p.partition_id != ps.partition_id {
+                    return result!(
+                        Code::ParamError,
+                        "the documents of transaction must be in one partition, id:{} is in partition:{} not:{}",
+                        doc.id,
+                        ps.partition_id,
+                        p.partition_id
+                    );
+                }
+            }
+
+            doc.slot = ps.slot;
+            doc.partition_id = ps.partition_id;
+            req.collection_id = ps.collection_id;
+            req.partition_id = ps.partition_id;
+            partition = Some(ps);
+        }
+
+        let ps = match partition {
+            Some(ps) => ps,
+            None => return result!(Code::ParamError, "transaction not has write"),
+        };
+
+        let rpc_client = self.channel_cache(ps.addr.as_str()).await?;
+        let resp = ps
+            .batch_write(
+                rpc_client,
+                BatchWriteRequest {
+                    collection_id: ps.collection_id,
+                    partition_id: ps.partition_id,
+                    writes: writes,
+                    atomic: true,
+                },
+            )
+            .await?;
+        Ok(resp.results)
+    }
+
     pub async fn get(
         &self,
         collection_name: String,
         id: String,
         sort_key: String,
+        source_filter: Option<SourceFilter>,
     ) -> ASResult<DocumentResponse> {
         'outer: for i in 0..RETRY {
             match self
-                ._get(collection_name.as_str(), id.as_str(), sort_key.as_str())
+                ._get(
+                    collection_name.as_str(),
+                    id.as_str(),
+                    sort_key.as_str(),
+                    source_filter.clone(),
+                )
                 .await
             {
                 Ok(r) => {
                     return Ok(r);
                 }
                 Err(e) => {
-                    if self.check_err_cache(i, collection_name.as_str(), &e) {
+                    if self.check_err_cache(i, collection_name.as_str(), &e).await {
                         continue 'outer;
                     } else {
                         return Err(e);
@@ -148,6 +392,7 @@ impl PsClient {
         collection_name: &str,
         id: &str,
         sort_key: &str,
+        source_filter: Option<SourceFilter>,
     ) -> ASResult<DocumentResponse> {
         let ps = self.select_partition(collection_name, id).await?;
 
@@ -158,16 +403,204 @@ impl PsClient {
                 partition_id: ps.partition_id,
                 id: id.to_string(),
                 sort_key: sort_key.to_string(),
+                source_filter: source_filter,
             },
         )
         .await
     }
 
+    pub async fn scan(&self, collection_name: String, req: ScanRequest) -> ASResult<ScanResponse> {
+        'outer: for i in 0..RETRY {
+            match self._scan(collection_name.as_str(), req.clone()).await {
+                Ok(r) => {
+                    return Ok(r);
+                }
+                Err(e) => {
+                    if self.check_err_cache(i, collection_name.as_str(), &e).await {
+                        continue 'outer;
+                    } else {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        panic!("out of range")
+    }
+
+    async fn _scan(&self, collection_name: &str, mut req: ScanRequest) -> ASResult<ScanResponse> {
+        let ps = self
+            .select_partition(collection_name, req.id.as_str())
+            .await?;
+
+        req.collection_id = ps.collection_id;
+        req.partition_id = ps.partition_id;
+
+        ps.scan(self.channel_cache(ps.addr.as_str()).await?, req)
+            .await
+    }
+
+    // export sends the documents of partitions one by one to tx, every partition is
+    // read on its own snapshot. it stops at the first error or the receiver closed
+    pub async fn export(
+        &self,
+        collection_name: &str,
+        query: String,
+        def_fields: Vec<String>,
+        tx: Sender<ASResult<ExportResponse>>,
+    ) {
+        let c = match self.cache_collection(collection_name).await {
+            Ok(c) => c,
+            Err(e) => {
+                try_send_until_closed(&tx, Err(e)).await;
+                return;
+            }
+        };
+
+        for p in c.partitions.iter() {
+            let ps = PartitionClient {
+                addr: p.leader.to_string(),
+                collection_id: c.collection.id,
+                partition_id: p.id,
+                slot: 0,
+            };
+
+            let req = ExportRequest {
+                collection_id: ps.collection_id,
+                partition_id: ps.partition_id,
+                query: query.clone(),
+                def_fields: def_fields.clone(),
+            };
+
+            let mut stream = match self.channel_cache(ps.addr.as_str()).await {
+                Ok(rpc_client) => match ps.export(rpc_client, req).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        try_send_until_closed(&tx, Err(e)).await;
+                        return;
+                    }
+                },
+                Err(e) => {
+                    try_send_until_closed(&tx, Err(e)).await;
+                    return;
+                }
+            };
+
+            loop {
+                let resp = match conver(stream.message().await) {
+                    Ok(Some(resp)) => resp,
+                    Ok(None) => break,
+                    Err(e) => {
+                        try_send_until_closed(&tx, Err(e)).await;
+                        return;
+                    }
+                };
+
+                let resp = if Code::from_i32(resp.code) != Code::Success {
+                    Err(err!(resp.code, resp.message))
+                } else {
+                    Ok(resp)
+                };
+
+                let is_err = resp.is_err();
+                if !try_send_until_closed(&tx, resp).await || is_err {
+                    return;
+                }
+            }
+        }
+    }
+
+    pub async fn partition_ids(&self, collection_name: &str) -> ASResult<Vec<u32>> {
+        let c = self.cache_collection(collection_name).await?;
+        Ok(c.partitions.iter().map(|p| p.id).collect())
+    }
+
+    // subscribe sends the changes of partition from the raft index to tx by the leader of it,
+    // it stops at the first error or the receiver closed
+    pub async fn subscribe(
+        &self,
+        collection_name: &str,
+        partition_id: u32,
+        from_index: u64,
+        with_source: bool,
+        tx: Sender<ASResult<SubscribeResponse>>,
+    ) {
+        let c = match self.cache_collection(collection_name).await {
+            Ok(c) => c,
+            Err(e) => {
+                try_send_until_closed(&tx, Err(e)).await;
+                return;
+            }
+        };
+
+        let ps = match c.partitions.iter().find(|p| p.id == partition_id) {
+            Some(p) => PartitionClient {
+                addr: p.leader.to_string(),
+                collection_id: c.collection.id,
+                partition_id: p.id,
+                slot: 0,
+            },
+            None => {
+                let e = err!(
+                    Code::ParamError,
+                    "not found partition:{} in collection:{}",
+                    partition_id,
+                    collection_name
+                );
+                try_send_until_closed(&tx, Err(e)).await;
+                return;
+            }
+        };
+
+        let req = SubscribeRequest {
+            collection_id: ps.collection_id,
+            partition_id: ps.partition_id,
+            from_index: from_index,
+            with_source: with_source,
+        };
+
+        let mut stream = match self.channel_cache(ps.addr.as_str()).await {
+            Ok(rpc_client) => match ps.subscribe(rpc_client, req).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    try_send_until_closed(&tx, Err(e)).await;
+                    return;
+                }
+            },
+            Err(e) => {
+                try_send_until_closed(&tx, Err(e)).await;
+                return;
+            }
+        };
+
+        loop {
+            let resp = match conver(stream.message().await) {
+                Ok(Some(resp)) => resp,
+                Ok(None) => return,
+                Err(e) => {
+                    try_send_until_closed(&tx, Err(e)).await;
+                    return;
+                }
+            };
+
+            let resp = if Code::from_i32(resp.code) != Code::Success {
+                Err(err!(resp.code, resp.message))
+            } else {
+                Ok(resp)
+            };
+
+            let is_err = resp.is_err();
+            if !try_send_until_closed(&tx, resp).await || is_err {
+                return;
+            }
+        }
+    }
+
     pub async fn search(
         &self,
         collection_name: &str,
         query: QueryRequest,
     ) -> ASResult<SearchDocumentResponse> {
+        let asc = sort_orders(&query.sort);
         'outer: for i in 0..RETRY {
             let (tx, rx) = channel(10);
 
@@ -190,7 +623,7 @@ impl PsClient {
                     }
                 }
                 Err(e) => {
-                    if self.check_err_cache(i, collection_name, &e) {
+                    if self.check_err_cache(i, collection_name, &e).await {
                         continue;
                     } else {
                         return Err(e);
@@ -203,25 +636,29 @@ impl PsClient {
             let mut dist = rx.recv().await.unwrap();
 
             if Code::from_i32(dist.code) != Code::Success
-                && self.check_response_cache(
-                    i,
-                    collection_name,
-                    err!(dist.code, msg_for_resp(&dist.info)),
-                )
+                && self
+                    .check_response_cache(
+                        i,
+                        collection_name,
+                        err!(dist.code, msg_for_resp(&dist.info)),
+                    )
+                    .await
             {
                 continue 'outer;
             }
             while let Ok(src) = rx.recv().await {
                 if Code::from_i32(src.code) != Code::Success
-                    && self.check_response_cache(
-                        i,
-                        collection_name,
-                        err!(dist.code, msg_for_resp(&dist.info)),
-                    )
+                    && self
+                        .check_response_cache(
+                            i,
+                            collection_name,
+                            err!(dist.code, msg_for_resp(&dist.info)),
+                        )
+                        .await
                 {
                     continue 'outer;
                 }
-                dist = merge_search_document_response(dist, src);
+                dist = merge_search_document_response(dist, src, &asc, query.size as usize);
             }
 
             return Ok(dist);
@@ -255,7 +692,7 @@ impl PsClient {
                     }
                 }
                 Err(e) => {
-                    if self.check_err_cache(i, collection_name, &e) {
+                    if self.check_err_cache(i, collection_name, &e).await {
                         continue;
                     } else {
                         return Err(e);
@@ -277,21 +714,25 @@ impl PsClient {
             }
 
             if Code::from_i32(dist.code) != Code::Success
-                && self.check_response_cache(
-                    i,
-                    collection_name,
-                    err!(dist.code, msg_for_resp(&dist.info)),
-                )
+                && self
+                    .check_response_cache(
+                        i,
+                        collection_name,
+                        err!(dist.code, msg_for_resp(&dist.info)),
+                    )
+                    .await
             {
                 continue 'outer;
             }
             while let Ok(src) = rx.recv().await {
                 if Code::from_i32(src.code) != Code::Success
-                    && self.check_response_cache(
-                        i,
-                        collection_name,
-                        err!(dist.code, msg_for_resp(&dist.info)),
-                    )
+                    && self
+                        .check_response_cache(
+                            i,
+                            collection_name,
+                            err!(dist.code, msg_for_resp(&dist.info)),
+                        )
+                        .await
                 {
                     continue 'outer;
                 }
@@ -326,7 +767,7 @@ impl PsClient {
                     }
                 }
                 Err(e) => {
-                    if self.check_err_cache(i, collection_name, &e) {
+                    if self.check_err_cache(i, collection_name, &e).await {
                         continue;
                     } else {
                         return Err(e);
@@ -339,14 +780,18 @@ impl PsClient {
             let mut dist = rx.recv().await.unwrap();
 
             if Code::from_i32(dist.code) != Code::Success
-                && self.check_response_cache(i, collection_name, err!(dist.code, dist.message))
+                && self
+                    .check_response_cache(i, collection_name, err!(dist.code, dist.message))
+                    .await
             {
                 continue 'outer;
             }
 
             while let Ok(src) = rx.recv().await {
                 if Code::from_i32(src.code) != Code::Success
-                    && self.check_response_cache(i, collection_name, err!(dist.code, dist.message))
+                    && self
+                        .check_response_cache(i, collection_name, err!(dist.code, dist.message))
+                        .await
                 {
                     continue 'outer;
                 }
@@ -428,7 +873,9 @@ impl PsClient {
     //TODO CACHE ME
     pub async fn cache_collection(&self, name: &str) -> ASResult<Arc<CollectionInfo>> {
         if let Some(c) = self.collection_cache.read().unwrap().get(name) {
-            return Ok(c.clone());
+            if c.cache_time + CACHE_TTL_MS > current_millis() {
+                return Ok(c.clone());
+            }
         }
 
         let lock = self
@@ -441,7 +888,9 @@ impl PsClient {
         let _ = lock.lock().unwrap();
 
         if let Some(c) = self.collection_cache.read().unwrap().get(name) {
-            return Ok(c.clone());
+            if c.cache_time + CACHE_TTL_MS > current_millis() {
+                return Ok(c.clone());
+            }
         }
 
         let collection = self.meta_cli.get_collection(name).await?;
@@ -464,6 +913,7 @@ impl PsClient {
             collection: collection,
             partitions: partitions,
             fields: cache_field,
+            cache_time: current_millis(),
         });
 
         self.collection_cache
@@ -473,7 +923,7 @@ impl PsClient {
         Ok(c.clone())
     }
 
-    fn check_err_cache(&self, i: usize, cname: &str, e: &ASError) -> bool {
+    async fn check_err_cache(&self, i: usize, cname: &str, e: &ASError) -> bool {
         if i + 1 == RETRY {
             return false;
         }
@@ -483,15 +933,24 @@ impl PsClient {
                 self.collection_cache.write().unwrap().remove(cname);
                 true
             }
+            Code::SlotMoved => {
+                warn!(
+                    "partition has been split, to remove cache by collection:{}",
+                    cname
+                );
+                self.collection_cache.write().unwrap().remove(cname);
+                task::sleep(Duration::from_millis(300)).await;
+                true
+            }
             _ => false,
         }
     }
 
-    fn check_response_cache(&self, i: usize, cname: &str, e: ASError) -> bool {
+    async fn check_response_cache(&self, i: usize, cname: &str, e: ASError) -> bool {
         if e == ASError::Success {
             return false;
         }
-        self.check_err_cache(i, cname, &e)
+        self.check_err_cache(i, cname, &e).await
     }
 
     async fn channel_cache(&self, addr: &str) -> ASResult<RpcClient<Channel>> {
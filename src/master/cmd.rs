@@ -20,9 +20,44 @@ pub struct PTransfer {
     pub to_server: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PReplica {
+    pub collection_id: u32,
+    pub partition_id: u32,
+    pub node_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PSplit {
+    pub collection_id: u32,
+    pub partition_id: u32,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PCreate {
     pub collection_name: String,
     pub partition_num: u32,
     pub zones: Vec<u32>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PFieldAdd {
+    pub collection_name: String,
+    pub fields: Vec<crate::util::entity::Field>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PReindex {
+    pub collection_id: u32,
+    pub partition_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReindexProgress {
+    pub node_id: u32,
+    pub running: bool,
+    pub total: u64,
+    pub indexed: u64,
+    pub vectors: u64,
+    pub message: String,
+}
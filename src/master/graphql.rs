@@ -1,3 +1,4 @@
+use crate::master::cmd::PFieldAdd;
 use crate::master::service::MasterService;
 use crate::util::{config, entity::*};
 use async_graphql::*;
@@ -53,6 +54,7 @@ impl Mutation {
         partition_num: i32,
         partition_replica_num: i32,
         fields: Option<Fields>,
+        ttl: Option<i32>,
     ) -> FieldResult<JsonValue> {
         let mut fs = vec![];
         if fields.is_some() {
@@ -71,6 +73,8 @@ impl Mutation {
             modify_time: 0,
             vector_field_index: vec![],
             scalar_field_index: vec![],
+            version: 0,
+            ttl: ttl.unwrap_or(0).max(0) as u64,
         };
 
         let v = serde_json::to_string(&info)?;
@@ -100,6 +104,33 @@ impl Mutation {
         }
     }
 
+    async fn collection_field_add(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        fields: Fields,
+    ) -> FieldResult<JsonValue> {
+        info!("prepare to add fields for collection name {}", name);
+
+        match ctx
+            .data_unchecked::<Arc<MasterService>>()
+            .add_fields(PFieldAdd {
+                collection_name: name.clone(),
+                fields: fields.to_collect(),
+            })
+            .await
+        {
+            Ok(s) => Ok(Json(serde_json::to_value(s)?)),
+            Err(e) => {
+                error!("add fields failed, collection_name: {}, err: {}", name, e);
+                Err(FieldError(
+                    format!("add fields failed, collection_name: {}, err: {}", name, e),
+                    None,
+                ))
+            }
+        }
+    }
+
     async fn collection_delete(&self, ctx: &Context<'_>, name: String) -> FieldResult<JsonValue> {
         info!("prepare to delete collection name {}", name);
 
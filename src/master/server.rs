@@ -36,6 +36,12 @@ pub async fn start(tx: Sender<String>, conf: Arc<config::Config>) -> std::io::Re
         MasterService::new(conf.clone()).expect(format!("master service init err").as_str()),
     );
 
+    let failover_service = service.clone();
+    std::thread::spawn(move || {
+        info!("to start failover job for pservers");
+        failover_service.start_failover_job();
+    });
+
     let schema = Schema::build(Query, Mutation, EmptySubscription)
         .data(service.clone())
         .finish();
@@ -69,6 +75,7 @@ pub async fn start(tx: Sender<String>, conf: Arc<config::Config>) -> std::io::Re
                 web::get().to(get_collection_by_id),
             )
             .route("/collection/list", web::get().to(list_collections))
+            .route("/collection/field/add", web::post().to(add_fields))
             //collection partition handler
             .route(
                 "/partition/get/{collection_id}/{partition_id}",
@@ -86,6 +93,26 @@ pub async fn start(tx: Sender<String>, conf: Arc<config::Config>) -> std::io::Re
                 "/collection/partition/transfer",
                 web::post().to(transfer_partition),
             )
+            .route(
+                "/collection/partition/split",
+                web::post().to(split_partition),
+            )
+            .route(
+                "/collection/partition/reindex",
+                web::post().to(reindex_partition),
+            )
+            .route(
+                "/collection/partition/reindex/{collection_id}/{partition_id}",
+                web::get().to(reindex_progress),
+            )
+            .route(
+                "/collection/partition/replica/add",
+                web::post().to(add_replica),
+            )
+            .route(
+                "/collection/partition/replica/remove",
+                web::post().to(remove_replica),
+            )
     })
     .bind(format!("0.0.0.0:{}", http_port))?
     .run()
@@ -254,6 +281,20 @@ async fn list_collections(rs: web::Data<Arc<MasterService>>) -> HttpResponse {
     }
 }
 
+async fn add_fields(rs: web::Data<Arc<MasterService>>, info: web::Json<PFieldAdd>) -> HttpResponse {
+    info!(
+        "prepare to add fields {:?} for collection {}",
+        info.fields, info.collection_name
+    );
+    match rs.add_fields(info.into_inner()).await {
+        Ok(s) => success_response(s),
+        Err(e) => {
+            error!("add fields failed, err:{}", e.to_string());
+            err_response(e)
+        }
+    }
+}
+
 async fn update_pserver(
     rs: web::Data<Arc<MasterService>>,
     info: web::Json<PServer>,
@@ -430,6 +471,104 @@ async fn transfer_partition(
     }
 }
 
+async fn split_partition(
+    rs: web::Data<Arc<MasterService>>,
+    info: web::Json<PSplit>,
+) -> HttpResponse {
+    info!(
+        "prepare to split collection {} partition {}",
+        info.collection_id, info.partition_id
+    );
+    match rs.split_partition(info.into_inner()).await {
+        Ok(s) => success_response(s),
+        Err(e) => {
+            error!("split partition failed, err:{}", e.to_string());
+            err_response(e)
+        }
+    }
+}
+
+async fn reindex_partition(
+    rs: web::Data<Arc<MasterService>>,
+    info: web::Json<PReindex>,
+) -> HttpResponse {
+    info!(
+        "prepare to reindex collection {} partition {}",
+        info.collection_id, info.partition_id
+    );
+    match rs.reindex(info.into_inner(), false).await {
+        Ok(s) => success_response(s),
+        Err(e) => {
+            error!("reindex partition failed, err:{}", e.to_string());
+            err_response(e)
+        }
+    }
+}
+
+async fn reindex_progress(rs: web::Data<Arc<MasterService>>, req: HttpRequest) -> HttpResponse {
+    let collection_id: u32 = req
+        .match_info()
+        .get("collection_id")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let partition_id: u32 = req
+        .match_info()
+        .get("partition_id")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let preindex = PReindex {
+        collection_id,
+        partition_id,
+    };
+    match rs.reindex(preindex, true).await {
+        Ok(s) => success_response(s),
+        Err(e) => {
+            error!(
+                "get reindex progress failed, collection_id:{}, partition_id:{}, err:{}",
+                collection_id,
+                partition_id,
+                e.to_string()
+            );
+            err_response(e)
+        }
+    }
+}
+
+async fn add_replica(rs: web::Data<Arc<MasterService>>, info: web::Json<PReplica>) -> HttpResponse {
+    info!(
+        "prepare to add replica {} for collection {} partition {}",
+        info.node_id, info.collection_id, info.partition_id
+    );
+    match rs.add_replica(info.into_inner()).await {
+        Ok(s) => success_response(s),
+        Err(e) => {
+            error!("add replica failed, err:{}", e.to_string());
+            err_response(e)
+        }
+    }
+}
+
+async fn remove_replica(
+    rs: web::Data<Arc<MasterService>>,
+    info: web::Json<PReplica>,
+) -> HttpResponse {
+    info!(
+        "prepare to remove replica {} for collection {} partition {}",
+        info.node_id, info.collection_id, info.partition_id
+    );
+    match rs.remove_replica(info.into_inner()).await {
+        Ok(s) => success_response(s),
+        Err(e) => {
+            error!("remove replica failed, err:{}", e.to_string());
+            err_response(e)
+        }
+    }
+}
+
 fn err_response(e: ASError) -> HttpResponse {
     return HttpResponse::build(e.code().http_code())
         .content_type("application/json")
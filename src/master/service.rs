@@ -27,6 +27,7 @@ use std::cmp;
 use std::sync::Arc;
 
 pub struct MasterService {
+    conf: Arc<Config>,
     ps_cli: PsClient,
     pub meta_service: HARepository,
     partition_lock: RwLock<usize>,
@@ -36,6 +37,7 @@ pub struct MasterService {
 impl MasterService {
     pub fn new(conf: Arc<Config>) -> ASResult<MasterService> {
         Ok(MasterService {
+            conf: conf.clone(),
             ps_cli: PsClient::new(conf.clone()),
             meta_service: HARepository::new(conf)?,
             partition_lock: RwLock::new(0),
@@ -138,8 +140,10 @@ impl MasterService {
         }
 
         let server_list: Vec<PServer> = self
-            .meta_service
-            .list(entity_key::pserver_prefix().as_str())?;
+            .list_servers()?
+            .into_iter()
+            .filter(|s| s.status == PServerStatus::ACTIVE)
+            .collect();
 
         let need_num = cmp::max(partition_num, partition_replica_num);
         if need_num as usize > server_list.len() {
@@ -272,6 +276,7 @@ impl MasterService {
 
     pub fn update_server(&self, mut server: PServer) -> ASResult<PServer> {
         server.modify_time = current_millis();
+        server.status = PServerStatus::ACTIVE;
         self.meta_service.put(&server)?;
         return Ok(server);
     }
@@ -440,7 +445,11 @@ impl MasterService {
         }
 
         // load begin to try offload partition, try not to repeat the load
-        for ps in self.list_servers()? {
+        for ps in self
+            .list_servers()?
+            .into_iter()
+            .filter(|s| s.status == PServerStatus::ACTIVE)
+        {
             for wp in ps.write_partitions {
                 if (wp.collection_id, wp.id) == (collection_id, partition_id) {
                     return result!(
@@ -500,6 +509,640 @@ impl MasterService {
         Ok(())
     }
 
+    pub async fn add_replica(&self, preplica: PReplica) -> ASResult<Partition> {
+        let (cid, pid, node_id) = (
+            preplica.collection_id,
+            preplica.partition_id,
+            preplica.node_id,
+        );
+        let _lock = self.partition_lock.write().await;
+
+        let mut partition = self.get_partition(cid, pid)?;
+        if partition.replicas.iter().any(|r| r.node_id == node_id) {
+            return result!(
+                Code::AlreadyExists,
+                "node:{} already is replica of collection:{} partition:{}",
+                node_id,
+                cid,
+                pid
+            );
+        }
+
+        let addr = self.get_server_addr(node_id)?;
+        self.ps_cli.status(addr.as_str()).await?; //validate can be added
+
+        let leader_id = match self.get_server(partition.leader.as_str())?.id {
+            Some(id) => id,
+            None => return result_def!("leader:{} not has id", partition.leader),
+        };
+
+        //the leader must be first, the new member takes it as leader when created
+        let mut replicas = vec![ReplicaInfo {
+            node: leader_id,
+            replica_type: ReplicaType::NORMAL as u32,
+        }];
+        for r in partition.replicas.iter().filter(|r| r.node_id != leader_id) {
+            replicas.push(ReplicaInfo {
+                node: r.node_id,
+                replica_type: r.replica_type.clone() as u32,
+            });
+        }
+        replicas.push(ReplicaInfo {
+            node: node_id,
+            replica_type: ReplicaType::NORMAL as u32,
+        });
+
+        info!(
+            "to add replica node:{} for collection:{} partition:{}",
+            node_id, cid, pid
+        );
+
+        //build the member before raft change, so it can receive logs from leader
+        PartitionClient::new(addr.clone())
+            .load_or_create_partition(PartitionRequest {
+                collection_id: cid,
+                partition_id: pid,
+                readonly: false,
+                version: 0,
+                replicas: replicas,
+            })
+            .await?;
+
+        if let Err(e) = PartitionClient::new(partition.leader.clone())
+            .change_member(MemberChangeRequest {
+                collection_id: cid,
+                partition_id: pid,
+                node_id: node_id,
+                change_type: MemberChangeType::Add as i32,
+            })
+            .await
+        {
+            if let Err(e) = self.offload_replica(addr.as_str(), cid, pid).await {
+                error!("offload replica:{} has err:{}", addr, e);
+            }
+            return Err(e);
+        }
+
+        partition.replicas.push(Replica {
+            node_id: node_id,
+            replica_type: ReplicaType::NORMAL,
+        });
+        partition.version += 1;
+        self.meta_service.put(&partition)?;
+
+        Ok(partition)
+    }
+
+    pub async fn remove_replica(&self, preplica: PReplica) -> ASResult<Partition> {
+        let (cid, pid, node_id) = (
+            preplica.collection_id,
+            preplica.partition_id,
+            preplica.node_id,
+        );
+        let _lock = self.partition_lock.write().await;
+
+        let mut partition = self.get_partition(cid, pid)?;
+        if partition.replicas.iter().all(|r| r.node_id != node_id) {
+            return result!(
+                Code::ParamError,
+                "node:{} not replica of collection:{} partition:{}",
+                node_id,
+                cid,
+                pid
+            );
+        }
+
+        if partition.replicas.len() <= 1 {
+            return result!(
+                Code::ParamError,
+                "can not remove the last replica of collection:{} partition:{}",
+                cid,
+                pid
+            );
+        }
+
+        let addr = self.get_server_addr(node_id)?;
+        if addr == partition.leader {
+            return result!(
+                Code::ParamError,
+                "node:{} is leader of collection:{} partition:{}, transfer it first",
+                node_id,
+                cid,
+                pid
+            );
+        }
+
+        info!(
+            "to remove replica node:{} for collection:{} partition:{}",
+            node_id, cid, pid
+        );
+
+        PartitionClient::new(partition.leader.clone())
+            .change_member(MemberChangeRequest {
+                collection_id: cid,
+                partition_id: pid,
+                node_id: node_id,
+                change_type: MemberChangeType::Remove as i32,
+            })
+            .await?;
+
+        partition.replicas.retain(|r| r.node_id != node_id);
+        partition.version += 1;
+        self.meta_service.put(&partition)?;
+
+        //the removed node may be down, it will not load the partition when it restart
+        if let Err(e) = self.offload_replica(addr.as_str(), cid, pid).await {
+            warn!("offload replica:{} has err:{}", addr, e);
+        }
+
+        Ok(partition)
+    }
+
+    async fn offload_replica(&self, addr: &str, cid: u32, pid: u32) -> ASResult<()> {
+        PartitionClient::new(addr.to_string())
+            .offload_partition(PartitionRequest {
+                collection_id: cid,
+                partition_id: pid,
+                readonly: false,
+                version: 0,
+                replicas: vec![],
+            })
+            .await?;
+        Ok(())
+    }
+
+    //split the slot range of partition in two, the upper half moves to a new partition
+    pub async fn split_partition(&self, psplit: PSplit) -> ASResult<Collection> {
+        let (cid, pid) = (psplit.collection_id, psplit.partition_id);
+        let _lock = self.collection_lock.lock().await;
+
+        let mut collection = self.get_collection_by_id(cid)?;
+
+        let index = match collection.partitions.iter().position(|p| *p == pid) {
+            Some(i) => i,
+            None => {
+                return result!(
+                    Code::ParamError,
+                    "not found partition:{} in collection:{}",
+                    pid,
+                    cid
+                )
+            }
+        };
+
+        let begin = collection.slots[index] as u64;
+        let end = if index + 1 < collection.slots.len() {
+            collection.slots[index + 1] as u64
+        } else {
+            u32::max_value() as u64 + 1
+        };
+
+        if end - begin < 2 {
+            return result!(
+                Code::ParamError,
+                "collection:{} partition:{} slot range:[{}, {}) can not be split",
+                cid,
+                pid,
+                begin,
+                end
+            );
+        }
+
+        let split_slot = (begin + (end - begin) / 2) as u32;
+        let new_pid = collection.partitions.iter().max().unwrap() + 1;
+
+        let source = self.get_partition(cid, pid)?;
+
+        //the new partition on the same servers, so documents copy in local
+        let partition = Partition {
+            id: new_pid,
+            collection_id: cid,
+            leader: source.leader.clone(),
+            version: 0,
+            replicas: source.replicas.clone(),
+        };
+
+        info!(
+            "to split collection:{} partition:{} to:{} by slot:{}",
+            cid, pid, new_pid, split_slot
+        );
+
+        self.meta_service.put(&partition)?;
+
+        let ps = PartitionClient::new(source.leader.clone());
+
+        let result = ps
+            .load_or_create_partition(PartitionRequest {
+                partition_id: new_pid,
+                collection_id: cid,
+                readonly: false,
+                version: 0,
+                replicas: partition
+                    .replicas
+                    .iter()
+                    .map(|r| ReplicaInfo {
+                        node: r.node_id,
+                        replica_type: r.replica_type.clone() as u32,
+                    })
+                    .collect(),
+            })
+            .await;
+
+        let result = match result {
+            Ok(_) => {
+                ps.split_partition(SplitPartitionRequest {
+                    collection_id: cid,
+                    partition_id: pid,
+                    new_partition_id: new_pid,
+                    split_slot: split_slot,
+                    phase: SplitPhase::Copy as i32,
+                })
+                .await
+            }
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            error!(
+                "split collection:{} partition:{} has err:{}, to abort it",
+                cid, pid, e
+            );
+            if let Err(e) = ps
+                .split_partition(SplitPartitionRequest {
+                    collection_id: cid,
+                    partition_id: pid,
+                    new_partition_id: new_pid,
+                    split_slot: split_slot,
+                    phase: SplitPhase::Abort as i32,
+                })
+                .await
+            {
+                error!("abort split has err:{}", e);
+            }
+            if let Err(e) = self
+                .offload_replica(source.leader.as_str(), cid, new_pid)
+                .await
+            {
+                error!("offload new partition has err:{}", e);
+            }
+            self.meta_service.delete(&partition)?;
+            return Err(e);
+        }
+
+        //publish slots and partitions in one write, the routers will find it by cache expired or SlotMoved
+        collection.slots.insert(index + 1, split_slot);
+        collection.partitions.insert(index + 1, new_pid);
+        collection.partition_num = collection.partitions.len() as u32;
+        collection.modify_time = current_millis();
+        self.meta_service.put(&collection)?;
+
+        if let Err(e) = ps
+            .split_partition(SplitPartitionRequest {
+                collection_id: cid,
+                partition_id: pid,
+                new_partition_id: new_pid,
+                split_slot: split_slot,
+                phase: SplitPhase::Cleanup as i32,
+            })
+            .await
+        {
+            //the moved documents are fenced, so only waste disk
+            error!("cleanup collection:{} partition:{} has err:{}", cid, pid, e);
+        }
+
+        Ok(collection)
+    }
+
+    //append fields to collection, the old documents are indexed again by the new schema in pservers
+    pub async fn add_fields(&self, pfield: PFieldAdd) -> ASResult<Collection> {
+        let _lock = self.collection_lock.lock().await;
+
+        let mut collection = self.get_collection(pfield.collection_name.as_str())?;
+
+        if pfield.fields.len() == 0 {
+            return result!(Code::ParamError, "no field to add");
+        }
+
+        let mut fields = pfield.fields;
+        let mut names = std::collections::HashSet::new();
+        for f in fields.iter_mut() {
+            validate_and_set_field(f)?;
+            if f.is_vector() {
+                return result!(
+                    Code::ParamError,
+                    "vector field:{} can not be added to collection",
+                    f.name()
+                );
+            }
+            //the old documents not have the field
+            if !f.none() {
+                return result!(
+                    Code::ParamError,
+                    "field:{} must be set none as true when added",
+                    f.name()
+                );
+            }
+            if collection.fields.iter().any(|c| c.name() == f.name())
+                || !names.insert(f.name().to_string())
+            {
+                return result!(
+                    Code::AlreadyExists,
+                    "field:{} already exists in collection:{}",
+                    f.name(),
+                    collection.name
+                );
+            }
+        }
+
+        for f in fields {
+            collection.scalar_field_index.push(collection.fields.len());
+            collection.fields.push(f);
+        }
+        collection.version += 1;
+        collection.modify_time = current_millis();
+        self.meta_service.put(&collection)?;
+
+        info!(
+            "collection:{} add fields to version:{}",
+            collection.name, collection.version
+        );
+
+        //the replica missed it takes the new schema when partition loaded again
+        let mut failed = Vec::new();
+        for pid in collection.partitions.iter() {
+            let partition = self.get_partition(collection.id, *pid)?;
+            for r in partition.replicas.iter() {
+                let result = match self.get_server_addr(r.node_id) {
+                    Ok(addr) => {
+                        PartitionClient::new(addr)
+                            .update_schema(UpdateSchemaRequest {
+                                collection_id: collection.id,
+                                partition_id: *pid,
+                                version: collection.version,
+                            })
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!(
+                        "update schema of collection:{} partition:{} on node:{} has err:{}",
+                        collection.id, pid, r.node_id, e
+                    );
+                    failed.push(format!("{}:{}", pid, r.node_id));
+                }
+            }
+        }
+
+        if failed.len() > 0 {
+            return result_def!(
+                "collection:{} fields added, but update schema failed on partition:node {:?}",
+                collection.name,
+                failed
+            );
+        }
+
+        Ok(collection)
+    }
+
+    //rebuild the indexes of partition on every replica, or only get the progress of them
+    pub async fn reindex(
+        &self,
+        preindex: PReindex,
+        progress: bool,
+    ) -> ASResult<Vec<ReindexProgress>> {
+        let (cid, pid) = (preindex.collection_id, preindex.partition_id);
+        let partition = self.get_partition(cid, pid)?;
+
+        let mut result = Vec::with_capacity(partition.replicas.len());
+        for r in partition.replicas.iter() {
+            let resp = match self.get_server_addr(r.node_id) {
+                Ok(addr) => {
+                    PartitionClient::new(addr)
+                        .reindex(ReindexRequest {
+                            collection_id: cid,
+                            partition_id: pid,
+                            progress: progress,
+                        })
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+
+            let p = match resp {
+                Ok(resp) => ReindexProgress {
+                    node_id: r.node_id,
+                    running: resp.running,
+                    total: resp.total,
+                    indexed: resp.indexed,
+                    vectors: resp.vectors,
+                    message: resp.message,
+                },
+                Err(e) => {
+                    error!(
+                        "reindex collection:{} partition:{} on node:{} has err:{}",
+                        cid, pid, r.node_id, e
+                    );
+                    ReindexProgress {
+                        node_id: r.node_id,
+                        running: false,
+                        total: 0,
+                        indexed: 0,
+                        vectors: 0,
+                        message: e.to_string(),
+                    }
+                }
+            };
+            result.push(p);
+        }
+
+        Ok(result)
+    }
+
+    //the job checks liveness of pservers, it only returns if self not in config
+    pub fn start_failover_job(&self) {
+        let timeout = match self.conf.self_master() {
+            Some(m) => m.down_timeout_sec * 1000,
+            None => {
+                error!("self not set in config, failover job not start");
+                return;
+            }
+        };
+
+        //give pservers a chance to heartbeat after master start
+        sleep!(timeout);
+
+        loop {
+            if let Err(e) = async_std::task::block_on(self.check_pservers(timeout)) {
+                error!("check pservers has err:{}", e);
+            }
+            sleep!(cmp::max(timeout / 3, 1000));
+        }
+    }
+
+    async fn check_pservers(&self, timeout: u64) -> ASResult<()> {
+        let now = current_millis();
+
+        let mut servers = self.list_servers()?;
+
+        for server in servers.iter_mut() {
+            if server.status == PServerStatus::ACTIVE && server.modify_time + timeout < now {
+                warn!(
+                    "pserver:{} not heartbeat since:{} mark it down",
+                    server.addr, server.modify_time
+                );
+                server.status = PServerStatus::DOWN;
+                self.meta_service.put(&*server)?;
+            }
+        }
+
+        let (actives, downs): (Vec<PServer>, Vec<PServer>) = servers
+            .into_iter()
+            .partition(|s| s.status == PServerStatus::ACTIVE);
+
+        for mut down in downs
+            .into_iter()
+            .filter(|s| s.status == PServerStatus::DOWN)
+        {
+            let down_id = match down.id {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let mut recovered = true;
+            for collection in self.list_collections()? {
+                for partition in self.list_partitions_by_id(collection.id)? {
+                    if partition.replicas.iter().all(|r| r.node_id != down_id)
+                        && partition.leader != down.addr
+                    {
+                        continue;
+                    }
+                    recovered = false;
+                    if let Err(e) = self.recover_partition(partition, &down, &actives).await {
+                        error!(
+                            "recover collection:{} partition from:{} has err:{}",
+                            collection.id, down.addr, e
+                        );
+                    }
+                }
+            }
+
+            if recovered {
+                info!("all partitions of pserver:{} are recovered", down.addr);
+                down.status = PServerStatus::RECOVERED;
+                self.meta_service.put(&down)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    //move the leader of partition away from down server, and replace the replica on it
+    async fn recover_partition(
+        &self,
+        partition: Partition,
+        down: &PServer,
+        actives: &Vec<PServer>,
+    ) -> ASResult<()> {
+        let (cid, pid, down_id) = (partition.collection_id, partition.id, down.id.unwrap());
+
+        if partition.leader == down.addr {
+            //raft has elected a new leader, it reports by heartbeat
+            let new_leader = actives.iter().find(|s| {
+                s.write_partitions
+                    .iter()
+                    .any(|wp| (wp.collection_id, wp.id) == (cid, pid))
+            });
+
+            if let Some(server) = new_leader {
+                info!(
+                    "move leader of collection:{} partition:{} from:{} to:{}",
+                    cid, pid, down.addr, server.addr
+                );
+                let _lock = self.partition_lock.write().await;
+                let mut partition = self.get_partition(cid, pid)?;
+                partition.leader = server.addr.clone();
+                partition.version += 1;
+                self.meta_service.put(&partition)?;
+                return Ok(());
+            }
+
+            if partition.replicas.len() > 1 || !self.conf.global.shared_disk {
+                info!(
+                    "collection:{} partition:{} is waiting for raft to elect leader",
+                    cid, pid
+                );
+                return Ok(());
+            }
+
+            //only one replica on shared disk, load it by other server
+            let target = match select_server(&partition, down, actives) {
+                Some(s) => s,
+                None => {
+                    return result_def!(
+                        "not found server in zone:{} for collection:{} partition:{}",
+                        down.zone,
+                        cid,
+                        pid
+                    )
+                }
+            };
+
+            info!(
+                "load collection:{} partition:{} from:{} to:{}",
+                cid, pid, down.addr, target.addr
+            );
+
+            self.load_or_create_partition(target.addr.as_str(), cid, pid, partition.version)
+                .await?;
+
+            let _lock = self.partition_lock.write().await;
+            let mut partition = self.get_partition(cid, pid)?;
+            partition.leader = target.addr.clone();
+            partition.replicas = vec![Replica {
+                node_id: target.id.unwrap(),
+                replica_type: ReplicaType::NORMAL,
+            }];
+            partition.version += 1;
+            self.meta_service.put(&partition)?;
+            return Ok(());
+        }
+
+        //leader is alive, so replace the replica on down server
+        let target = match select_server(&partition, down, actives) {
+            Some(s) => s,
+            None => {
+                return result_def!(
+                    "not found server in zone:{} for collection:{} partition:{}",
+                    down.zone,
+                    cid,
+                    pid
+                )
+            }
+        };
+
+        info!(
+            "replace replica of collection:{} partition:{} from:{} to:{}",
+            cid, pid, down.addr, target.addr
+        );
+
+        self.remove_replica(PReplica {
+            collection_id: cid,
+            partition_id: pid,
+            node_id: down_id,
+        })
+        .await?;
+
+        self.add_replica(PReplica {
+            collection_id: cid,
+            partition_id: pid,
+            node_id: target.id.unwrap(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn update_partition(&self, partition: Partition) -> ASResult<()> {
         let _lock = self.partition_lock.write().await;
         match self.get_partition(partition.collection_id, partition.id) {
@@ -525,11 +1168,43 @@ impl MasterService {
     }
 }
 
+//select an active server in the same zone of down server, which not has the partition
+fn select_server<'a>(
+    partition: &Partition,
+    down: &PServer,
+    actives: &'a Vec<PServer>,
+) -> Option<&'a PServer> {
+    let candidates = actives
+        .iter()
+        .filter(|s| s.zone == down.zone && s.id.is_some())
+        .filter(|s| partition.replicas.iter().all(|r| Some(r.node_id) != s.id))
+        .collect::<Vec<&PServer>>();
+
+    if candidates.len() == 0 {
+        return None;
+    }
+
+    //the one has least leader partitions
+    candidates
+        .into_iter()
+        .min_by_key(|s| s.write_partitions.len())
+}
+
 fn validate_and_set_field(field: &mut Field) -> ASResult<()> {
     if field.name().trim() == "" {
         return result_def!("unset field name in field:{:?}", field);
     }
 
+    match field {
+        Field::text(f) => {
+            f.analyzer()?;
+        }
+        Field::vector(f) => {
+            f.description = f.index_description()?;
+        }
+        _ => {}
+    }
+
     Ok(())
 }
 
@@ -1,11 +1,11 @@
 use crate::client::meta_client::MetaClient;
 use crate::pserver::service::PartitionService;
 use crate::pserver::simba::simba::Simba;
-use crate::util::error::ASError;
+use crate::util::error::{ASError, ASResult, Code};
 use crate::util::{coding::*, config, entity::*};
 use async_std::task;
 use log::error;
-use raft4rs::{entity::Config, error::*, state_machine::*};
+use raft4rs::{entity::Config, error::*, raft::Raft, state_machine::*};
 use std::sync::Arc;
 
 pub struct NodeStateMachine {
@@ -50,11 +50,20 @@ impl StateMachine for NodeStateMachine {
 		&self,
 		_term: u64,
 		_index: u64,
-		_node_id: u64,
-		_action: u8,
+		node_id: u64,
+		action: u8,
 		_exists: bool,
 	) -> RaftResult<()> {
-		panic!()
+		if let Err(e) = task::block_on(self.ps.apply_member_change(
+			&self.collection,
+			&self.partition,
+			node_id,
+			action,
+		)) {
+			error!("apply member change has err:{}", e);
+			return Err(RaftError::ErrCode(e.code() as i32, e.message()));
+		};
+		Ok(())
 	}
 
 	fn apply_leader_change(&self, _term: u64, _index: u64, leader: u64) -> RaftResult<()> {
@@ -94,6 +103,18 @@ impl Resolver for NodeResolver {
 	}
 }
 
+//action of raft member change
+pub const MEMBER_ADD: u8 = 0;
+pub const MEMBER_REMOVE: u8 = 1;
+
+pub async fn member_change(raft: &Arc<Raft>, node_id: u64, action: u8) -> ASResult<()> {
+	match raft.member_change(node_id, action).await {
+		Ok(()) => Ok(()),
+		Err(RaftError::ErrCode(c, m)) => Err(ASError::Error(Code::from_i32(c), m)),
+		Err(e) => Err(ASError::from(e)),
+	}
+}
+
 pub fn make_raft_conf(node_id: u64, conf: &Arc<config::Config>) -> Config {
 	let r = &conf.ps.raft;
 	Config {
@@ -118,6 +139,9 @@ pub enum EventType {
 	Delete = 0,
 	Create = 1,
 	Update = 2,
+	Batch = 3,
+	Fence = 4,
+	Origin = 5,
 }
 
 pub enum Event {
@@ -127,6 +151,12 @@ pub enum Event {
 	Create(Vec<u8>, Vec<u8>),
 	//value + key + len(k) + iid + 2
 	Update(Vec<u8>, Vec<u8>, Vec<u8>),
+	//[event + len(event)]* + count + 3
+	Batch(Vec<Event>),
+	//write_fence + read_fence + 4
+	Fence(u64, u64),
+	//event + origin + 5, the event is not written by user
+	Origin(u8, Box<Event>),
 }
 
 impl Event {
@@ -162,7 +192,61 @@ impl Event {
 				v.push(EventType::Update as u8);
 				v
 			}
+			Event::Batch(events) => {
+				let count = events.len() as u32;
+				let mut buf = Vec::new();
+				for event in events {
+					let v = event.encode();
+					let len = v.len() as u32;
+					buf.extend_from_slice(&v);
+					buf.extend_from_slice(&u32_slice(len)[..]);
+				}
+				buf.extend_from_slice(&u32_slice(count)[..]);
+				buf.push(EventType::Batch as u8);
+				buf
+			}
+			Event::Fence(write_fence, read_fence) => {
+				let mut buf = Vec::with_capacity(17);
+				buf.extend_from_slice(&u64_slice(write_fence)[..]);
+				buf.extend_from_slice(&u64_slice(read_fence)[..]);
+				buf.push(EventType::Fence as u8);
+				buf
+			}
+			Event::Origin(origin, event) => {
+				let mut buf = event.encode();
+				buf.push(origin);
+				buf.push(EventType::Origin as u8);
+				buf
+			}
+		}
+	}
+
+	//the origin and the event in it, the one not wrapped is written by user as 0
+	pub fn decode_origin<'a>(data: &'a [u8]) -> (u8, &'a [u8]) {
+		let len = data.len() - 1;
+		if data[len] == EventType::Origin as u8 {
+			(data[len - 1], &data[..len - 1])
+		} else {
+			(0, data)
+		}
+	}
+
+	pub fn is_batch(data: &[u8]) -> bool {
+		data[data.len() - 1] == EventType::Batch as u8
+	}
+
+	//split a batch event to the encoded events in it, keep the order of encode
+	pub fn decode_batch<'a>(data: &'a [u8]) -> Vec<&'a [u8]> {
+		let mut end = data.len() - 5;
+		let count = slice_u32(&data[end..end + 4]) as usize;
+		let mut result = Vec::with_capacity(count);
+		for _ in 0..count {
+			let len = slice_u32(&data[end - 4..end]) as usize;
+			result.push(&data[end - 4 - len..end - 4]);
+			end = end - 4 - len;
 		}
+		result.reverse();
+		result
 	}
 
 	//iid  key value
@@ -171,6 +255,7 @@ impl Event {
 		//key+ old_id+ 0
 		//value+ key + len(key)+1
 		//value + key + len(k) + iid + 2
+		//write_fence + read_fence + 4, they are in key
 		let len = data.len() - 1;
 		match data[len] {
 			0 => (
@@ -197,6 +282,7 @@ impl Event {
 					&data[..len - key_len - 6],
 				)
 			}
+			4 => (EventType::Fence, 0, &data[..len], empty),
 			_ => panic!("decode has err type:{}", data[len]),
 		}
 	}
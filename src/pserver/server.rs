@@ -18,8 +18,13 @@ use crate::pserverpb::{
 };
 use crate::util::entity::*;
 use crate::util::{config, error::*};
+use async_std::{
+    stream::{Stream, StreamExt},
+    sync::{channel, Receiver, TrySendError},
+};
 use log::{error, info};
 use std::error::Error;
+use std::pin::Pin;
 use std::sync::{mpsc::Sender, Arc};
 use std::time;
 use tonic::{transport::Server, Request, Response, Status};
@@ -61,7 +66,21 @@ pub async fn start(tx: Sender<String>, conf: Arc<config::Config>) -> Result<(),
         time::SystemTime::now().duration_since(now)
     );
 
-    //start heartbeat  TODO..........
+    let heartbeat_ps = ps.clone();
+    let heartbeat_ms = conf.ps.heartbeat_sec.unwrap_or(3) * 1000;
+    std::thread::spawn(move || loop {
+        crate::sleep!(heartbeat_ms);
+        if let Err(e) = async_std::task::block_on(heartbeat_ps.take_heartbeat()) {
+            error!("take heartbeat to master has err:{:?}", e);
+        }
+    });
+
+    let reap_ps = ps.clone();
+    let reap_ms = conf.ps.reap_expired_sec.unwrap_or(60) * 1000;
+    std::thread::spawn(move || loop {
+        crate::sleep!(reap_ms);
+        async_std::task::block_on(reap_ps.reap_expired());
+    });
 
     let addr = format!("{}:{}", conf.global.ip, conf.ps.rpc_port)
         .parse()
@@ -101,6 +120,18 @@ impl Rpc for RPCService {
         Ok(Response::new(result))
     }
 
+    async fn batch_write(
+        &self,
+        request: Request<BatchWriteRequest>,
+    ) -> Result<Response<BatchWriteResponse>, Status> {
+        let result = match self.service.batch_write(request.into_inner()).await {
+            Ok(bwr) => bwr,
+            Err(e) => e.into(),
+        };
+
+        Ok(Response::new(result))
+    }
+
     async fn get(
         &self,
         request: Request<GetDocumentRequest>,
@@ -112,6 +143,61 @@ impl Rpc for RPCService {
         Ok(Response::new(result))
     }
 
+    async fn scan(&self, request: Request<ScanRequest>) -> Result<Response<ScanResponse>, Status> {
+        let result = match self.service.scan(request.into_inner()) {
+            Ok(sr) => sr,
+            Err(e) => e.into(),
+        };
+
+        Ok(Response::new(result))
+    }
+
+    type ExportStream = Receiver<Result<ExportResponse, Status>>;
+
+    async fn export(
+        &self,
+        request: Request<ExportRequest>,
+    ) -> Result<Response<Self::ExportStream>, Status> {
+        let (tx, rx) = channel(8);
+
+        let sender = tx.clone();
+        let result = self.service.export(request.into_inner(), move |resp| {
+            let mut msg = Ok(resp);
+            loop {
+                match sender.try_send(msg) {
+                    Ok(_) => return true,
+                    Err(TrySendError::Full(m)) => {
+                        msg = m;
+                        std::thread::sleep(time::Duration::from_millis(10));
+                    }
+                    Err(TrySendError::Disconnected(_)) => return false,
+                }
+            }
+        });
+
+        if let Err(e) = result {
+            let _ = tx.try_send(Ok(e.into()));
+        }
+
+        Ok(Response::new(rx))
+    }
+
+    type SubscribeStream =
+        Pin<Box<dyn Stream<Item = Result<SubscribeResponse, Status>> + Send + Sync>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let (tx, rx) = channel(8);
+
+        if let Err(e) = self.service.subscribe(request.into_inner(), tx.clone()) {
+            let _ = tx.try_send(e.into());
+        }
+
+        Ok(Response::new(Box::pin(rx.map(Ok))))
+    }
+
     async fn search(
         &self,
         request: Request<QueryRequest>,
@@ -211,11 +297,60 @@ impl Rpc for RPCService {
 
         Ok(Response::new(rep))
     }
+
+    async fn change_member(
+        &self,
+        request: Request<MemberChangeRequest>,
+    ) -> Result<Response<GeneralResponse>, Status> {
+        let result = match self.service.change_member(request.into_inner()).await {
+            Ok(gr) => gr,
+            Err(e) => e.into(),
+        };
+
+        Ok(Response::new(result))
+    }
+
+    async fn split_partition(
+        &self,
+        request: Request<SplitPartitionRequest>,
+    ) -> Result<Response<GeneralResponse>, Status> {
+        let result = match self.service.split_partition(request.into_inner()).await {
+            Ok(gr) => gr,
+            Err(e) => e.into(),
+        };
+
+        Ok(Response::new(result))
+    }
+
+    async fn reindex(
+        &self,
+        request: Request<ReindexRequest>,
+    ) -> Result<Response<ReindexResponse>, Status> {
+        let result = match self.service.reindex(request.into_inner()) {
+            Ok(r) => r,
+            Err(e) => e.into(),
+        };
+
+        Ok(Response::new(result))
+    }
+
+    async fn update_schema(
+        &self,
+        request: Request<UpdateSchemaRequest>,
+    ) -> Result<Response<GeneralResponse>, Status> {
+        let result = match self.service.update_schema(request.into_inner()).await {
+            Ok(gr) => gr,
+            Err(e) => e.into(),
+        };
+
+        Ok(Response::new(result))
+    }
 }
 
 fn make_general_success() -> GeneralResponse {
     GeneralResponse {
         code: Code::Success as i32,
         message: String::from("success"),
+        version: 0,
     }
 }
@@ -14,13 +14,17 @@
 use crate::client::meta_client::MetaClient;
 use crate::pserver::raft::*;
 use crate::pserver::simba::aggregation;
-use crate::pserver::simba::engine::tantivy::sort::FieldScore;
 use crate::pserver::simba::simba::Simba;
 use crate::pserverpb::*;
-use crate::util::{coding, config, entity::*, error::*};
+use crate::util::{coding, config, entity::*, error::*, try_send_until_closed};
 use crate::*;
-use async_std::{sync::channel, task};
-use log::{error, info};
+use async_std::{
+    stream::StreamExt,
+    sync::{channel, Sender},
+    task,
+};
+use log::{error, info, warn};
+use prost::Message;
 use raft4rs::{
     entity::{Decode, Entry},
     error::*,
@@ -33,6 +37,14 @@ use std::sync::{
     atomic::{AtomicU64, Ordering::SeqCst},
     Arc, Mutex, RwLock,
 };
+use std::time::Duration;
+
+//the number of documents in one export response
+const EXPORT_BATCH: usize = 100;
+//the subscriber reads raft log until it is less than it behind, it must be far less than the
+//buffer of subscribe, so the live changes are not full while reading the log again
+const SUBSCRIBE_CATCH_UP: u64 = 100;
+
 enum Store {
     Leader {
         partition: Arc<Partition>,
@@ -337,6 +349,237 @@ impl PartitionService {
         self.take_heartbeat().await
     }
 
+    //it only can be called by leader, the member changes when raft applies it
+    pub async fn change_member(&self, req: MemberChangeRequest) -> ASResult<GeneralResponse> {
+        let (_, raft) = if let Some(store) = self
+            .simba_map
+            .read()
+            .unwrap()
+            .get(&(req.collection_id, req.partition_id))
+        {
+            store.leader_simba()?
+        } else {
+            return Err(make_not_found_err(req.collection_id, req.partition_id)?);
+        };
+
+        let action = match MemberChangeType::from_i32(req.change_type) {
+            Some(MemberChangeType::Add) => MEMBER_ADD,
+            Some(MemberChangeType::Remove) => MEMBER_REMOVE,
+            None => {
+                return result!(
+                    Code::ParamError,
+                    "member change type:{} not support",
+                    req.change_type
+                )
+            }
+        };
+
+        info!(
+            "to change member collection:{} partition:{} node:{} action:{}",
+            req.collection_id, req.partition_id, req.node_id, action
+        );
+
+        member_change(&raft, req.node_id as u64, action).await?;
+
+        make_general_success()
+    }
+
+    pub async fn split_partition(&self, req: SplitPartitionRequest) -> ASResult<GeneralResponse> {
+        let (simba, raft) = self
+            .wait_leader(req.collection_id, req.partition_id)
+            .await?;
+
+        info!(
+            "to split collection:{} partition:{} to:{} by slot:{} phase:{}",
+            req.collection_id, req.partition_id, req.new_partition_id, req.split_slot, req.phase
+        );
+
+        match SplitPhase::from_i32(req.phase) {
+            Some(SplitPhase::Copy) => {
+                let (target, target_raft) = self
+                    .wait_leader(req.collection_id, req.new_partition_id)
+                    .await?;
+                simba
+                    .set_write_fence(req.split_slot as u64, raft.clone())
+                    .await?;
+                let mut from_iid = 0;
+                loop {
+                    let (docs, next) = simba.slot_docs(from_iid, req.split_slot, 1000)?;
+                    target.import(docs, target_raft.clone()).await?;
+                    match next {
+                        Some(iid) => from_iid = iid,
+                        None => break,
+                    }
+                }
+            }
+            Some(SplitPhase::Cleanup) => {
+                simba.set_fence(req.split_slot as u64, raft.clone()).await?;
+                simba.delete_slot_docs(req.split_slot, raft).await?;
+            }
+            Some(SplitPhase::Abort) => simba.reset_fence(raft).await?,
+            None => return result!(Code::ParamError, "split phase:{} not support", req.phase),
+        }
+
+        make_general_success()
+    }
+
+    //reload the collection from master and rebuild the index by the new schema in background
+    pub async fn update_schema(&self, req: UpdateSchemaRequest) -> ASResult<GeneralResponse> {
+        let store = if let Some(store) = self
+            .simba_map
+            .read()
+            .unwrap()
+            .get(&(req.collection_id, req.partition_id))
+        {
+            store.clone()
+        } else {
+            make_not_found_err(req.collection_id, req.partition_id)?
+        };
+
+        let collection = self
+            .meta_client
+            .get_collection_by_id(req.collection_id)
+            .await?;
+
+        if collection.version < req.version {
+            return result!(
+                Code::VersionErr,
+                "collection:{} version:{} in master less than request:{}",
+                req.collection_id,
+                collection.version,
+                req.version
+            );
+        }
+
+        store.simba()?.update_schema(Arc::new(collection))?;
+
+        make_general_success()
+    }
+
+    //rebuild the index of partition from rocksdb in background, or return the progress of it
+    pub fn reindex(&self, req: ReindexRequest) -> ASResult<ReindexResponse> {
+        let store = if let Some(store) = self
+            .simba_map
+            .read()
+            .unwrap()
+            .get(&(req.collection_id, req.partition_id))
+        {
+            store.clone()
+        } else {
+            make_not_found_err(req.collection_id, req.partition_id)?
+        };
+
+        let simba = store.simba()?;
+        if !req.progress {
+            info!(
+                "to reindex collection:{} partition:{}",
+                req.collection_id, req.partition_id
+            );
+            simba.rebuild_index(true)?;
+        }
+
+        Ok(simba.reindex_progress())
+    }
+
+    //the partition may be just created, so wait it to be leader
+    async fn wait_leader(&self, cid: u32, pid: u32) -> ASResult<(Arc<Simba>, Arc<Raft>)> {
+        let mut times = 0;
+        loop {
+            let result = match self.simba_map.read().unwrap().get(&(cid, pid)) {
+                Some(store) => store.leader_simba(),
+                None => make_not_found_err(cid, pid),
+            };
+            times += 1;
+            match result {
+                Ok(v) => return Ok(v),
+                Err(e) => {
+                    if times >= 100 {
+                        return Err(e);
+                    }
+                }
+            }
+            task::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    pub async fn apply_member_change(
+        self: &Arc<Self>,
+        collection: &Arc<Collection>,
+        partition: &Arc<Partition>,
+        node_id: u64,
+        action: u8,
+    ) -> ASResult<()> {
+        let (cid, pid) = (collection.id, partition.id);
+
+        let store = match self.simba_map.read().unwrap().get(&(cid, pid)) {
+            Some(store) => store.clone(),
+            None => {
+                warn!(
+                    "not found partition_id:{} collection_id:{} in server, skip member change",
+                    pid, cid
+                );
+                return Ok(());
+            }
+        };
+
+        let mut partition = Partition::clone(&*store.partition());
+
+        match action {
+            MEMBER_ADD => {
+                if partition
+                    .replicas
+                    .iter()
+                    .all(|r| r.node_id as u64 != node_id)
+                {
+                    partition.replicas.push(Replica {
+                        node_id: node_id as u32,
+                        replica_type: ReplicaType::NORMAL,
+                    });
+                }
+            }
+            MEMBER_REMOVE => partition.replicas.retain(|r| r.node_id as u64 != node_id),
+            _ => return result_def!("member change action:{} not support", action),
+        }
+
+        let partition = Arc::new(partition);
+        let store = match &*store {
+            Store::Leader { raft, simba, .. } => Store::Leader {
+                partition: partition,
+                raft: raft.clone(),
+                simba: simba.clone(),
+            },
+            Store::Member { raft, simba, .. } => Store::Member {
+                partition: partition,
+                raft: raft.clone(),
+                simba: simba.clone(),
+            },
+        };
+
+        self.simba_map
+            .write()
+            .unwrap()
+            .insert((cid, pid), Arc::new(store));
+
+        //self has been removed, so release the simba of it
+        if action == MEMBER_REMOVE && self.server_id.load(SeqCst) == node_id {
+            let ps = self.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = ps.offload_partition(PartitionRequest {
+                    collection_id: cid,
+                    partition_id: pid,
+                    ..Default::default()
+                }) {
+                    error!(
+                        "offload removed collection:{} partition:{} has err:{}",
+                        cid, pid, e
+                    );
+                }
+            });
+        }
+
+        Ok(())
+    }
+
     async fn init_simba_by_raft(&self, simba: &Arc<Simba>, raft: &Arc<Raft>) -> RaftResult<()> {
         let index = simba.get_raft_index() + 1;
         let mut iter = raft.store.iter(index).await?;
@@ -377,10 +620,53 @@ impl PartitionService {
                 write_partitions: wps,
                 zone: self.conf.ps.zone.clone(),
                 modify_time: 0,
+                status: PServerStatus::ACTIVE,
             })
             .await
     }
 
+    //the leader deletes the expired documents by raft, the members only clean the expire keys.
+    //every replica removes the versions of deleted documents after retention
+    pub async fn reap_expired(&self) {
+        let retention_ms = self.conf.ps.version_retention_sec.unwrap_or(86400) * 1000;
+        let stores = self
+            .simba_map
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, s)| (*k, s.clone()))
+            .collect::<Vec<((u32, u32), Arc<Store>)>>();
+
+        for ((cid, pid), store) in stores {
+            let (simba, raft) = match store.leader_simba() {
+                Ok((simba, raft)) => (simba, Some(raft)),
+                Err(_) => (store.simba().unwrap(), None),
+            };
+            match simba.reap_expired(raft).await {
+                Ok(0) => {}
+                Ok(n) => info!(
+                    "collection:{} partition:{} reaped {} expired documents",
+                    cid, pid, n
+                ),
+                Err(e) => error!(
+                    "collection:{} partition:{} reap expired has err:{:?}",
+                    cid, pid, e
+                ),
+            }
+            match simba.reap_versions(retention_ms).await {
+                Ok(0) => {}
+                Ok(n) => info!(
+                    "collection:{} partition:{} removed {} versions of deleted documents",
+                    cid, pid, n
+                ),
+                Err(e) => error!(
+                    "collection:{} partition:{} reap versions has err:{:?}",
+                    cid, pid, e
+                ),
+            }
+        }
+    }
+
     pub async fn write(&self, req: WriteDocumentRequest) -> ASResult<GeneralResponse> {
         let (simba, raft) = if let Some(store) = self
             .simba_map
@@ -394,17 +680,106 @@ impl PartitionService {
         };
 
         match simba.write(req, raft).await {
-            Ok(_) | Err(ASError::Success) => Ok(GeneralResponse {
+            Ok(version) => Ok(GeneralResponse {
+                code: Code::Success as i32,
+                message: String::from("success"),
+                version: version,
+            }),
+            Err(ASError::Success) => Ok(GeneralResponse {
                 code: Code::Success as i32,
                 message: String::from("success"),
+                version: 0,
             }),
             Err(ASError::Error(c, m)) => Ok(GeneralResponse {
                 code: c as i32,
                 message: m,
+                version: 0,
             }),
         }
     }
 
+    pub async fn batch_write(&self, req: BatchWriteRequest) -> ASResult<BatchWriteResponse> {
+        let (simba, raft) = if let Some(store) = self
+            .simba_map
+            .read()
+            .unwrap()
+            .get(&(req.collection_id, req.partition_id))
+        {
+            store.leader_simba()?.clone()
+        } else {
+            return Err(make_not_found_err(req.collection_id, req.partition_id)?);
+        };
+
+        if req.atomic {
+            let results = simba
+                .transaction(req.writes, raft)
+                .await?
+                .into_iter()
+                .map(|version| GeneralResponse {
+                    code: Code::Success as i32,
+                    message: String::from("success"),
+                    version: version,
+                })
+                .collect();
+            return Ok(BatchWriteResponse {
+                code: Code::Success as i32,
+                message: String::from("success"),
+                results: results,
+            });
+        }
+
+        let results = simba
+            .batch_write(req.writes, raft)
+            .await
+            .into_iter()
+            .map(|r| match r {
+                Ok(version) => GeneralResponse {
+                    code: Code::Success as i32,
+                    message: String::from("success"),
+                    version: version,
+                },
+                Err(ASError::Success) => GeneralResponse {
+                    code: Code::Success as i32,
+                    message: String::from("success"),
+                    version: 0,
+                },
+                Err(ASError::Error(c, m)) => GeneralResponse {
+                    code: c as i32,
+                    message: m,
+                    version: 0,
+                },
+            })
+            .collect();
+
+        Ok(BatchWriteResponse {
+            code: Code::Success as i32,
+            message: String::from("success"),
+            results: results,
+        })
+    }
+
+    pub fn scan(&self, req: ScanRequest) -> ASResult<ScanResponse> {
+        let store = if let Some(store) = self
+            .simba_map
+            .read()
+            .unwrap()
+            .get(&(req.collection_id, req.partition_id))
+        {
+            store.clone()
+        } else {
+            make_not_found_err(req.collection_id, req.partition_id)?
+        };
+
+        let (docs, token) = store.simba()?.scan(&req)?;
+
+        Ok(ScanResponse {
+            code: Code::Success as i32,
+            message: String::from("success"),
+            docs: docs,
+            token: token,
+        })
+    }
+
     pub fn get(&self, req: GetDocumentRequest) -> ASResult<DocumentResponse> {
         let store = if let Some(store) = self
             .simba_map
@@ -420,10 +795,111 @@ impl PartitionService {
         Ok(DocumentResponse {
             code: Code::Success as i32,
             message: String::from("success"),
-            doc: store.simba()?.get(req.id.as_str(), req.sort_key.as_str())?,
+            doc: store.simba()?.get(
+                req.id.as_str(),
+                req.sort_key.as_str(),
+                req.source_filter.as_ref(),
+            )?,
         })
     }
 
+    // export sends the documents of partition in batches by f, it is done in a thread
+    // and stops when f returns false
+    pub fn export(
+        &self,
+        req: ExportRequest,
+        mut f: impl FnMut(ExportResponse) -> bool + Send + 'static,
+    ) -> ASResult<()> {
+        let store = if let Some(store) = self
+            .simba_map
+            .read()
+            .unwrap()
+            .get(&(req.collection_id, req.partition_id))
+        {
+            store.clone()
+        } else {
+            make_not_found_err(req.collection_id, req.partition_id)?
+        };
+
+        let simba = store.simba()?;
+
+        let query = Arc::new(QueryRequest {
+            query: if req.query.is_empty() {
+                String::from("*")
+            } else {
+                req.query
+            },
+            def_fields: req.def_fields,
+            ..Default::default()
+        });
+
+        std::thread::spawn(move || {
+            let mut docs = Vec::with_capacity(EXPORT_BATCH);
+
+            let result = simba.export(query, |doc| {
+                let mut buf = Vec::new();
+                if let Err(error) = doc.encode(&mut buf) {
+                    return Err(error.into());
+                }
+                docs.push(buf);
+                if docs.len() < EXPORT_BATCH {
+                    return Ok(true);
+                }
+                Ok(f(ExportResponse {
+                    code: Code::Success as i32,
+                    message: String::from("success"),
+                    docs: std::mem::replace(&mut docs, Vec::with_capacity(EXPORT_BATCH)),
+                }))
+            });
+
+            f(match result {
+                Ok(_) => ExportResponse {
+                    code: Code::Success as i32,
+                    message: String::from("success"),
+                    docs: docs,
+                },
+                Err(e) => {
+                    error!("export partition:{} has err:{:?}", req.partition_id, e);
+                    e.into()
+                }
+            });
+        });
+
+        Ok(())
+    }
+
+    // subscribe sends the changes of partition from the raft index to tx in a task, the ones
+    // in raft log are sent first and then the ones applied later. it stops when the receiver
+    // closed or at the first error, the error tells the index to resume from
+    pub fn subscribe(&self, req: SubscribeRequest, tx: Sender<SubscribeResponse>) -> ASResult<()> {
+        let store = if let Some(store) = self
+            .simba_map
+            .read()
+            .unwrap()
+            .get(&(req.collection_id, req.partition_id))
+        {
+            store.clone()
+        } else {
+            make_not_found_err(req.collection_id, req.partition_id)?
+        };
+
+        let (simba, raft) = (store.simba()?, store.raft()?);
+
+        task::spawn(async move {
+            if let Err(e) = send_changes(&simba, &raft, &req, &tx).await {
+                warn!(
+                    "subscribe partition:{} stopped by:{:?}",
+                    req.partition_id, e
+                );
+                let mut resp: SubscribeResponse = e.into();
+                resp.partition_id = req.partition_id;
+                try_send_until_closed(&tx, resp).await;
+            }
+        });
+
+        Ok(())
+    }
+
     pub async fn count(&self, req: CountDocumentRequest) -> ASResult<CountDocumentResponse> {
         let mut cdr = CountDocumentResponse {
             code: Code::Success as i32,
@@ -537,24 +1013,16 @@ impl PartitionService {
             }
         }
 
+        let asc = sort_orders(&sdreq.sort);
+
         let mut dist = rx.recv().await?;
         for _ in 0..len - 1 {
-            dist = merge_search_document_response(dist, rx.recv().await.unwrap());
-        }
-
-        let asc = if sdreq.sort.len() == 0 {
-            vec![true]
-        } else {
-            sdreq.sort.iter().map(|o| o.order == "asc").collect()
-        };
-
-        dist.hits
-            .sort_by(|v1, v2| FieldScore::cmp_by_order(&v1.sort, &v2.sort, &asc));
-
-        if dist.hits.len() > sdreq.size as usize {
-            unsafe {
-                dist.hits.set_len(sdreq.size as usize);
-            }
+            dist = merge_search_document_response(
+                dist,
+                rx.recv().await.unwrap(),
+                &asc,
+                sdreq.size as usize,
+            );
         }
 
         Ok(dist)
@@ -564,6 +1032,7 @@ impl PartitionService {
         Ok(GeneralResponse {
             code: Code::Success as i32,
             message: String::from("ok"),
+            version: 0,
         })
     }
 }
@@ -597,6 +1066,127 @@ impl PartitionService {
     }
 }
 
+//it returns Ok when the receiver closed
+async fn send_changes(
+    simba: &Arc<Simba>,
+    raft: &Arc<Raft>,
+    req: &SubscribeRequest,
+    tx: &Sender<SubscribeResponse>,
+) -> ASResult<()> {
+    let applied = simba.get_raft_index();
+    let from = if req.from_index == 0 {
+        applied + 1
+    } else {
+        req.from_index
+    };
+    if from > applied + 1 {
+        return result!(
+            Code::ParamError,
+            "from_index:{} is after the applied index:{} of partition:{}",
+            from,
+            applied,
+            req.partition_id
+        );
+    }
+
+    //catch up by raft log before subscribing, the live channel is full if it falls far behind
+    let mut last = from - 1;
+    while simba.get_raft_index() > last + SUBSCRIBE_CATCH_UP {
+        last = match send_log(simba, raft, req, tx, last + 1).await? {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+    }
+
+    //read the log again after subscribing, so the changes between them are not missed
+    let mut live = simba.subscribe();
+    last = match send_log(simba, raft, req, tx, last + 1).await? {
+        Some(last) => last,
+        None => return Ok(()),
+    };
+
+    while let Some(changes) = live.next().await {
+        //it may have been sent from raft log
+        if changes[0].raft_index <= last {
+            continue;
+        }
+        last = changes[0].raft_index;
+        if !try_send_until_closed(tx, change_response(req, changes.to_vec())).await {
+            return Ok(());
+        }
+    }
+
+    result!(
+        Code::InternalErr,
+        "the subscriber of partition:{} falls behind too much or the partition stopped, resume from index:{}",
+        req.partition_id,
+        last + 1
+    )
+}
+
+//send the changes in raft log from index to the applied one, it returns the last index sent,
+//or None when the receiver closed
+async fn send_log(
+    simba: &Arc<Simba>,
+    raft: &Arc<Raft>,
+    req: &SubscribeRequest,
+    tx: &Sender<SubscribeResponse>,
+    from: u64,
+) -> ASResult<Option<u64>> {
+    let truncated = || {
+        result!(
+            Code::ParamError,
+            "the raft log of partition:{} from index:{} has been truncated",
+            req.partition_id,
+            from
+        )
+    };
+
+    let applied = simba.get_raft_index();
+    let mut last = from - 1;
+    let mut seen = false;
+    let mut iter = raft.store.iter(from).await?;
+    while let Some(body) = iter.next(&raft.store).await? {
+        let first = !seen;
+        seen = true;
+        let (index, commond) = match Entry::decode(&body)? {
+            Entry::Commit { index, commond, .. } => (index, commond),
+            _ => continue,
+        };
+        if first && index > from {
+            return truncated();
+        }
+        //the one not applied is sent by live
+        if index > simba.get_raft_index() {
+            break;
+        }
+        last = index;
+        let changes = simba.changes(index, &commond)?;
+        if changes.len() > 0 && !try_send_until_closed(tx, change_response(req, changes)).await {
+            return Ok(None);
+        }
+    }
+    if !seen && from <= applied {
+        return truncated();
+    }
+
+    Ok(Some(last))
+}
+
+fn change_response(req: &SubscribeRequest, mut changes: Vec<Change>) -> SubscribeResponse {
+    if !req.with_source {
+        for change in changes.iter_mut() {
+            change.source = Vec::new();
+        }
+    }
+    SubscribeResponse {
+        code: Code::Success as i32,
+        message: String::from("success"),
+        partition_id: req.partition_id,
+        changes: changes,
+    }
+}
+
 fn make_not_found_err<T>(cid: u32, pid: u32) -> ASResult<T> {
     result!(
         Code::RocksDBNotFound,
@@ -610,5 +1200,6 @@ fn make_general_success() -> ASResult<GeneralResponse> {
     Ok(GeneralResponse {
         code: Code::Success as i32,
         message: String::from("success"),
+        version: 0,
     })
 }
@@ -262,6 +262,7 @@ impl Hits {
             score: 1f32,
             doc: value,
             sort: vec![],
+            highlights: Default::default(),
         });
         Ok(true)
     }
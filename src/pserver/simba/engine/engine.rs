@@ -15,7 +15,7 @@ use crate::util::{config, entity::*, error::ASResult};
 use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, Ordering::SeqCst},
-    Arc,
+    Arc, RwLock,
 };
 
 pub trait Engine {
@@ -25,12 +25,34 @@ pub trait Engine {
 
 pub struct BaseEngine {
     pub conf: Arc<config::Config>,
-    pub collection: Arc<Collection>,
+    //it is replaced when the schema of collection changed
+    collection: RwLock<Arc<Collection>>,
     pub partition: Arc<Partition>,
     pub stoped: AtomicBool,
 }
 
 impl BaseEngine {
+    pub fn new(
+        conf: Arc<config::Config>,
+        collection: Arc<Collection>,
+        partition: Arc<Partition>,
+    ) -> BaseEngine {
+        BaseEngine {
+            conf,
+            collection: RwLock::new(collection),
+            partition,
+            stoped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn collection(&self) -> Arc<Collection> {
+        self.collection.read().unwrap().clone()
+    }
+
+    pub fn set_collection(&self, collection: Arc<Collection>) {
+        *self.collection.write().unwrap() = collection;
+    }
+
     pub fn base_path(&self) -> PathBuf {
         Path::new(&self.conf.ps.data)
             .join(Path::new(
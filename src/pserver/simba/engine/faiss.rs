@@ -1,5 +1,6 @@
 use crate::pserver::simba::engine::engine::{BaseEngine, Engine};
 use crate::pserver::simba::engine::rocksdb::RocksDB;
+use crate::pserver::simba::engine::tantivy::sort::{score_coding, tie_coding, FieldScore};
 use crate::pserverpb::*;
 use crate::util::time::current_millis;
 use crate::util::{
@@ -12,11 +13,12 @@ use crate::*;
 use faiss4rs::{Config, Index};
 use log::{debug, error, info, warn};
 use roaring::RoaringBitmap;
-use std::collections::HashMap;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::sync::{
-    atomic::{AtomicU32, Ordering::SeqCst},
+    atomic::{AtomicU32, AtomicU64, Ordering::SeqCst},
     Arc, RwLock,
 };
 #[derive(PartialEq, Copy, Clone)]
@@ -27,52 +29,226 @@ pub enum IndexStatus {
     Stoped,
 }
 
+//the search params of faiss when they are not set
+const DEFAULT_NPROBE: u32 = 1;
+const DEFAULT_EF_SEARCH: u32 = 16;
+
+//the search params applied to faiss index, 0 is unknown
+#[derive(PartialEq, Copy, Clone, Default, Debug)]
+pub struct SearchParams {
+    nprobe: u32,
+    ef_search: u32,
+}
+
 const VECTOR_DIR_NAME: &'static str = "vector";
 const INDEX_FILE_NAME: &'static str = "index.dat";
 const INDEX_FILE_NAME_TMP: &'static str = "index.dat.tmp";
+const META_FILE_NAME: &'static str = "index.meta";
+const META_FILE_NAME_TMP: &'static str = "index.meta.tmp";
+//snapshot the index when it has the number of new vectors
+const FLUSH_COUNT: u32 = 10000;
+const DEFAULT_SNAPSHOT_SEC: u64 = 600;
+
+//the meta of index snapshot, it is written with the index file. on startup the snapshot
+//is loaded when they match, and the vectors over max_iid are indexed from rocksdb
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexMeta {
+    max_iid: u32,
+    count: u32,
+    dimension: i32,
+    description: String,
+    time: u64,
+}
+
+impl IndexMeta {
+    fn load(index_dir: &Path, name: &str) -> ASResult<Option<IndexMeta>> {
+        let path = index_dir.join(format!("{}_{}", name, META_FILE_NAME));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+}
 
 pub struct IndexField {
     pub field: VectorField,
     pub index: RwLock<Index>,
     index_dir: PathBuf,
     flush_count: AtomicU32,
+    //the max iid in index, the vectors are added in order of iid
+    max_iid: AtomicU32,
+    snapshot_time: AtomicU64,
+    snapshot_ms: u64,
     status: RwLock<IndexStatus>,
+    //it is changed only when the index is locked to write
+    params: RwLock<SearchParams>,
+    ivf: bool,
+    hnsw: bool,
 }
 
 impl IndexField {
+    fn new(
+        field: VectorField,
+        index: Index,
+        index_dir: PathBuf,
+        status: IndexStatus,
+        max_iid: u32,
+        snapshot_ms: u64,
+    ) -> IndexField {
+        let (ivf, hnsw) = match &field.index_params {
+            Some(p) => (p.is_ivf(), p.is_hnsw()),
+            None => (
+                field.description.contains("IVF"),
+                field.description.contains("HNSW"),
+            ),
+        };
+        let count = index.count() as u32;
+        IndexField {
+            field: field,
+            index: RwLock::new(index),
+            index_dir: index_dir,
+            flush_count: AtomicU32::new(count),
+            max_iid: AtomicU32::new(max_iid),
+            snapshot_time: AtomicU64::new(current_millis()),
+            snapshot_ms: snapshot_ms,
+            status: RwLock::new(status),
+            params: RwLock::new(SearchParams::default()),
+            ivf: ivf,
+            hnsw: hnsw,
+        }
+    }
+
     fn add_with_ids(&self, ids: &Vec<i64>, data: &Vec<f32>) -> ASResult<()> {
-        conver(self.index.read().unwrap().add_with_ids(ids, data))
+        let index = self.index.read().unwrap();
+        conver(index.add_with_ids(ids, data))?;
+        if let Some(id) = ids.last() {
+            self.max_iid.store(*id as u32, SeqCst);
+        }
+        Ok(())
     }
 
-    fn search(&self, queries: &Vec<f32>, size: i32) -> ASResult<(Vec<i64>, Vec<f32>)> {
-        let num_query = queries.len() as i32 / self.field.dimension;
-        Ok(self.index.read().unwrap().search(size, num_query, queries))
+    //the params of query override the ones of field
+    fn search_params(&self, vq: &VectorQuery) -> ASResult<SearchParams> {
+        if vq.nprobe > 0 && !self.ivf {
+            return result!(
+                Code::ParamError,
+                "nprobe only for ivf index, field:{} is:{}",
+                self.field.name,
+                self.field.description
+            );
+        }
+        if vq.ef_search > 0 && !self.hnsw {
+            return result!(
+                Code::ParamError,
+                "ef_search only for hnsw index, field:{} is:{}",
+                self.field.name,
+                self.field.description
+            );
+        }
+
+        let (nprobe, ef_search) = match &self.field.index_params {
+            Some(p) => (p.nprobe as u32, p.ef_search as u32),
+            None => (0, 0),
+        };
+
+        let pick = |query: u32, field: u32, default: u32| {
+            if query > 0 {
+                query
+            } else if field > 0 {
+                field
+            } else {
+                default
+            }
+        };
+
+        Ok(SearchParams {
+            nprobe: if self.ivf {
+                pick(vq.nprobe, nprobe, DEFAULT_NPROBE)
+            } else {
+                0
+            },
+            ef_search: if self.hnsw {
+                pick(vq.ef_search, ef_search, DEFAULT_EF_SEARCH)
+            } else {
+                0
+            },
+        })
     }
 
-    //return bool about need train model
-    fn flush(&self) -> ASResult<()> {
-        if self.status.read().unwrap().deref() == &IndexStatus::Runging {
-            let count = self.flush_count.load(SeqCst);
-            if self.count() - count > 10000 {
-                self.flush_count.store(self.count(), SeqCst);
-                self._flush()?;
+    fn search(
+        &self,
+        queries: &Vec<f32>,
+        size: i32,
+        params: SearchParams,
+    ) -> ASResult<(Vec<i64>, Vec<f32>)> {
+        let num_query = queries.len() as i32 / self.field.dimension;
+        loop {
+            {
+                let index = self.index.read().unwrap();
+                //the untrained index is flat, it has no params
+                if self.status() != IndexStatus::Runging || *self.params.read().unwrap() == params {
+                    return Ok(index.search(size, num_query, queries));
+                }
             }
+            self.set_params(params)?;
+        }
+    }
+
+    //the params are kept in faiss index, so they are changed when no one searches it
+    fn set_params(&self, params: SearchParams) -> ASResult<()> {
+        let index = self.index.write().unwrap();
+        let mut current = self.params.write().unwrap();
+        if self.ivf && current.nprobe != params.nprobe {
+            conver(index.set_parameter("nprobe", params.nprobe as f64))?;
         }
+        if self.hnsw && current.ef_search != params.ef_search {
+            conver(index.set_parameter("efSearch", params.ef_search as f64))?;
+        }
+        *current = params;
+        Ok(())
+    }
 
+    //snapshot the index when it has many new vectors or some for a while
+    fn flush(&self) -> ASResult<()> {
+        if self.status() != IndexStatus::Runging {
+            return Ok(());
+        }
+        let added = self.count().saturating_sub(self.flush_count.load(SeqCst));
+        let elapsed = current_millis().saturating_sub(self.snapshot_time.load(SeqCst));
+        if added > FLUSH_COUNT || (added > 0 && elapsed > self.snapshot_ms) {
+            self._flush()?;
+        }
         Ok(())
     }
 
+    //the index and its meta are written to tmp files and renamed, if it breaks between
+    //the renames, the count of them not match and the snapshot is not used
     fn _flush(&self) -> ASResult<()> {
-        info!("field:{} begin flush vector index", self.field.name);
         let now = current_millis();
-        self.index.write().unwrap().write_index();
-        let tmp = self
-            .index_dir
-            .join(format!("{}_{}", self.field.name, INDEX_FILE_NAME_TMP));
-        let effe = self
-            .index_dir
-            .join(format!("{}_{}", self.field.name, INDEX_FILE_NAME));
-        std::fs::rename(tmp, effe)?;
+        let meta = {
+            let index = self.index.write().unwrap();
+            //the flat index before training is not kept, it is trained again on startup
+            if index.config.description != self.field.description {
+                return Ok(());
+            }
+            info!("field:{} begin flush vector index", self.field.name);
+            index.write_index();
+            IndexMeta {
+                max_iid: self.max_id(),
+                count: index.count() as u32,
+                dimension: self.field.dimension,
+                description: self.field.description.clone(),
+                time: now,
+            }
+        };
+
+        let file = |name: &str| self.index_dir.join(format!("{}_{}", self.field.name, name));
+        std::fs::write(file(META_FILE_NAME_TMP), serde_json::to_vec(&meta)?)?;
+        std::fs::rename(file(INDEX_FILE_NAME_TMP), file(INDEX_FILE_NAME))?;
+        std::fs::rename(file(META_FILE_NAME_TMP), file(META_FILE_NAME))?;
+
+        self.flush_count.store(meta.count, SeqCst);
+        self.snapshot_time.store(now, SeqCst);
         info!(
             "field:{} begin flush vector index ok use time:{}",
             self.field.name,
@@ -111,7 +287,7 @@ impl IndexField {
     }
 
     pub fn max_id(&self) -> u32 {
-        self.index.read().unwrap().max_id() as u32
+        self.max_iid.load(SeqCst)
     }
 
     pub fn count(&self) -> u32 {
@@ -120,6 +296,7 @@ impl IndexField {
 }
 
 pub struct Faiss {
+    db: Arc<RocksDB>,
     base: Arc<BaseEngine>,
     pub fields: HashMap<String, Arc<IndexField>>,
 }
@@ -135,7 +312,7 @@ impl crate::util::entity::MetricType {
     pub fn to_faiss(&self) -> faiss4rs::MetricType {
         match self {
             MetricType::L2 => faiss4rs::MetricType::L2,
-            MetricType::InnerProduct => faiss4rs::MetricType::InnerProduct,
+            MetricType::InnerProduct | MetricType::Cosine => faiss4rs::MetricType::InnerProduct,
         }
     }
 }
@@ -143,44 +320,35 @@ impl crate::util::entity::MetricType {
 impl Faiss {
     pub fn new(db: Arc<RocksDB>, base: Arc<BaseEngine>) -> ASResult<Faiss> {
         let mut faiss = Faiss {
+            db: db.clone(),
             base: base.clone(),
             fields: HashMap::new(),
         };
-        for i in faiss.base.collection.vector_field_index.iter() {
-            let f = base.collection.fields[*i as usize].vector()?;
+        let collection = base.collection();
+        for i in collection.vector_field_index.iter() {
+            let f = collection.fields[*i as usize].vector()?;
 
             let index_dir = base.base_path().join(Path::new(VECTOR_DIR_NAME));
             if !index_dir.exists() {
                 std::fs::create_dir_all(&index_dir)?;
             }
 
-            let mut conf = Config {
-                dimension: f.dimension,
-                description: f.description.clone(),
-                metric_type: f.metric_type.to_faiss(),
-                path: index_dir
-                    .clone()
-                    .join(format!("{}_{}", f.name, INDEX_FILE_NAME))
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            };
-
-            let (status, index) = if std::path::Path::new(conf.path.as_str()).is_file() {
-                (IndexStatus::Runging, Index::open_or_create(conf))
-            } else {
-                conf.description = String::from("Flat");
-                (IndexStatus::NotReady, Index::new(conf))
-            };
-
-            let count = index.count() as u32;
-            let index = Arc::new(IndexField {
-                field: f.clone(),
-                index: RwLock::new(index),
-                index_dir: index_dir,
-                flush_count: AtomicU32::new(count),
-                status: RwLock::new(status),
-            });
+            let (status, index, max_iid) = open_index(f, &index_dir);
+            let snapshot_ms = base
+                .conf
+                .ps
+                .vector_snapshot_sec
+                .unwrap_or(DEFAULT_SNAPSHOT_SEC)
+                * 1000;
+
+            let index = Arc::new(IndexField::new(
+                f.clone(),
+                index,
+                index_dir,
+                status,
+                max_iid,
+                snapshot_ms,
+            ));
 
             let suffix = field_coding(&f.name, u32::max_value());
 
@@ -241,42 +409,250 @@ impl Faiss {
     pub fn search(
         &self,
         sdreq: Arc<QueryRequest>,
-        _bitmap: Option<RoaringBitmap>,
+        bitmap: Option<RoaringBitmap>,
         total: u64,
     ) -> ASResult<SearchDocumentResponse> {
+        let mut sdr = SearchDocumentResponse {
+            code: Code::Success as i32,
+            total: total,
+            hits: Vec::new(),
+            info: None, //if this is none means it is success
+            batch: Vec::new(),
+        };
+
         if total == 0 {
-            return Ok(SearchDocumentResponse {
-                code: Code::Success as i32,
-                total: total,
-                hits: Vec::new(),
-                info: None, //if this is none means it is success
-            });
+            return Ok(sdr);
         }
 
         if let Some(vq) = sdreq.vector_query.as_ref() {
-            let (ids, scores) = self
-                .get_field(vq.field.as_str())?
-                .search(&vq.vector, sdreq.size as i32)?;
-
-            let mut sdr = SearchDocumentResponse {
-                code: Code::Success as i32,
-                total: total,
-                hits: Vec::with_capacity(ids.len()),
-                info: None, //if this is none means it is success
-            };
-
-            for (i, id) in ids.into_iter().enumerate() {
-                sdr.hits.push(Hit {
-                    collection_name: self.collection.name.clone(),
-                    score: scores[i],
-                    doc: u32_slice(id as u32).to_vec(),
-                });
+            let index = self.get_field(vq.field.as_str())?;
+            let mut batch = self.knn(&index, vq, sdreq.size as usize, bitmap.as_ref())?;
+            if batch.len() == 1 {
+                sdr.hits = batch.pop().unwrap();
+            } else {
+                sdr.batch = batch.into_iter().map(|hits| HitList { hits }).collect();
             }
             return Ok(sdr);
         }
         return result_def!("impossible");
     }
 
+    //the nearest size documents of every query vector. a document of array field has many
+    //vectors, it is deduplicated by iid and keeps the best one, so it searches more until
+    //there are enough documents
+    fn knn(
+        &self,
+        index: &IndexField,
+        vq: &VectorQuery,
+        size: usize,
+        bitmap: Option<&RoaringBitmap>,
+    ) -> ASResult<Vec<Vec<Hit>>> {
+        let dimension = index.field.dimension as usize;
+        if vq.vector.len() == 0 || vq.vector.len() % dimension != 0 {
+            return result!(
+                Code::ParamError,
+                "the len:{} of vector is not multiple of dimension:{}",
+                vq.vector.len(),
+                dimension
+            );
+        }
+
+        let num = vq.vector.len() / dimension;
+        if size == 0 {
+            return Ok(vec![Vec::new(); num]);
+        }
+
+        let mut queries = vq.vector.clone();
+        index.field.normalize(&mut queries)?;
+
+        let params = index.search_params(vq)?;
+        let metric = &index.field.metric_type;
+        let min = metric.min_similarity(vq)?;
+        let collection_name = self.collection().name.clone();
+        let partition_id = u32_slice(self.partition.id);
+        let count = index.count() as usize;
+        //the candidates to rerank
+        let depth = size * std::cmp::max(vq.rerank, 1) as usize;
+        let mut k = depth;
+
+        let mut batch = loop {
+            let (ids, scores) = index.search(&queries, k as i32, params)?;
+            let mut batch = Vec::with_capacity(num);
+            let mut enough = true;
+
+            for q in 0..num {
+                let mut hits: Vec<Hit> = Vec::with_capacity(depth);
+                let mut iids = HashSet::with_capacity(depth);
+                //the rest are weaker than the one under min
+                let mut weak = false;
+                for i in q * k..(q + 1) * k {
+                    //faiss fills -1 when the index has less than k vectors
+                    if ids[i] < 0 || hits.len() >= depth {
+                        break;
+                    }
+                    let score = metric.similarity(scores[i]);
+                    //the approximate score is not cut if rerank, the exact one is cut after it
+                    if depth == size && min.map_or(false, |m| score < m) {
+                        weak = true;
+                        break;
+                    }
+                    //the ones not matched by the filter query are skipped
+                    if bitmap.map_or(false, |b| !b.contains(ids[i] as u32)) {
+                        continue;
+                    }
+                    if !iids.insert(ids[i]) {
+                        continue;
+                    }
+                    let iid = u32_slice(ids[i] as u32);
+                    hits.push(Hit {
+                        collection_name: collection_name.clone(),
+                        score: score,
+                        doc: iid.to_vec(),
+                        sort: vec![score_coding(score), tie_coding(&partition_id, &iid)],
+                        highlights: HashMap::new(),
+                    });
+                }
+                if !weak && hits.len() < depth && ids[(q + 1) * k - 1] >= 0 {
+                    enough = false;
+                }
+                batch.push(hits);
+            }
+
+            if enough || !(index.field.array || bitmap.is_some()) || k >= count {
+                break batch;
+            }
+            k = std::cmp::min(k * 2, count);
+        };
+
+        if depth > size {
+            for (q, hits) in batch.iter_mut().enumerate() {
+                let query = &queries[q * dimension..(q + 1) * dimension];
+                self.rerank(index, query, hits)?;
+                if let Some(m) = min {
+                    hits.retain(|h| h.score >= m);
+                }
+                hits.truncate(size);
+            }
+        }
+
+        Ok(batch)
+    }
+
+    //reorder the hits by the exact distance to the vectors in rocksdb
+    fn rerank(&self, index: &IndexField, query: &[f32], hits: &mut Vec<Hit>) -> ASResult<()> {
+        let metric = &index.field.metric_type;
+        for hit in hits.iter_mut() {
+            let key = field_coding(&index.field.name, slice_u32(&hit.doc));
+            let value = match self.db.db.get(key)? {
+                Some(v) => v,
+                None => continue,
+            };
+            let vectors: &[f32] = slice_slice(&value);
+            let best = vectors
+                .chunks(query.len())
+                .map(|v| metric.similarity(metric.distance(query, v)))
+                .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            if let Some(score) = best {
+                hit.score = score;
+                hit.sort[0] = score_coding(score);
+            }
+        }
+        hits.sort_by(|a, b| FieldScore::cmp_by_order(&a.sort, &b.sort, &[false]));
+        Ok(())
+    }
+
+    //rebuild the vector indexes from rocksdb, progress is called by the number of vectors indexed
+    pub fn rebuild(&self, mut progress: impl FnMut(u64)) -> ASResult<()> {
+        for (_, index) in self.fields.iter() {
+            self.rebuild_field(index, &mut progress)?;
+        }
+        Ok(())
+    }
+
+    //the new index replaces the old when it has all vectors, the index job goes on
+    //from its max id, so the vectors written in rebuilding are not lost
+    fn rebuild_field(
+        &self,
+        index: &Arc<IndexField>,
+        progress: &mut impl FnMut(u64),
+    ) -> ASResult<()> {
+        let field_name = index.field.name.as_str();
+        info!("field:{} begin rebuild vector index", field_name);
+
+        let trained = index.status() == IndexStatus::Runging;
+        let faiss_index = Index::new(Config {
+            dimension: index.field.dimension,
+            description: if trained {
+                index.field.description.clone()
+            } else {
+                String::from("Flat")
+            },
+            metric_type: index.field.metric_type.to_faiss(),
+            path: index.index.read().unwrap().config.path.clone(),
+        });
+
+        let suffix = field_coding(field_name, u32::max_value());
+
+        if trained {
+            let max_len = (index.field.train_size * index.field.dimension) as usize;
+            let mut buf: Vec<f32> = Vec::with_capacity(max_len);
+            db_vectors(&self.db, field_name, &suffix, &mut buf, max_len)?;
+            if let Err(e) = faiss_index.train(&buf) {
+                return result_def!("field:{} train index has err:{:?}", field_name, e);
+            }
+        }
+
+        let dimension = index.field.dimension as usize;
+        let is_array = index.field.array;
+        let batch_size = 1000;
+        let mut buf: Vec<f32> = Vec::with_capacity(dimension * batch_size);
+        let mut ids: Vec<i64> = Vec::with_capacity(batch_size);
+        let mut max_id = 0;
+
+        loop {
+            if !self.runing() {
+                return result!(Code::EngineWillClose, "field:{} is stoped", field_name);
+            }
+
+            ids.clear();
+            buf.clear();
+
+            read_vector_buffer(
+                &self.db,
+                field_coding(field_name, max_id + 1),
+                suffix.as_slice(),
+                &mut ids,
+                &mut buf,
+                dimension,
+                is_array,
+            )?;
+
+            if ids.len() == 0 {
+                break;
+            }
+
+            conver(faiss_index.add_with_ids(&ids, &buf))?;
+            max_id = *ids.last().unwrap() as u32;
+            progress(ids.len() as u64);
+        }
+
+        let count = faiss_index.count() as u32;
+        {
+            let mut i = index.index.write().unwrap();
+            *index.params.write().unwrap() = SearchParams::default();
+            index.max_iid.store(max_id, SeqCst);
+            *i = faiss_index;
+        }
+        index._flush()?;
+
+        info!(
+            "field:{} rebuild vector index ok, count:{}",
+            field_name, count
+        );
+
+        Ok(())
+    }
+
     pub fn get_field(&self, name: &str) -> ASResult<Arc<IndexField>> {
         match self.fields.get(name) {
             Some(i) => Ok(i.clone()),
@@ -410,6 +786,8 @@ impl Faiss {
         let train_size = index.field.train_size as usize;
 
         let mut ids: Vec<i64> = Vec::with_capacity(train_size as usize);
+        //the trained index is made from the first vector, not goes on the flat one
+        let mut max_id = 0;
         while index.status() != IndexStatus::Stoping {
             let temp = &mut buf;
             let ids = &mut ids;
@@ -420,7 +798,7 @@ impl Faiss {
 
             let result = read_vector_buffer(
                 &db,
-                field_coding(&field_name, index.max_id() + 1),
+                field_coding(&field_name, max_id + 1),
                 suffix.as_slice(),
                 ids,
                 temp,
@@ -443,6 +821,7 @@ impl Faiss {
                 crate::sleep!(3000);
                 continue;
             };
+            max_id = *ids.last().unwrap() as u32;
             //if id is over goto index_job_by_index
             if ids.len() < train_size {
                 break;
@@ -452,9 +831,16 @@ impl Faiss {
         {
             let mut i = index.index.write().unwrap();
             *index.status.write().unwrap() = IndexStatus::Runging;
+            *index.params.write().unwrap() = SearchParams::default();
+            index.max_iid.store(max_id, SeqCst);
             *i = faiss_index;
         }
 
+        //keep the trained index, so it need not train again on startup
+        if let Err(e) = index._flush() {
+            error!("field:{} flush trained index has err:{:?}", field_name, e);
+        }
+
         std::thread::spawn(move || {
             db.arc_count.fetch_sub(1, SeqCst);
             Faiss::index_job_by_index(db, index);
@@ -554,6 +940,83 @@ impl Engine for Faiss {
     }
 }
 
+//open the snapshot of field if it matches the meta, or a flat index to train. it returns
+//the max iid in the index, the vectors over it are indexed from rocksdb
+fn open_index(field: &VectorField, index_dir: &Path) -> (IndexStatus, Index, u32) {
+    let path = index_dir.join(format!("{}_{}", field.name, INDEX_FILE_NAME));
+    let conf = |description: &str| Config {
+        dimension: field.dimension,
+        description: description.to_string(),
+        metric_type: field.metric_type.to_faiss(),
+        path: path.to_str().unwrap().to_string(),
+    };
+    let flat = || (IndexStatus::NotReady, Index::new(conf("Flat")), 0);
+
+    if !path.is_file() {
+        return flat();
+    }
+
+    match IndexMeta::load(index_dir, &field.name) {
+        Ok(Some(meta))
+            if meta.description == field.description && meta.dimension == field.dimension =>
+        {
+            let index = Index::open_or_create(conf(&field.description));
+            if index.count() as u32 != meta.count {
+                warn!(
+                    "field:{} index count:{} not match snapshot:{}, so train it again",
+                    field.name,
+                    index.count(),
+                    meta.count
+                );
+                return flat();
+            }
+            info!(
+                "field:{} load vector index snapshot count:{} max_iid:{}",
+                field.name, meta.count, meta.max_iid
+            );
+            (IndexStatus::Runging, index, meta.max_iid)
+        }
+        Ok(Some(meta)) => {
+            warn!(
+                "field:{} snapshot:{:?} not match the field, so train it again",
+                field.name, meta
+            );
+            flat()
+        }
+        //the snapshot is written before meta
+        Ok(None) => {
+            let index = Index::open_or_create(conf(&field.description));
+            let max_iid = index.max_id() as u32;
+            (IndexStatus::Runging, index, max_iid)
+        }
+        Err(e) => {
+            warn!(
+                "field:{} read snapshot meta has err:{}, so train it again",
+                field.name,
+                e.to_string()
+            );
+            flat()
+        }
+    }
+}
+
+//read the vectors of field for train until max_len
+fn db_vectors(
+    db: &Arc<RocksDB>,
+    field_name: &str,
+    suffix: &[u8],
+    temp: &mut Vec<f32>,
+    max_len: usize,
+) -> ASResult<()> {
+    db.prefix_range(field_coding(field_name, 1), |k, v| -> ASResult<bool> {
+        if k >= suffix {
+            return Ok(false);
+        }
+        temp.extend_from_slice(slice_slice(v));
+        Ok(temp.len() < max_len)
+    })
+}
+
 // cache size is ids.capacity()
 fn read_vector_buffer(
     db: &Arc<RocksDB>,
@@ -0,0 +1,348 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use crate::util::{
+    coding::{slice_u32, u32_slice},
+    entity::MetricType,
+    error::*,
+};
+use crate::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+const DEFAULT_M: usize = 16;
+const EF_CONSTRUCTION: usize = 100;
+const NONE_ENTRY: u32 = u32::max_value();
+
+//the distance to a node, the less is the nearer
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Near(f32, u32);
+
+impl Eq for Near {}
+
+impl Ord for Near {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .unwrap_or(Ordering::Equal)
+            .then(self.1.cmp(&other.1))
+    }
+}
+
+impl PartialOrd for Near {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+//the vectors of nodes, the node is the index of vector in it
+pub struct Space<'a> {
+    vectors: &'a [f32],
+    dimension: usize,
+    metric: &'a MetricType,
+}
+
+impl<'a> Space<'a> {
+    pub fn new(vectors: &'a [f32], dimension: usize, metric: &'a MetricType) -> Space<'a> {
+        Space {
+            vectors,
+            dimension,
+            metric,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len() / self.dimension
+    }
+
+    pub fn point(&self, node: u32) -> &'a [f32] {
+        let start = node as usize * self.dimension;
+        &self.vectors[start..start + self.dimension]
+    }
+
+    //the less is the nearer, so the inner product is negated
+    pub fn distance(&self, query: &[f32], node: u32) -> f32 {
+        let d = self.metric.distance(query, self.point(node));
+        match self.metric {
+            MetricType::L2 => d,
+            MetricType::InnerProduct | MetricType::Cosine => -d,
+        }
+    }
+
+    //the score of hit by distance, it is the same as faiss
+    pub fn score(&self, distance: f32) -> f32 {
+        match self.metric {
+            MetricType::L2 => distance,
+            MetricType::InnerProduct | MetricType::Cosine => -distance,
+        }
+    }
+}
+
+// the hierarchical navigable small world graph, the nodes are inserted in order of
+// space, and the links of node are kept by layer
+pub struct Hnsw {
+    m: usize,
+    links: Vec<Vec<Vec<u32>>>,
+    entry: Option<u32>,
+    max_level: usize,
+}
+
+impl Hnsw {
+    pub fn new(m: usize) -> Hnsw {
+        Hnsw {
+            m: if m == 0 { DEFAULT_M } else { m },
+            links: Vec::new(),
+            entry: None,
+            max_level: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    fn random_level(&self) -> usize {
+        let ml = 1.0 / (self.m as f64).ln();
+        let r: f64 = rand::random();
+        (-(1.0 - r).ln() * ml) as usize
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    //the node must be the next one in space
+    pub fn insert(&mut self, space: &Space, node: u32) {
+        let level = self.random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        let entry = match self.entry {
+            Some(e) => e,
+            None => {
+                self.entry = Some(node);
+                self.max_level = level;
+                return;
+            }
+        };
+
+        let query = space.point(node);
+        let mut ep = Near(space.distance(query, entry), entry);
+        for layer in (level + 1..=self.max_level).rev() {
+            ep = self.greedy(space, query, ep, layer);
+        }
+
+        for layer in (0..=std::cmp::min(level, self.max_level)).rev() {
+            let nears = self.search_layer(space, query, ep, EF_CONSTRUCTION, layer);
+            ep = nears[0];
+            let neighbors: Vec<u32> = nears.iter().take(self.m).map(|n| n.1).collect();
+            for n in neighbors.iter() {
+                self.links[*n as usize][layer].push(node);
+                if self.links[*n as usize][layer].len() > self.max_links(layer) {
+                    self.shrink(space, *n, layer);
+                }
+            }
+            self.links[node as usize][layer] = neighbors;
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry = Some(node);
+        }
+    }
+
+    //keep the nearest links of node
+    fn shrink(&mut self, space: &Space, node: u32, layer: usize) {
+        let point = space.point(node);
+        let mut nears: Vec<Near> = self.links[node as usize][layer]
+            .iter()
+            .map(|n| Near(space.distance(point, *n), *n))
+            .collect();
+        nears.sort();
+        nears.truncate(self.max_links(layer));
+        self.links[node as usize][layer] = nears.into_iter().map(|n| n.1).collect();
+    }
+
+    fn greedy(&self, space: &Space, query: &[f32], mut ep: Near, layer: usize) -> Near {
+        loop {
+            let mut changed = false;
+            for n in self.links[ep.1 as usize][layer].iter() {
+                let d = space.distance(query, *n);
+                if d < ep.0 {
+                    ep = Near(d, *n);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return ep;
+            }
+        }
+    }
+
+    //the ef nearest nodes in layer, ordered by distance
+    fn search_layer(
+        &self,
+        space: &Space,
+        query: &[f32],
+        ep: Near,
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Near> {
+        let mut visited = HashSet::new();
+        visited.insert(ep.1);
+        let mut candidates = BinaryHeap::new();
+        candidates.push(Reverse(ep));
+        let mut result = BinaryHeap::new();
+        result.push(ep);
+
+        while let Some(Reverse(c)) = candidates.pop() {
+            if result.len() >= ef && c.0 > result.peek().unwrap().0 {
+                break;
+            }
+            for n in self.links[c.1 as usize][layer].iter() {
+                if !visited.insert(*n) {
+                    continue;
+                }
+                let d = space.distance(query, *n);
+                if result.len() < ef || d < result.peek().unwrap().0 {
+                    candidates.push(Reverse(Near(d, *n)));
+                    result.push(Near(d, *n));
+                    if result.len() > ef {
+                        result.pop();
+                    }
+                }
+            }
+        }
+
+        result.into_sorted_vec()
+    }
+
+    //the k nearest (node, distance) of query, ordered by distance
+    pub fn search(&self, space: &Space, query: &[f32], k: usize, ef: usize) -> Vec<(u32, f32)> {
+        let entry = match self.entry {
+            Some(e) => e,
+            None => return Vec::new(),
+        };
+
+        let mut ep = Near(space.distance(query, entry), entry);
+        for layer in (1..=self.max_level).rev() {
+            ep = self.greedy(space, query, ep, layer);
+        }
+
+        let mut nears = self.search_layer(space, query, ep, std::cmp::max(ef, k), 0);
+        nears.truncate(k);
+        nears.into_iter().map(|n| (n.1, n.0)).collect()
+    }
+
+    //m + entry + max_level + nodes + [layers + [len + links]]
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&u32_slice(self.m as u32));
+        buf.extend_from_slice(&u32_slice(self.entry.unwrap_or(NONE_ENTRY)));
+        buf.extend_from_slice(&u32_slice(self.max_level as u32));
+        buf.extend_from_slice(&u32_slice(self.links.len() as u32));
+        for layers in self.links.iter() {
+            buf.extend_from_slice(&u32_slice(layers.len() as u32));
+            for links in layers.iter() {
+                buf.extend_from_slice(&u32_slice(links.len() as u32));
+                for n in links.iter() {
+                    buf.extend_from_slice(&u32_slice(*n));
+                }
+            }
+        }
+    }
+
+    pub fn decode(data: &[u8], offset: &mut usize) -> ASResult<Hnsw> {
+        let m = read_u32(data, offset)? as usize;
+        let entry = read_u32(data, offset)?;
+        let max_level = read_u32(data, offset)? as usize;
+        let nodes = read_u32(data, offset)? as usize;
+
+        let mut links = Vec::with_capacity(nodes);
+        for _ in 0..nodes {
+            let layer_num = read_u32(data, offset)? as usize;
+            let mut layers = Vec::with_capacity(layer_num);
+            for _ in 0..layer_num {
+                let len = read_u32(data, offset)? as usize;
+                let mut ns = Vec::with_capacity(len);
+                for _ in 0..len {
+                    ns.push(read_u32(data, offset)?);
+                }
+                layers.push(ns);
+            }
+            links.push(layers);
+        }
+
+        Ok(Hnsw {
+            m,
+            links,
+            entry: if entry == NONE_ENTRY {
+                None
+            } else {
+                Some(entry)
+            },
+            max_level,
+        })
+    }
+}
+
+pub fn read_u32(data: &[u8], offset: &mut usize) -> ASResult<u32> {
+    if *offset + 4 > data.len() {
+        return result!(
+            Code::EncodingErr,
+            "read u32 at:{} out of len:{}",
+            offset,
+            data.len()
+        );
+    }
+    let v = slice_u32(&data[*offset..*offset + 4]);
+    *offset += 4;
+    Ok(v)
+}
+
+#[test]
+fn hnsw_recall() {
+    let dimension = 8;
+    let vectors: Vec<f32> = (0..1000 * dimension).map(|_| rand::random()).collect();
+    let metric = MetricType::L2;
+    let space = Space::new(&vectors, dimension, &metric);
+
+    let mut hnsw = Hnsw::new(0);
+    for node in 0..space.len() {
+        hnsw.insert(&space, node as u32);
+    }
+
+    let mut buf = Vec::new();
+    hnsw.encode(&mut buf);
+    let hnsw = Hnsw::decode(&buf, &mut 0).unwrap();
+    assert_eq!(1000, hnsw.len());
+
+    let mut found = 0;
+    for q in 0..20 {
+        let query: Vec<f32> = (0..dimension).map(|_| rand::random()).collect();
+        let mut exact: Vec<Near> = (0..space.len() as u32)
+            .map(|n| Near(space.distance(&query, n), n))
+            .collect();
+        exact.sort();
+        let result = hnsw.search(&space, &query, 10, 64);
+        assert_eq!(10, result.len(), "query:{}", q);
+        for e in exact.iter().take(10) {
+            if result.iter().any(|(n, _)| *n == e.1) {
+                found += 1;
+            }
+        }
+    }
+    assert!(found >= 180, "recall:{}/200", found);
+}
@@ -0,0 +1,544 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use crate::pserver::simba::engine::engine::{BaseEngine, Engine};
+use crate::pserver::simba::engine::hnsw::{read_u32, Hnsw, Space};
+use crate::pserver::simba::engine::rocksdb::RocksDB;
+use crate::pserver::simba::engine::tantivy::sort::{score_coding, tie_coding};
+use crate::pserverpb::*;
+use crate::util::{
+    coding::{field_coding, slice_slice, slice_u32, u32_slice},
+    entity::*,
+    error::*,
+    time::current_millis,
+};
+use crate::*;
+use log::{error, info, warn};
+use roaring::RoaringBitmap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicU32, AtomicU64, Ordering::SeqCst},
+    Arc, RwLock,
+};
+
+const VECTOR_DIR_NAME: &'static str = "vector";
+const INDEX_FILE_NAME: &'static str = "hnsw.dat";
+const INDEX_FILE_NAME_TMP: &'static str = "hnsw.dat.tmp";
+const DEFAULT_EF_SEARCH: usize = 16;
+const BATCH_SIZE: usize = 1000;
+const FLUSH_COUNT: u32 = 10000;
+const DEFAULT_SNAPSHOT_SEC: u64 = 600;
+
+//the vectors of field in order of iid, the graph is made when they are more than train_size
+struct Vectors {
+    ids: Vec<u32>,
+    data: Vec<f32>,
+    graph: Option<Hnsw>,
+}
+
+impl Vectors {
+    fn new() -> Vectors {
+        Vectors {
+            ids: Vec::new(),
+            data: Vec::new(),
+            graph: None,
+        }
+    }
+
+    fn max_id(&self) -> u32 {
+        self.ids.last().cloned().unwrap_or(0)
+    }
+
+    //count + ids + vectors + has graph + graph
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(4 + self.ids.len() * 4 + self.data.len() * 4 + 1);
+        buf.extend_from_slice(&u32_slice(self.ids.len() as u32));
+        for id in self.ids.iter() {
+            buf.extend_from_slice(&u32_slice(*id));
+        }
+        for v in self.data.iter() {
+            buf.extend_from_slice(&u32_slice(v.to_bits()));
+        }
+        match &self.graph {
+            Some(g) => {
+                buf.push(1);
+                g.encode(&mut buf);
+            }
+            None => buf.push(0),
+        }
+        buf
+    }
+
+    fn decode(data: &[u8], dimension: usize) -> ASResult<Vectors> {
+        let mut offset = 0;
+        let count = read_u32(data, &mut offset)? as usize;
+
+        let mut ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            ids.push(read_u32(data, &mut offset)?);
+        }
+
+        let mut vectors = Vec::with_capacity(count * dimension);
+        for _ in 0..count * dimension {
+            vectors.push(f32::from_bits(read_u32(data, &mut offset)?));
+        }
+
+        let graph = match data.get(offset) {
+            Some(1) => {
+                offset += 1;
+                Some(Hnsw::decode(data, &mut offset)?)
+            }
+            Some(0) => None,
+            _ => return result!(Code::EncodingErr, "vector index has no graph flag"),
+        };
+
+        Ok(Vectors {
+            ids,
+            data: vectors,
+            graph,
+        })
+    }
+}
+
+pub struct IndexField {
+    pub field: VectorField,
+    vectors: RwLock<Vectors>,
+    index_dir: PathBuf,
+    flush_count: AtomicU32,
+    snapshot_time: AtomicU64,
+    snapshot_ms: u64,
+}
+
+impl IndexField {
+    fn open(field: VectorField, index_dir: PathBuf, snapshot_ms: u64) -> ASResult<IndexField> {
+        let path = index_dir.join(format!("{}_{}", field.name, INDEX_FILE_NAME));
+        let vectors = if path.is_file() {
+            Vectors::decode(&std::fs::read(&path)?, field.dimension as usize)?
+        } else {
+            Vectors::new()
+        };
+        let count = vectors.ids.len() as u32;
+        Ok(IndexField {
+            field,
+            vectors: RwLock::new(vectors),
+            index_dir,
+            flush_count: AtomicU32::new(count),
+            snapshot_time: AtomicU64::new(current_millis()),
+            snapshot_ms,
+        })
+    }
+
+    pub fn count(&self) -> u32 {
+        self.vectors.read().unwrap().ids.len() as u32
+    }
+
+    pub fn max_id(&self) -> u32 {
+        self.vectors.read().unwrap().max_id()
+    }
+
+    fn need_graph(&self, count: usize) -> bool {
+        self.field.train_size > 0 && count > self.field.train_size as usize
+    }
+
+    fn new_graph(&self) -> Hnsw {
+        let m = match &self.field.index_params {
+            Some(p) => p.hnsw_m as usize,
+            None => 0,
+        };
+        Hnsw::new(m)
+    }
+
+    //append the vectors to index, the graph is made when they are enough
+    fn append(&self, vectors: &mut Vectors, ids: Vec<u32>, data: Vec<f32>) {
+        let start = vectors.ids.len();
+        vectors.ids.extend(ids);
+        vectors.data.extend(data);
+
+        let from = match vectors.graph {
+            Some(_) => start,
+            None if self.need_graph(vectors.ids.len()) => {
+                info!(
+                    "field:{} has {} vectors, begin to make hnsw graph",
+                    self.field.name,
+                    vectors.ids.len()
+                );
+                vectors.graph = Some(self.new_graph());
+                0
+            }
+            None => return,
+        };
+
+        let space = Space::new(
+            &vectors.data,
+            self.field.dimension as usize,
+            &self.field.metric_type,
+        );
+        let graph = vectors.graph.as_mut().unwrap();
+        for node in from..vectors.ids.len() {
+            graph.insert(&space, node as u32);
+        }
+    }
+
+    //the nearest k (iid, score) of query
+    fn nearest(&self, query: &[f32], k: usize, ef: usize) -> Vec<(u32, f32)> {
+        let vectors = self.vectors.read().unwrap();
+        let space = Space::new(
+            &vectors.data,
+            self.field.dimension as usize,
+            &self.field.metric_type,
+        );
+
+        let nodes = match &vectors.graph {
+            Some(graph) => graph.search(&space, query, k, ef),
+            None => {
+                let mut all: Vec<(u32, f32)> = (0..space.len() as u32)
+                    .map(|n| (n, space.distance(query, n)))
+                    .collect();
+                all.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+                all.truncate(k);
+                all
+            }
+        };
+
+        nodes
+            .into_iter()
+            .map(|(n, d)| (vectors.ids[n as usize], space.score(d)))
+            .collect()
+    }
+
+    fn flush(&self, force: bool) -> ASResult<()> {
+        let count = self.count();
+        let added = count.saturating_sub(self.flush_count.load(SeqCst));
+        let elapsed = current_millis().saturating_sub(self.snapshot_time.load(SeqCst));
+        if !force && added <= FLUSH_COUNT && (added == 0 || elapsed <= self.snapshot_ms) {
+            return Ok(());
+        }
+        self.flush_count.store(count, SeqCst);
+        self.snapshot_time.store(current_millis(), SeqCst);
+        self._flush()
+    }
+
+    fn _flush(&self) -> ASResult<()> {
+        let buf = self.vectors.read().unwrap().encode();
+        let tmp = self
+            .index_dir
+            .join(format!("{}_{}", self.field.name, INDEX_FILE_NAME_TMP));
+        let effe = self
+            .index_dir
+            .join(format!("{}_{}", self.field.name, INDEX_FILE_NAME));
+        std::fs::write(&tmp, buf)?;
+        std::fs::rename(tmp, effe)?;
+        Ok(())
+    }
+}
+
+// the vector engine in pure rust when faiss is not linked, it has the same interface of
+// faiss engine. the vectors are searched by brute force until they are more than
+// train_size of field, then by a hnsw graph
+pub struct Faiss {
+    db: Arc<RocksDB>,
+    base: Arc<BaseEngine>,
+    pub fields: HashMap<String, Arc<IndexField>>,
+}
+
+impl Deref for Faiss {
+    type Target = Arc<BaseEngine>;
+    fn deref<'a>(&'a self) -> &'a Arc<BaseEngine> {
+        &self.base
+    }
+}
+
+impl Faiss {
+    pub fn new(db: Arc<RocksDB>, base: Arc<BaseEngine>) -> ASResult<Faiss> {
+        let mut faiss = Faiss {
+            db: db.clone(),
+            base: base.clone(),
+            fields: HashMap::new(),
+        };
+
+        let index_dir = base.base_path().join(Path::new(VECTOR_DIR_NAME));
+        let snapshot_ms = base
+            .conf
+            .ps
+            .vector_snapshot_sec
+            .unwrap_or(DEFAULT_SNAPSHOT_SEC)
+            * 1000;
+        let collection = base.collection();
+        for i in collection.vector_field_index.iter() {
+            let f = collection.fields[*i as usize].vector()?;
+            if !index_dir.exists() {
+                std::fs::create_dir_all(&index_dir)?;
+            }
+
+            let index = Arc::new(IndexField::open(f.clone(), index_dir.clone(), snapshot_ms)?);
+            //catch up the vectors written after last flush
+            while index_vectors(&db, &index)? > 0 {}
+
+            {
+                let db = db.clone();
+                let base = base.clone();
+                let index = index.clone();
+                std::thread::spawn(move || {
+                    let name = index.field.name.clone();
+                    Faiss::index_job(db, base, index);
+                    warn!("field:{:?} stop index_job", name);
+                });
+            }
+
+            faiss.fields.insert(f.name.clone(), index);
+        }
+        Ok(faiss)
+    }
+
+    pub fn search(
+        &self,
+        sdreq: Arc<QueryRequest>,
+        bitmap: Option<RoaringBitmap>,
+        total: u64,
+    ) -> ASResult<SearchDocumentResponse> {
+        let mut sdr = SearchDocumentResponse {
+            code: Code::Success as i32,
+            total: total,
+            hits: Vec::new(),
+            info: None, //if this is none means it is success
+            batch: Vec::new(),
+        };
+
+        if total == 0 {
+            return Ok(sdr);
+        }
+
+        if let Some(vq) = sdreq.vector_query.as_ref() {
+            let index = self.get_field(vq.field.as_str())?;
+            let mut batch = self.knn(&index, vq, sdreq.size as usize, bitmap.as_ref())?;
+            if batch.len() == 1 {
+                sdr.hits = batch.pop().unwrap();
+            } else {
+                sdr.batch = batch.into_iter().map(|hits| HitList { hits }).collect();
+            }
+            return Ok(sdr);
+        }
+        return result_def!("impossible");
+    }
+
+    //the vectors are exact, so nprobe and rerank are not need. a document of array field
+    //has many vectors and the filter skips some, it searches more until there are size documents
+    fn knn(
+        &self,
+        index: &IndexField,
+        vq: &VectorQuery,
+        size: usize,
+        bitmap: Option<&RoaringBitmap>,
+    ) -> ASResult<Vec<Vec<Hit>>> {
+        let dimension = index.field.dimension as usize;
+        if vq.vector.len() == 0 || vq.vector.len() % dimension != 0 {
+            return result!(
+                Code::ParamError,
+                "the len:{} of vector is not multiple of dimension:{}",
+                vq.vector.len(),
+                dimension
+            );
+        }
+
+        let ef = if vq.ef_search > 0 {
+            vq.ef_search as usize
+        } else {
+            match &index.field.index_params {
+                Some(p) if p.ef_search > 0 => p.ef_search as usize,
+                _ => DEFAULT_EF_SEARCH,
+            }
+        };
+
+        let metric = &index.field.metric_type;
+        let min = metric.min_similarity(vq)?;
+        let collection_name = self.collection().name.clone();
+        let partition_id = u32_slice(self.partition.id);
+        let count = index.count() as usize;
+
+        let mut queries = vq.vector.clone();
+        index.field.normalize(&mut queries)?;
+
+        let mut batch = Vec::with_capacity(queries.len() / dimension);
+        for query in queries.chunks(dimension) {
+            let mut k = size;
+            let hits = loop {
+                let nears = index.nearest(query, k, ef);
+                let full = nears.len() >= k;
+                let mut hits: Vec<Hit> = Vec::with_capacity(size);
+                let mut iids = HashSet::with_capacity(size);
+                //the rest are weaker than the one under min
+                let mut weak = false;
+                for (iid, score) in nears {
+                    if hits.len() >= size {
+                        break;
+                    }
+                    let score = metric.similarity(score);
+                    if min.map_or(false, |m| score < m) {
+                        weak = true;
+                        break;
+                    }
+                    //the ones not matched by the filter query are skipped
+                    if bitmap.map_or(false, |b| !b.contains(iid)) {
+                        continue;
+                    }
+                    if !iids.insert(iid) {
+                        continue;
+                    }
+                    let iid = u32_slice(iid);
+                    hits.push(Hit {
+                        collection_name: collection_name.clone(),
+                        score: score,
+                        doc: iid.to_vec(),
+                        sort: vec![score_coding(score), tie_coding(&partition_id, &iid)],
+                        highlights: HashMap::new(),
+                    });
+                }
+                if weak
+                    || hits.len() >= size
+                    || !full
+                    || !(index.field.array || bitmap.is_some())
+                    || k >= count
+                {
+                    break hits;
+                }
+                k = std::cmp::min(k * 2, count);
+            };
+            batch.push(hits);
+        }
+
+        Ok(batch)
+    }
+
+    //rebuild the vector indexes from rocksdb, progress is called by the number of vectors indexed
+    pub fn rebuild(&self, mut progress: impl FnMut(u64)) -> ASResult<()> {
+        for (_, index) in self.fields.iter() {
+            info!("field:{} begin rebuild vector index", index.field.name);
+            let mut vectors = Vectors::new();
+            loop {
+                if !self.runing() {
+                    return result!(
+                        Code::EngineWillClose,
+                        "field:{} is stoped",
+                        index.field.name
+                    );
+                }
+                let (ids, data) = read_vectors(&self.db, &index.field, vectors.max_id())?;
+                if ids.len() == 0 {
+                    break;
+                }
+                progress(ids.len() as u64);
+                index.append(&mut vectors, ids, data);
+            }
+            //the index job goes on from the max id of new one
+            *index.vectors.write().unwrap() = vectors;
+            index.flush_count.store(index.count(), SeqCst);
+            index._flush()?;
+            info!(
+                "field:{} rebuild vector index ok, count:{}",
+                index.field.name,
+                index.count()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn get_field(&self, name: &str) -> ASResult<Arc<IndexField>> {
+        match self.fields.get(name) {
+            Some(i) => Ok(i.clone()),
+            None => result_def!("the field:{} not have vector index", name),
+        }
+    }
+
+    fn index_job(db: Arc<RocksDB>, base: Arc<BaseEngine>, index: Arc<IndexField>) {
+        db.arc_count.fetch_add(1, SeqCst);
+        while base.runing() {
+            match index_vectors(&db, &index) {
+                Ok(0) => crate::sleep!(3000),
+                Ok(_) => {}
+                Err(e) => {
+                    error!("field:{} index vectors has err:{:?}", index.field.name, e);
+                    crate::sleep!(3000);
+                }
+            }
+            if let Err(e) = index.flush(false) {
+                error!("field:{} flush has err:{:?}", index.field.name, e);
+            }
+        }
+        if let Err(e) = index.flush(true) {
+            error!("field:{} flush has err:{:?}", index.field.name, e);
+        }
+        db.arc_count.fetch_sub(1, SeqCst);
+    }
+}
+
+impl Engine for Faiss {
+    fn flush(&self) -> ASResult<()> {
+        for (_, fi) in self.fields.iter() {
+            if let Err(e) = fi.flush(false) {
+                info!(
+                    "field:{} flush vector index has err:{}",
+                    fi.field.name,
+                    e.to_string()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn release(&self) {
+        for (_, fi) in self.fields.iter() {
+            if let Err(e) = fi.flush(true) {
+                error!("field:{} flush has err:{:?}", fi.field.name, e);
+            }
+        }
+    }
+}
+
+//index the vectors after the max id of index, return the number of them
+fn index_vectors(db: &Arc<RocksDB>, index: &IndexField) -> ASResult<usize> {
+    let (ids, data) = read_vectors(db, &index.field, index.max_id())?;
+    let len = ids.len();
+    if len > 0 {
+        index.append(&mut index.vectors.write().unwrap(), ids, data);
+    }
+    Ok(len)
+}
+
+//read a batch of vectors after max_id, every vector of array field has the iid
+fn read_vectors(
+    db: &Arc<RocksDB>,
+    field: &VectorField,
+    max_id: u32,
+) -> ASResult<(Vec<u32>, Vec<f32>)> {
+    let dimension = field.dimension as usize;
+    let suffix = field_coding(&field.name, u32::max_value());
+    let mut ids = Vec::with_capacity(BATCH_SIZE);
+    let mut data = Vec::with_capacity(BATCH_SIZE * dimension);
+
+    db.prefix_range(field_coding(&field.name, max_id + 1), |k, v| {
+        if k >= suffix.as_slice() {
+            return Ok(false);
+        }
+        let id = slice_u32(&k[k.len() - 4..]);
+        let vectors: &[f32] = slice_slice(v);
+        for _ in 0..vectors.len() / dimension {
+            ids.push(id);
+        }
+        data.extend_from_slice(vectors);
+        Ok(ids.len() < BATCH_SIZE)
+    })?;
+
+    Ok((ids, data))
+}
@@ -12,9 +12,10 @@
 // implied. See the License for the specific language governing
 // permissions and limitations under the License.
 pub mod engine;
-#[cfg(vector)]
+#[cfg(feature = "vector")]
 pub mod faiss;
-#[cfg(not(vector))]
-pub mod faiss_empty;
+pub mod hnsw;
+#[cfg(not(feature = "vector"))]
+pub mod knn;
 pub mod rocksdb;
 pub mod tantivy;
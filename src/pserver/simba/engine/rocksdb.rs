@@ -12,12 +12,18 @@
 // implied. See the License for the specific language governing
 // permissions and limitations under the License.
 use crate::pserver::simba::engine::engine::{BaseEngine, Engine};
+use crate::pserverpb::{Document, Vector};
 use crate::util::{
-    coding::{slice_u32, slice_u64, u64_slice, RAFT_INDEX_KEY},
+    coding::{
+        doc_key, expire_coding, expire_decoding, field_coding, iid_coding, slice_slice, slice_u32,
+        slice_u64, u64_slice, version_expire_coding, version_expire_decoding, FENCE_KEY,
+        INDEX_DIR_KEY, RAFT_INDEX_KEY,
+    },
     error::*,
 };
 use crate::*;
 use log::{error, info};
+use prost::Message;
 use rocksdb::{ColumnFamily, Direction, FlushOptions, IteratorMode, WriteBatch, WriteOptions, DB};
 use std::ops::Deref;
 use std::path::Path;
@@ -132,6 +138,42 @@ impl RocksDB {
         }
     }
 
+    //it is flushed at once, the index dir must not be lost
+    pub fn write_index_dir(&self, dir_name: &str) -> ASResult<()> {
+        let mut batch = WriteBatch::default();
+        batch.put(INDEX_DIR_KEY, dir_name.as_bytes());
+        conver(self.db.write_opt(batch, &&self.wo))?;
+        self.flush()
+    }
+
+    pub fn read_index_dir(&self) -> ASResult<Option<String>> {
+        match self.db.get(INDEX_DIR_KEY)? {
+            Some(bs) => Ok(Some(String::from_utf8_lossy(bs.as_slice()).to_string())),
+            None => Ok(None),
+        }
+    }
+
+    //the (write_fence, read_fence) of split, None if it never be set
+    pub fn read_fence(&self) -> ASResult<Option<(u64, u64)>> {
+        match self.db.get(FENCE_KEY)? {
+            Some(bs) => Ok(Some((slice_u64(&bs[..8]), slice_u64(&bs[8..16])))),
+            None => Ok(None),
+        }
+    }
+
+    //the document of iid is not deleted or replaced by update
+    pub fn is_live(&self, iid: u32) -> ASResult<bool> {
+        let value = match self.get_doc_by_id(iid_coding(iid))? {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+        let doc: Document = Message::decode(prost::bytes::Bytes::from(value))?;
+        match self.db.get(doc_key(&doc))? {
+            Some(v) => Ok(v.as_slice() == &iid_coding(iid)[..]),
+            None => Ok(false),
+        }
+    }
+
     pub fn find_max_iid(&self) -> u32 {
         let iter = self.db.iterator_cf(self.id_cf(), IteratorMode::End); // From a key in Direction::{forward,reverse}
 
@@ -142,6 +184,133 @@ impl RocksDB {
         return 0;
     }
 
+    // iterate documents by iid from from_iid, the one has been updated or deleted will be skipped
+    pub fn scan_docs(
+        &self,
+        from_iid: u32,
+        mut f: impl FnMut(u32, Document) -> ASResult<bool>,
+    ) -> ASResult<()> {
+        let from = iid_coding(from_iid);
+        let iter = self
+            .db
+            .iterator_cf(self.id_cf(), IteratorMode::From(&from, Direction::Forward));
+
+        for (k, v) in iter {
+            let doc: Document = Message::decode(prost::bytes::Bytes::from(v.to_vec()))?;
+            match self.db.get(doc_key(&doc))? {
+                Some(iid) if iid.as_slice() == &*k => {}
+                _ => continue,
+            }
+            if !f(slice_u32(&k), doc)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // iterate the live documents on a snapshot, the writes after it are not visible.
+    // the vectors of vector_fields are attached to the document
+    pub fn snapshot_docs(
+        &self,
+        vector_fields: &Vec<String>,
+        mut f: impl FnMut(u32, Document) -> ASResult<bool>,
+    ) -> ASResult<()> {
+        let snapshot = self.db.snapshot();
+        let iter = snapshot.iterator_cf(self.id_cf(), IteratorMode::Start);
+
+        for (k, v) in iter {
+            let mut doc: Document = Message::decode(prost::bytes::Bytes::from(v.to_vec()))?;
+            match snapshot.get(doc_key(&doc))? {
+                Some(iid) if iid.as_slice() == &*k => {}
+                _ => continue,
+            }
+            let iid = slice_u32(&k);
+            for name in vector_fields {
+                if let Some(v) = snapshot.get(field_coding(name, iid))? {
+                    doc.vectors.push(Vector {
+                        name: name.to_string(),
+                        vector: slice_slice(&v).to_vec(),
+                    });
+                }
+            }
+            if !f(iid, doc)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // iterate the expire keys which expire_at <= now by time, the document of iid may have
+    // been replaced or deleted, so the caller must check it
+    pub fn scan_expired(
+        &self,
+        now: u64,
+        mut f: impl FnMut(&[u8], u32) -> ASResult<bool>,
+    ) -> ASResult<()> {
+        let end = expire_coding(now + 1, 0);
+        let iter = self
+            .db
+            .iterator(IteratorMode::From(&[5], Direction::Forward));
+
+        for (k, _) in iter {
+            if &*k >= end.as_slice() {
+                break;
+            }
+            if !f(&*k, expire_decoding(&k).1)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // iterate the version expire keys which deleted_at < before by time, the version of key
+    // may have been removed or written again, so the caller must check it
+    pub fn scan_versions(
+        &self,
+        before: u64,
+        mut f: impl FnMut(&[u8], u64, u32, &[u8]) -> ASResult<bool>,
+    ) -> ASResult<()> {
+        let end = version_expire_coding(before, 0, &[]);
+        let iter = self
+            .db
+            .iterator(IteratorMode::From(&[7], Direction::Forward));
+
+        for (k, _) in iter {
+            if &*k >= end.as_slice() {
+                break;
+            }
+            let (deleted_at, slot, key) = version_expire_decoding(&k);
+            if !f(&*k, deleted_at, slot, key)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    //the iids which expire_at <= now, include the replaced ones
+    pub fn expired_iids(&self, now: u64) -> ASResult<Vec<u32>> {
+        let mut iids = Vec::new();
+        self.scan_expired(now, |_, iid| {
+            iids.push(iid);
+            Ok(true)
+        })?;
+        Ok(iids)
+    }
+
+    //whether there is a expire key which expire_at <= now, include the replaced ones
+    pub fn has_expired(&self, now: u64) -> ASResult<bool> {
+        let mut has = false;
+        self.scan_expired(now, |_, _| {
+            has = true;
+            Ok(false)
+        })?;
+        Ok(has)
+    }
+
     // query rocksdb range key ,  the range key > prefix , end  ,
     pub fn prefix_range(
         &self,
@@ -1,5 +1,5 @@
-use crate::pserver::simba::engine::tantivy::ID_INDEX;
-use crate::util::coding::slice_i64;
+use crate::pserver::simba::engine::tantivy::ID_BYTES_INDEX;
+use crate::util::coding::slice_u32;
 use roaring::RoaringBitmap;
 use tantivy::{
     collector::Collector, collector::SegmentCollector, fastfield::BytesFastFieldReader,
@@ -20,7 +20,7 @@ impl Collector for Bitmap {
     ) -> tantivy::Result<SegmentBitMapCollector> {
         Ok(SegmentBitMapCollector {
             bit_map: RoaringBitmap::new(),
-            fast_field: sr.fast_fields().bytes(Field::from_field_id(ID_INDEX)),
+            fast_field: sr.fast_fields().bytes(Field::from_field_id(ID_BYTES_INDEX)),
         })
     }
 
@@ -49,7 +49,7 @@ impl SegmentCollector for SegmentBitMapCollector {
         if let Some(ffr) = self.fast_field.as_ref() {
             let v = ffr.get_bytes(doc_id);
             if v.len() > 0 {
-                self.bit_map.insert(slice_i64(v) as u32);
+                self.bit_map.insert(slice_u32(v));
             }
         }
     }
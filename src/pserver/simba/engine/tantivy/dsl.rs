@@ -0,0 +1,306 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use crate::util::{convert::*, error::*};
+use crate::*;
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+use std::convert::TryInto;
+use std::ops::Bound;
+use tantivy::{
+    query::{
+        AllQuery, BooleanQuery, EmptyQuery, Occur, PhraseQuery, Query, RangeQuery, RegexQuery,
+        TermQuery,
+    },
+    schema::{Field, FieldType, IndexRecordOption, Type},
+    Index, Term,
+};
+
+// compile the json dsl to query, the dsl is a object with one key as:
+// {"bool":{"must":[..], "should":[..], "must_not":[..]}}
+// {"term":{"field":value}}
+// {"terms":{"field":[value, ..]}}
+// {"range":{"field":{"gt|gte|lt|lte":value}}}
+// {"prefix":{"field":"str"}}
+// {"wildcard":{"field":"str*?"}}
+// {"exists":{"field":"name"}}
+// {"match_phrase":{"field":"text"}}
+pub fn parse(index: &Index, dsl: &str) -> ASResult<Box<dyn Query>> {
+    let value: Value = serde_json::from_str(dsl)
+        .map_err(|e| err!(Code::ParamError, "dsl:{} is not json:{}", dsl, e))?;
+    Compiler { index }.compile(&value)
+}
+
+struct Compiler<'a> {
+    index: &'a Index,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile(&self, value: &Value) -> ASResult<Box<dyn Query>> {
+        let (name, body) = single_entry(value)?;
+        match name {
+            "bool" => self.bool_query(body),
+            "term" => {
+                let (field, v) = self.field_entry(body)?;
+                Ok(Box::new(TermQuery::new(
+                    self.term(field, v)?,
+                    IndexRecordOption::Basic,
+                )))
+            }
+            "terms" => {
+                let (field, v) = self.field_entry(body)?;
+                let values = v.as_array().ok_or_else(|| {
+                    err!(Code::ParamError, "terms need array values, found:{}", v)
+                })?;
+                let mut queries: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(values.len());
+                for v in values {
+                    queries.push((
+                        Occur::Should,
+                        Box::new(TermQuery::new(
+                            self.term(field, v)?,
+                            IndexRecordOption::Basic,
+                        )),
+                    ));
+                }
+                if queries.len() == 0 {
+                    return Ok(Box::new(EmptyQuery));
+                }
+                Ok(Box::new(BooleanQuery::from(queries)))
+            }
+            "range" => {
+                let (field, v) = self.field_entry(body)?;
+                self.range_query(field, v)
+            }
+            "prefix" => {
+                let (field, v) = self.field_entry(body)?;
+                let prefix = self.str_value(field, v)?;
+                let pattern = format!("{}.*", regex_escape(prefix));
+                self.regex_query(field, pattern.as_str())
+            }
+            "wildcard" => {
+                let (field, v) = self.field_entry(body)?;
+                let mut pattern = String::new();
+                for c in self.str_value(field, v)?.chars() {
+                    match c {
+                        '*' => pattern.push_str(".*"),
+                        '?' => pattern.push('.'),
+                        _ => pattern.push_str(regex_escape(c.to_string().as_str()).as_str()),
+                    }
+                }
+                self.regex_query(field, pattern.as_str())
+            }
+            "exists" => {
+                let name = body.get("field").and_then(|v| v.as_str()).ok_or_else(|| {
+                    err!(Code::ParamError, "exists need field name, found:{}", body)
+                })?;
+                let field = self.field(name)?;
+                //all terms of field, so the documents have any value of it
+                Ok(Box::new(RangeQuery::new_term_bounds(
+                    field,
+                    self.value_type(field),
+                    &Bound::Unbounded,
+                    &Bound::Unbounded,
+                )))
+            }
+            "match_phrase" => {
+                let (field, v) = self.field_entry(body)?;
+                self.phrase_query(field, v)
+            }
+            _ => result!(Code::ParamError, "dsl not support query:{}", name),
+        }
+    }
+
+    fn bool_query(&self, body: &Value) -> ASResult<Box<dyn Query>> {
+        let body = body
+            .as_object()
+            .ok_or_else(|| err!(Code::ParamError, "bool need object, found:{}", body))?;
+
+        let mut queries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        let mut only_not = true;
+        for (k, v) in body {
+            let occur = match k.as_str() {
+                "must" => Occur::Must,
+                "should" => Occur::Should,
+                "must_not" => Occur::MustNot,
+                _ => return result!(Code::ParamError, "bool not support clause:{}", k),
+            };
+            if occur != Occur::MustNot {
+                only_not = false;
+            }
+            //the clause can be one query or array of them
+            match v {
+                Value::Array(arr) => {
+                    for q in arr {
+                        queries.push((occur, self.compile(q)?));
+                    }
+                }
+                _ => queries.push((occur, self.compile(v)?)),
+            }
+        }
+
+        if queries.len() == 0 {
+            return Ok(Box::new(AllQuery));
+        }
+
+        //must_not alone excludes from all documents
+        if only_not {
+            queries.push((Occur::Must, Box::new(AllQuery)));
+        }
+
+        Ok(Box::new(BooleanQuery::from(queries)))
+    }
+
+    fn range_query(&self, field: Field, body: &Value) -> ASResult<Box<dyn Query>> {
+        let body = body
+            .as_object()
+            .ok_or_else(|| err!(Code::ParamError, "range need object, found:{}", body))?;
+
+        let (mut left, mut right) = (Bound::Unbounded, Bound::Unbounded);
+        for (k, v) in body {
+            let term = self.term(field, v)?;
+            match k.as_str() {
+                "gt" => left = Bound::Excluded(term),
+                "gte" => left = Bound::Included(term),
+                "lt" => right = Bound::Excluded(term),
+                "lte" => right = Bound::Included(term),
+                _ => return result!(Code::ParamError, "range not support:{}", k),
+            }
+        }
+
+        Ok(Box::new(RangeQuery::new_term_bounds(
+            field,
+            self.value_type(field),
+            &left,
+            &right,
+        )))
+    }
+
+    fn regex_query(&self, field: Field, pattern: &str) -> ASResult<Box<dyn Query>> {
+        match RegexQuery::from_pattern(pattern, field) {
+            Ok(q) => Ok(Box::new(q)),
+            Err(e) => result!(Code::ParamError, "pattern:{} is invalid:{:?}", pattern, e),
+        }
+    }
+
+    //the text is split by the analyzer of field, the terms must be adjacent
+    fn phrase_query(&self, field: Field, v: &Value) -> ASResult<Box<dyn Query>> {
+        let text = self.str_value(field, v)?;
+        let analyzer = conver(self.index.tokenizer_for_field(field))?;
+
+        let mut terms = Vec::new();
+        let mut stream = analyzer.token_stream(text);
+        while stream.advance() {
+            terms.push(Term::from_field_text(field, stream.token().text.as_str()));
+        }
+
+        match terms.len() {
+            0 => Ok(Box::new(EmptyQuery)),
+            1 => Ok(Box::new(TermQuery::new(
+                terms.pop().unwrap(),
+                IndexRecordOption::WithFreqs,
+            ))),
+            _ => Ok(Box::new(PhraseQuery::new(terms))),
+        }
+    }
+
+    //{"field_name": value}
+    fn field_entry<'b>(&self, body: &'b Value) -> ASResult<(Field, &'b Value)> {
+        let (name, v) = single_entry(body)?;
+        Ok((self.field(name)?, v))
+    }
+
+    fn field(&self, name: &str) -> ASResult<Field> {
+        self.index
+            .schema()
+            .get_field(name)
+            .ok_or_else(|| err!(Code::ParamError, "not found field:{} in index", name))
+    }
+
+    fn value_type(&self, field: Field) -> Type {
+        self.index
+            .schema()
+            .get_field_entry(field)
+            .field_type()
+            .value_type()
+    }
+
+    fn str_value<'b>(&self, field: Field, v: &'b Value) -> ASResult<&'b str> {
+        match (self.value_type(field), v.as_str()) {
+            (Type::Str, Some(s)) => Ok(s),
+            (Type::Str, None) => result!(Code::ParamError, "need string value, found:{}", v),
+            _ => result!(
+                Code::FieldTypeErr,
+                "field:{} is not string or text",
+                self.index.schema().get_field_name(field)
+            ),
+        }
+    }
+
+    //make term by the type of field
+    fn term(&self, field: Field, v: &Value) -> ASResult<Term> {
+        let schema = self.index.schema();
+        let term = match schema.get_field_entry(field).field_type() {
+            FieldType::Str(_) => {
+                let s: String = json(v)?.try_into()?;
+                Term::from_field_text(field, s.as_str())
+            }
+            FieldType::I64(_) => Term::from_field_i64(field, json(v)?.try_into()?),
+            FieldType::F64(_) => Term::from_field_f64(field, json(v)?.try_into()?),
+            FieldType::Date(_) => {
+                let naive: NaiveDateTime = json(v)?.try_into()?;
+                Term::from_field_date(field, &DateTime::from_utc(naive, Utc))
+            }
+            _ => {
+                return result!(
+                    Code::FieldTypeErr,
+                    "field:{} not support query by dsl",
+                    schema.get_field_name(field)
+                )
+            }
+        };
+        Ok(term)
+    }
+}
+
+fn single_entry(value: &Value) -> ASResult<(&str, &Value)> {
+    let map: &Map<String, Value> = value
+        .as_object()
+        .ok_or_else(|| err!(Code::ParamError, "dsl need object, found:{}", value))?;
+    if map.len() != 1 {
+        return result!(
+            Code::ParamError,
+            "dsl object need only one key, found:{}",
+            value
+        );
+    }
+    let (k, v) = map.iter().next().unwrap();
+    Ok((k.as_str(), v))
+}
+
+fn regex_escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        //the meta characters of regex syntax
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[test]
+fn regex_escape_test() {
+    assert_eq!("a\\.b\\*", regex_escape("a.b*"));
+    assert_eq!("中国", regex_escape("中国"));
+}
@@ -0,0 +1,180 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use crate::pserverpb::*;
+use crate::util::{
+    convert::*,
+    entity::{Collection, Field},
+    error::*,
+};
+use crate::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use tantivy::{query::Query, Searcher, Snippet, SnippetGenerator};
+
+const DEFAULT_FRAGMENT_SIZE: usize = 150;
+const DEFAULT_PRE_TAG: &'static str = "<em>";
+const DEFAULT_POST_TAG: &'static str = "</em>";
+const ENCODER_HTML: &'static str = "html";
+const ENCODER_NONE: &'static str = "none";
+
+// Highlighter makes the fragments of text fields for the terms of query. the text is not
+// stored in index, so it is taken from the source of document
+pub struct Highlighter {
+    generators: Vec<(String, SnippetGenerator)>,
+    pre_tag: String,
+    post_tag: String,
+    //the text of source is escaped, so the fragments can be shown as html
+    escape: bool,
+}
+
+impl Highlighter {
+    pub fn new(
+        searcher: &Searcher,
+        query: &dyn Query,
+        collection: &Collection,
+        hl: &Highlight,
+    ) -> ASResult<Highlighter> {
+        let fragment_size = if hl.fragment_size > 0 {
+            hl.fragment_size as usize
+        } else {
+            DEFAULT_FRAGMENT_SIZE
+        };
+
+        let escape = match hl.encoder.as_str() {
+            "" | ENCODER_HTML => true,
+            ENCODER_NONE => false,
+            e => {
+                return result!(
+                    Code::ParamError,
+                    "highlight encoder:{} only support {} or {}",
+                    e,
+                    ENCODER_HTML,
+                    ENCODER_NONE
+                )
+            }
+        };
+
+        let mut generators = Vec::with_capacity(hl.fields.len());
+        for name in hl.fields.iter() {
+            match collection.fields.iter().find(|f| f.name() == name) {
+                Some(Field::text(_)) => {}
+                Some(_) => {
+                    return result!(Code::ParamError, "highlight field:{} is not text", name)
+                }
+                None => return result!(Code::ParamError, "highlight field:{} not found", name),
+            }
+            let field = searcher
+                .schema()
+                .get_field(name)
+                .ok_or_else(|| err!(Code::ParamError, "highlight field:{} not in index", name))?;
+            let mut generator = conver(SnippetGenerator::create(searcher, query, field))?;
+            generator.set_max_num_chars(fragment_size);
+            generators.push((name.clone(), generator));
+        }
+
+        let tag = |tag: &str, default: &str| {
+            if tag.is_empty() {
+                default.to_string()
+            } else {
+                tag.to_string()
+            }
+        };
+
+        Ok(Highlighter {
+            generators,
+            pre_tag: tag(hl.pre_tag.as_str(), DEFAULT_PRE_TAG),
+            post_tag: tag(hl.post_tag.as_str(), DEFAULT_POST_TAG),
+            escape,
+        })
+    }
+
+    //the fragments of fields in source, every element of array field may have one
+    pub fn highlight(&self, source: &Value) -> HashMap<String, Fragments> {
+        let mut result = HashMap::new();
+        for (name, generator) in self.generators.iter() {
+            let mut fragments = Vec::new();
+            match &source[name.as_str()] {
+                Value::String(text) => self.push(generator, text, &mut fragments),
+                Value::Array(arr) => {
+                    for v in arr {
+                        if let Value::String(text) = v {
+                            self.push(generator, text, &mut fragments);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if fragments.len() > 0 {
+                result.insert(name.clone(), Fragments { fragments });
+            }
+        }
+        result
+    }
+
+    fn push(&self, generator: &SnippetGenerator, text: &str, fragments: &mut Vec<String>) {
+        let snippet = generator.snippet(text);
+        if snippet.highlighted().len() > 0 {
+            fragments.push(self.tag(&snippet));
+        }
+    }
+
+    //wrap the highlighted sections of fragment by tags, the tags are not escaped
+    fn tag(&self, snippet: &Snippet) -> String {
+        let fragment = snippet.fragments();
+        let mut result = String::with_capacity(fragment.len() + 16);
+        let mut start = 0;
+        for section in snippet.highlighted() {
+            let (from, to) = section.bounds();
+            self.push_text(&mut result, &fragment[start..from]);
+            result.push_str(self.pre_tag.as_str());
+            self.push_text(&mut result, &fragment[from..to]);
+            result.push_str(self.post_tag.as_str());
+            start = to;
+        }
+        self.push_text(&mut result, &fragment[start..]);
+        result
+    }
+
+    fn push_text(&self, result: &mut String, text: &str) {
+        if self.escape {
+            escape_html(result, text);
+        } else {
+            result.push_str(text);
+        }
+    }
+}
+
+//the same chars as Snippet::to_html escapes
+fn escape_html(result: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#x27;"),
+            c => result.push(c),
+        }
+    }
+}
+
+#[test]
+fn escape_fragment() {
+    let mut result = String::new();
+    escape_html(&mut result, r#"<script>alert("x") & 'y'</script> java"#);
+    assert_eq!(
+        "&lt;script&gt;alert(&quot;x&quot;) &amp; &#x27;y&#x27;&lt;/script&gt; java",
+        result
+    );
+}
@@ -13,7 +13,10 @@
 // permissions and limitations under the License.
 mod aggregation_collector;
 pub mod bitmap_collector;
+mod dsl;
+mod highlight;
 pub mod sort;
+pub mod tokenizer;
 
 use crate::pserver::simba::aggregation::Aggregator;
 use crate::pserver::simba::engine::{
@@ -22,13 +25,14 @@ use crate::pserver::simba::engine::{
 };
 use crate::pserverpb::*;
 use crate::util::coding::{
-    f32_slice, iid_coding,
+    iid_coding, slice_f32,
     sort_coding::{f64_arr_coding, i64_arr_coding, str_arr_coding},
 };
 use crate::util::{
     convert::*,
-    entity::{Field::*, ID_BYTES},
+    entity::{Collection, Field::*, ID_BYTES},
     error::*,
+    time::current_millis,
 };
 use crate::*;
 use chrono::prelude::*;
@@ -36,9 +40,10 @@ use log::{debug, error, info, warn};
 use roaring::RoaringBitmap;
 use std::convert::TryInto;
 use std::{
+    collections::HashMap,
     fs,
     ops::Deref,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicU32, Ordering::SeqCst},
         mpsc::{channel, Receiver, Sender},
@@ -49,7 +54,7 @@ use std::{
 use tantivy::{
     collector::{Count, MultiCollector, TopDocs},
     directory::MmapDirectory,
-    query::{QueryParser, TermQuery},
+    query::{BooleanQuery, Occur, Query, QueryParser, RangeQuery, TermQuery},
     schema,
     schema::{Field, FieldType, FieldValue, IndexRecordOption, Schema, Value},
     Document, Index, IndexReader, IndexWriter, ReloadPolicy, Term,
@@ -60,17 +65,28 @@ const INDEXER_THREAD: usize = 1;
 const ID: &'static str = "_iid";
 const ID_INDEX: u32 = 0;
 const ID_BYTES_INDEX: u32 = 1;
-const INDEX_DIR_NAME: &'static str = "index";
+//the expire time of document, 0 is never. it is the last field, so the ids of others not changed.
+//the index made before it has not, and it is added when the index rebuilt
+const EXPIRE_AT: &'static str = "_expire_at";
+pub const INDEX_DIR_NAME: &'static str = "index";
 
+#[derive(Clone)]
 pub enum Event {
     Delete(u32),
     // Update(old_iid , new_iid)
     Update(u32, u32),
+    // Fill(iid) is sent by rebuild, the document is indexed only if it is still live
+    Fill(u32),
+    // Commit is done after all events before it, the result is sent back
+    Commit(Sender<ASResult<()>>),
     Stop,
 }
 
 pub struct Tantivy {
     base: Arc<BaseEngine>,
+    //the collection which the schema of index made by
+    collection: Arc<Collection>,
+    index_dir: PathBuf,
     index: Index,
     index_writer: RwLock<IndexWriter>,
     index_reader: IndexReader,
@@ -88,15 +104,79 @@ impl Deref for Tantivy {
 }
 
 impl Tantivy {
-    pub fn new(db: Arc<RocksDB>, base: Arc<BaseEngine>) -> ASResult<Arc<Tantivy>> {
+    pub fn new(
+        db: Arc<RocksDB>,
+        base: Arc<BaseEngine>,
+        collection: Arc<Collection>,
+        dir_name: &str,
+    ) -> ASResult<Arc<Tantivy>> {
         let now = SystemTime::now();
 
+        let index_dir = base.base_path().join(Path::new(dir_name));
+        if !index_dir.exists() {
+            fs::create_dir_all(&index_dir)?;
+        }
+
+        //the index made by old schema is opened with it, the fields not in it are not indexed
+        let dir = MmapDirectory::open(index_dir.to_str().unwrap())?;
+        let (index, collection) = if Index::exists(&dir) {
+            let index = conver(Index::open(dir))?;
+            let collection = Arc::new(Self::schema_collection(&collection, &index.schema()));
+            (index, collection)
+        } else {
+            let schema = Self::make_schema(&collection, true)?;
+            (conver(Index::create(dir, schema))?, collection)
+        };
+        let field_num = index.schema().fields().count();
+
+        tokenizer::register(&index, &collection)?;
+
+        let index_writer = index
+            .writer_with_num_threads(INDEXER_THREAD, INDEXER_MEMORY_SIZE)
+            .unwrap();
+
+        let index_reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommit)
+            .try_into()
+            .unwrap();
+
+        let (tx, rx) = channel::<Event>();
+
+        db.arc_count.fetch_add(1, SeqCst);
+        let tantivy = Arc::new(Tantivy {
+            base,
+            collection,
+            index_dir,
+            index,
+            index_writer: RwLock::new(index_writer),
+            index_reader,
+            field_num,
+            db,
+            tx: Mutex::new(tx),
+            status: AtomicU32::new(0),
+        });
+
+        Tantivy::start_job(tantivy.clone(), rx);
+
+        info!(
+            "init index:{} by collection:{} partition:{} success , use time:{:?} ",
+            dir_name,
+            tantivy.collection.id,
+            tantivy.partition.id,
+            SystemTime::now().duration_since(now).unwrap().as_millis(),
+        );
+
+        Ok(tantivy)
+    }
+
+    fn make_schema(collection: &Collection, expire: bool) -> ASResult<Schema> {
         let mut schema_builder = Schema::builder();
         schema_builder.add_i64_field(ID, schema::IntOptions::default().set_indexed());
         schema_builder.add_bytes_field(ID_BYTES); //if you want put default filed mut modify validate method - 2 in code
 
-        for i in base.collection.scalar_field_index.iter() {
-            let field = &base.collection.fields[*i as usize];
+        for i in collection.scalar_field_index.iter() {
+            let field = &collection.fields[*i as usize];
 
             let name = field.name();
 
@@ -110,8 +190,14 @@ impl Tantivy {
                 string(_) => {
                     schema_builder.add_text_field(name, schema::STRING);
                 }
-                text(_) => {
-                    schema_builder.add_text_field(name, schema::TEXT);
+                text(f) => {
+                    let indexing = schema::TextFieldIndexing::default()
+                        .set_tokenizer(f.analyzer()?.tokenizer_name().as_str())
+                        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+                    schema_builder.add_text_field(
+                        name,
+                        schema::TextOptions::default().set_indexing_options(indexing),
+                    );
                 }
                 date(_) => {
                     schema_builder
@@ -125,53 +211,118 @@ impl Tantivy {
             }
         }
 
-        let schema = schema_builder.build();
-        let field_num = schema.fields().count();
+        if expire {
+            schema_builder.add_u64_field(EXPIRE_AT, schema::IntOptions::default().set_indexed());
+        }
+
+        Ok(schema_builder.build())
+    }
+
+    //the collection only has the scalar fields in schema
+    fn schema_collection(collection: &Collection, schema: &Schema) -> Collection {
+        let mut collection = collection.clone();
+        let scalar_field_index = collection
+            .scalar_field_index
+            .iter()
+            .filter(|i| schema.get_field(collection.fields[**i].name()).is_some())
+            .cloned()
+            .collect();
+        collection.scalar_field_index = scalar_field_index;
+        collection
+    }
 
-        let index_dir = base.base_path().join(Path::new(INDEX_DIR_NAME));
+    //the index in dir was made by other schema, it must be rebuilt for collection
+    pub fn schema_changed(
+        base: &BaseEngine,
+        collection: &Collection,
+        dir_name: &str,
+    ) -> ASResult<bool> {
+        let index_dir = base.base_path().join(Path::new(dir_name));
         if !index_dir.exists() {
-            fs::create_dir_all(&index_dir)?;
+            return Ok(false);
+        }
+        let dir = MmapDirectory::open(index_dir.to_str().unwrap())?;
+        if !Index::exists(&dir) {
+            return Ok(false);
         }
+        let schema = conver(Index::open(dir))?.schema();
+        let expire = schema.get_field(EXPIRE_AT).is_some();
+        Ok(schema != Self::make_schema(collection, expire)?)
+    }
 
-        let index = conver(Index::open_or_create::<MmapDirectory>(
-            MmapDirectory::open(index_dir.to_str().unwrap())?,
-            schema,
-        ))?;
+    //the source text is not stored in index, so it is read from db by iid
+    fn highlight(
+        &self,
+        highlighter: &highlight::Highlighter,
+        iid: &[u8],
+    ) -> ASResult<HashMap<String, Fragments>> {
+        let doc = match self.db.get_doc_by_id(iid)? {
+            Some(doc) => doc,
+            None => return Ok(HashMap::new()),
+        };
+        let pbdoc: crate::pserverpb::Document =
+            prost::Message::decode(prost::bytes::Bytes::from(doc))?;
+        let source: serde_json::Value = serde_json::from_slice(pbdoc.source.as_slice())?;
+        Ok(highlighter.highlight(&source))
+    }
 
-        let index_writer = index
-            .writer_with_num_threads(INDEXER_THREAD, INDEXER_MEMORY_SIZE)
-            .unwrap();
+    //the expired documents not reaped yet are excluded from the query by the range of expire_at,
+    //the index has not expire_at excludes the iids of them
+    fn make_query(&self, sdr: &QueryRequest) -> ASResult<Box<dyn Query>> {
+        let query = self.match_query(sdr)?;
+        let now = current_millis();
+        let mut subqueries = vec![(Occur::Must, query)];
+        match self.index.schema().get_field(EXPIRE_AT) {
+            Some(expire_at) => subqueries.push((
+                Occur::MustNot,
+                Box::new(RangeQuery::new_u64(expire_at, 1..now + 1)) as Box<dyn Query>,
+            )),
+            None => {
+                for iid in self.db.expired_iids(now)? {
+                    subqueries.push((
+                        Occur::MustNot,
+                        Box::new(TermQuery::new(
+                            Term::from_field_i64(Field::from_field_id(ID_INDEX), iid as i64),
+                            IndexRecordOption::Basic,
+                        )) as Box<dyn Query>,
+                    ));
+                }
+            }
+        }
 
-        let index_reader = index
-            .reader_builder()
-            .reload_policy(ReloadPolicy::OnCommit)
-            .try_into()
-            .unwrap();
+        if subqueries.len() == 1 {
+            return Ok(subqueries.pop().unwrap().1);
+        }
+        Ok(Box::new(BooleanQuery::from(subqueries)))
+    }
 
-        let (tx, rx) = channel::<Event>();
+    //the dsl is used if it is set, and it must match with the query string if both set
+    fn match_query(&self, sdr: &QueryRequest) -> ASResult<Box<dyn Query>> {
+        if sdr.dsl.is_empty() {
+            return self.parse_query(sdr);
+        }
 
-        db.arc_count.fetch_add(1, SeqCst);
-        let tantivy = Arc::new(Tantivy {
-            base,
-            index,
-            index_writer: RwLock::new(index_writer),
-            index_reader,
-            field_num,
-            db,
-            tx: Mutex::new(tx),
-            status: AtomicU32::new(0),
-        });
+        let dsl = dsl::parse(&self.index, sdr.dsl.as_str())?;
+        if sdr.query.is_empty() || sdr.query == "*" {
+            return Ok(dsl);
+        }
 
-        Tantivy::start_job(tantivy.clone(), rx);
+        Ok(Box::new(BooleanQuery::from(vec![
+            (Occur::Must, self.parse_query(sdr)?),
+            (Occur::Must, dsl),
+        ])))
+    }
 
-        info!(
-            "init index by collection:{} partition:{} success , use time:{:?} ",
-            tantivy.collection.id,
-            tantivy.partition.id,
-            SystemTime::now().duration_since(now).unwrap().as_millis(),
+    fn parse_query(&self, sdr: &QueryRequest) -> ASResult<Box<dyn Query>> {
+        let schema = self.index.schema();
+        let query_parser = QueryParser::for_index(
+            &self.index,
+            sdr.def_fields
+                .iter()
+                .map(|s| schema.get_field(s).unwrap())
+                .collect(),
         );
-
-        Ok(tantivy)
+        conver(query_parser.parse_query(sdr.query.as_str()))
     }
 
     pub fn value_field_format(name: &str) -> String {
@@ -195,20 +346,13 @@ impl Tantivy {
     }
 
     pub fn filter(&self, sdr: Arc<QueryRequest>) -> ASResult<(Option<RoaringBitmap>, u64)> {
-        if sdr.query == "*" {
+        if sdr.query == "*" && sdr.dsl.is_empty() && !self.db.has_expired(current_millis())? {
             return Ok((None, self.count()?));
         }
 
         self.check_index()?;
         let searcher = self.index_reader.searcher();
-        let query_parser = QueryParser::for_index(
-            &self.index,
-            sdr.def_fields
-                .iter()
-                .map(|s| self.index.schema().get_field(s).unwrap())
-                .collect(),
-        );
-        let q = conver(query_parser.parse_query(sdr.query.as_str()))?;
+        let q = self.make_query(&sdr)?;
         let result = conver(searcher.search(&q, &bitmap_collector::Bitmap))?;
         let len = result.len();
         Ok((Some(result), len))
@@ -217,14 +361,7 @@ impl Tantivy {
     pub fn agg(&self, sdr: Arc<QueryRequest>) -> ASResult<AggregationResponse> {
         self.check_index()?;
         let searcher = self.index_reader.searcher();
-        let query_parser = QueryParser::for_index(
-            &self.index,
-            sdr.def_fields
-                .iter()
-                .map(|s| self.index.schema().get_field(s).unwrap())
-                .collect(),
-        );
-        let q = conver(query_parser.parse_query(sdr.query.as_str()))?;
+        let q = self.make_query(&sdr)?;
 
         let agg = Aggregator::new(
             &self.collection,
@@ -274,47 +411,68 @@ impl Tantivy {
         self.check_index()?;
         let searcher = self.index_reader.searcher();
         let schema = self.index.schema();
-        let query_parser = QueryParser::for_index(
-            &self.index,
-            sdr.def_fields
-                .iter()
-                .map(|s| schema.get_field(s).unwrap())
-                .collect(),
-        );
         let size = sdr.size as usize;
-        let q = conver(query_parser.parse_query(sdr.query.as_str()))?;
+        let q = self.make_query(&sdr)?;
+
+        let highlighter = match &sdr.highlight {
+            Some(hl) if hl.fields.len() > 0 => Some(highlight::Highlighter::new(
+                &searcher,
+                q.as_ref(),
+                &self.collection,
+                hl,
+            )?),
+            _ => None,
+        };
 
         let sort_len = sdr.sort.len() > 0;
 
-        let mut collectors = MultiCollector::new();
+        //the cursor is the sort values of last hit and a tie breaker
+        let after_len = if sort_len { sdr.sort.len() + 1 } else { 2 };
+        if sdr.search_after.len() > 0 && sdr.search_after.len() != after_len {
+            return result!(
+                Code::ParamError,
+                "search_after has {} values but the sort need {}",
+                sdr.search_after.len(),
+                after_len
+            );
+        }
 
-        let sort_top_docs_handle = if sort_len {
-            let mut field_sorts = sort::FieldSorts::new(sdr.sort.len());
-            for s in &sdr.sort {
-                let schema_field = schema.get_field(&s.name).ok_or_else(|| {
-                    err!(Code::FieldTypeErr, "order by field:{:?} not found", s.name)
-                })?;
+        let mut field_sorts = sort::FieldSorts::new(
+            sdr.sort.len(),
+            Field::from_field_id(ID_BYTES_INDEX),
+            self.base.partition.id,
+            sdr.search_after.clone(),
+        );
 
-                let signed = match schema.get_field_entry(schema_field).field_type() {
-                    FieldType::I64(_) | FieldType::Date(_) => true,
-                    _ => false,
-                };
+        for s in &sdr.sort {
+            let schema_field = schema
+                .get_field(&s.name)
+                .ok_or_else(|| err!(Code::FieldTypeErr, "order by field:{:?} not found", s.name))?;
 
-                field_sorts.push(
-                    Field::from_field_id(schema_field.field_id() + 1),
-                    s.order.eq_ignore_ascii_case("asc"),
-                    signed,
-                );
-            }
-            Some(collectors.add_collector(TopDocs::with_limit(size).custom_score(field_sorts)))
-        } else {
-            None
-        };
+            let signed = match schema.get_field_entry(schema_field).field_type() {
+                FieldType::I64(_) | FieldType::Date(_) => true,
+                _ => false,
+            };
+
+            field_sorts.push(
+                Field::from_field_id(schema_field.field_id() + 1),
+                s.order.eq_ignore_ascii_case("asc"),
+                signed,
+            );
+        }
 
-        let score_top_docs_handle = if !sort_len {
-            Some(collectors.add_collector(TopDocs::with_limit(size)))
+        let mut collectors = MultiCollector::new();
+
+        let (sort_top_docs_handle, score_top_docs_handle) = if sort_len {
+            (
+                Some(collectors.add_collector(TopDocs::with_limit(size).custom_score(field_sorts))),
+                None,
+            )
         } else {
-            None
+            (
+                None,
+                Some(collectors.add_collector(TopDocs::with_limit(size).tweak_score(field_sorts))),
+            )
         };
 
         let count_handle = collectors.add_collector(Count);
@@ -329,42 +487,39 @@ impl Tantivy {
             total: count as u64,
             hits: Vec::with_capacity(size),
             info: None, //if this is none means it is success
+            batch: Vec::new(),
         };
 
-        if let Some(top_docs_handle) = sort_top_docs_handle {
-            let top_docs = top_docs_handle.extract(&mut multi_fruit);
-
-            for (score, doc_address) in top_docs {
-                let bytes_reader = searcher
-                    .segment_reader(doc_address.0)
-                    .fast_fields()
-                    .bytes(Field::from_field_id(ID_BYTES_INDEX))
-                    .unwrap();
-                let doc = bytes_reader.get_bytes(doc_address.1);
-                sdr.hits.push(Hit {
-                    collection_name: self.collection.name.to_string(),
-                    score: 1.0,
-                    doc: doc.to_vec(),
-                    sort: score.fields,
-                });
-            }
-        } else {
-            let top_docs = score_top_docs_handle.unwrap().extract(&mut multi_fruit);
-
-            for (score, doc_address) in top_docs {
-                let bytes_reader = searcher
-                    .segment_reader(doc_address.0)
-                    .fast_fields()
-                    .bytes(Field::from_field_id(ID_BYTES_INDEX))
-                    .unwrap();
-                let doc = bytes_reader.get_bytes(doc_address.1);
-                sdr.hits.push(Hit {
-                    collection_name: self.collection.name.to_string(),
-                    score: score,
-                    doc: doc.to_vec(),
-                    sort: vec![f32_slice(score)[..].to_vec()],
-                });
+        let top_docs = match sort_top_docs_handle {
+            Some(handle) => handle.extract(&mut multi_fruit),
+            None => score_top_docs_handle.unwrap().extract(&mut multi_fruit),
+        };
+
+        for (score, doc_address) in top_docs {
+            if score.skip {
+                continue;
             }
+            let bytes_reader = searcher
+                .segment_reader(doc_address.0)
+                .fast_fields()
+                .bytes(Field::from_field_id(ID_BYTES_INDEX))
+                .unwrap();
+            let doc = bytes_reader.get_bytes(doc_address.1);
+            let highlights = match &highlighter {
+                Some(h) => self.highlight(h, doc)?,
+                None => Default::default(),
+            };
+            sdr.hits.push(Hit {
+                collection_name: self.collection.name.to_string(),
+                score: if sort_len {
+                    1.0
+                } else {
+                    slice_f32(&score.fields[0])
+                },
+                doc: doc.to_vec(),
+                sort: score.fields,
+                highlights: highlights,
+            });
         }
 
         let search_finish = SystemTime::now();
@@ -392,7 +547,7 @@ impl Tantivy {
 
     pub fn start_job(index: Arc<Tantivy>, receiver: Receiver<Event>) {
         std::thread::spawn(move || {
-            let (cid, pid) = (index.base.collection.id, index.base.partition.id);
+            let (cid, pid) = (index.collection.id, index.base.partition.id);
             Tantivy::index_job(index, receiver);
             warn!("collection:{}  partition:{} stop index job ", cid, pid);
         });
@@ -414,6 +569,19 @@ impl Tantivy {
             let (old_iid, iid) = match e.unwrap() {
                 Event::Delete(iid) => (iid, 0),
                 Event::Update(old_iid, iid) => (old_iid, iid),
+                Event::Fill(iid) => match index.db.is_live(iid) {
+                    Ok(true) => (iid, iid),
+                    Ok(false) => continue,
+                    Err(e) => {
+                        error!("check live of doc:{} has err:{:?}", iid, e);
+                        continue;
+                    }
+                },
+                Event::Commit(tx) => {
+                    let _ =
+                        tx.send(conver(index.index_writer.write().unwrap().commit()).map(|_| ()));
+                    continue;
+                }
                 Event::Stop => {
                     warn!("reviced stop event to stod index loop");
                     return;
@@ -451,6 +619,38 @@ impl Tantivy {
         conver(self.tx.lock().unwrap().send(event))
     }
 
+    //commit the index after all the events have been done
+    pub fn wait_commit(&self) -> ASResult<()> {
+        let (tx, rx) = channel();
+        self.write(Event::Commit(tx))?;
+        conver(rx.recv())?
+    }
+
+    pub fn stop(&self) {
+        if let Err(e) = self.write(Event::Stop) {
+            error!("stop index job has err:{:?}", e);
+        }
+    }
+
+    pub fn schema_version(&self) -> u64 {
+        self.collection.version
+    }
+
+    pub fn dir_name(&self) -> String {
+        self.index_dir
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    //remove the files of index, it must be stoped before
+    pub fn remove_dir(&self) {
+        if let Err(e) = fs::remove_dir_all(&self.index_dir) {
+            error!("remove index dir:{:?} has err:{:?}", self.index_dir, e);
+        }
+    }
+
     fn _delete(&self, iid: u32) -> ASResult<()> {
         self.check_index()?;
         let ops = self
@@ -475,6 +675,9 @@ impl Tantivy {
             Field::from_field_id(ID_BYTES_INDEX),
             iid_coding(iid).to_vec(),
         );
+        if let Some(expire_at) = self.index.schema().get_field(EXPIRE_AT) {
+            doc.add_u64(expire_at, pbdoc.expire_at);
+        }
 
         let source: serde_json::Value = serde_json::from_slice(pbdoc.source.as_slice())?;
 
@@ -612,7 +815,12 @@ impl Tantivy {
             flag = true;
         }
         let writer = self.index_writer.write().unwrap();
-        if old_iid > 0 {
+        //the document may has been indexed by fill of rebuild
+        writer.delete_term(Term::from_field_i64(
+            Field::from_field_id(ID_INDEX),
+            iid as i64,
+        ));
+        if old_iid > 0 && old_iid != iid {
             writer.delete_term(Term::from_field_i64(
                 Field::from_field_id(ID_INDEX),
                 old_iid as i64,
@@ -633,6 +841,12 @@ impl Tantivy {
     }
 }
 
+impl Drop for Tantivy {
+    fn drop(&mut self) {
+        self.db.arc_count.fetch_sub(1, SeqCst);
+    }
+}
+
 impl Engine for Tantivy {
     fn flush(&self) -> ASResult<()> {
         if self.status.fetch_add(1, SeqCst) > 10 {
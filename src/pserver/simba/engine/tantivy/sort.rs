@@ -1,16 +1,19 @@
-use crate::util::coding::sort_coding;
+use crate::util::coding::{f32_slice, sort_coding, u32_slice};
 use std::cmp::Ordering;
+use std::sync::Arc;
 use tantivy::{
-    collector::{CustomScorer, CustomSegmentScorer},
+    collector::{CustomScorer, CustomSegmentScorer, ScoreSegmentTweaker, ScoreTweaker},
     fastfield::BytesFastFieldReader,
     schema::Field,
-    DocId, SegmentReader,
+    DocId, Score, SegmentReader,
 };
 
 #[derive(Clone)]
 pub struct FieldScore {
     pub fields: Vec<Vec<u8>>,
     asc: Vec<bool>,
+    //the doc is not after the search_after cursor, it will be dropped
+    pub skip: bool,
 }
 
 impl FieldScore {
@@ -18,6 +21,7 @@ impl FieldScore {
         FieldScore {
             fields: Vec::with_capacity(cap),
             asc: Vec::with_capacity(cap),
+            skip: false,
         }
     }
 
@@ -26,13 +30,20 @@ impl FieldScore {
         self.asc.push(asc);
     }
 
-    pub fn cmp_by_order(s: &Vec<Vec<u8>>, o: &Vec<Vec<u8>>, asc: &Vec<bool>) -> Ordering {
-        let len = asc.len();
+    // Less means s ranks before o. the values beyond asc are tie breakers
+    // (partition_id + iid) and always compared in ascending order
+    pub fn cmp_by_order(s: &[Vec<u8>], o: &[Vec<u8>], asc: &[bool]) -> Ordering {
+        let len = std::cmp::max(s.len(), o.len());
         for i in 0..len {
-            let asc = asc[i];
-            let order = Self::cmp(&s[i], &o[i]);
+            let order = match (s.get(i), o.get(i)) {
+                (Some(s), Some(o)) if i < asc.len() => Self::cmp(s, o),
+                (Some(s), Some(o)) => s.cmp(o),
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => Ordering::Equal,
+            };
             if order != Ordering::Equal {
-                if asc {
+                if asc.get(i).cloned().unwrap_or(true) {
                     return order;
                 } else {
                     return order.reverse();
@@ -44,7 +55,7 @@ impl FieldScore {
 
     fn cmp(s: &[u8], o: &[u8]) -> Ordering {
         //default num compare
-        if s[0] != crate::util::coding::sort_coding::STR {
+        if s.len() == 0 || o.len() == 0 || s[0] != crate::util::coding::sort_coding::STR {
             return s.cmp(o);
         }
 
@@ -72,20 +83,32 @@ impl FieldScore {
     }
 }
 
+//the tie breaker of hits is partition_id + iid, it makes the order of hits total
+pub fn tie_coding(partition_id: &[u8], iid: &[u8]) -> Vec<u8> {
+    let mut tie = Vec::with_capacity(8);
+    tie.extend_from_slice(partition_id);
+    tie.extend_from_slice(iid);
+    tie
+}
+
+//the bytes keep the order of scores, the negative ones too
+pub fn score_coding(score: f32) -> Vec<u8> {
+    let bits = score.to_bits();
+    let bits = if bits & (1 << 31) != 0 {
+        !bits
+    } else {
+        bits | (1 << 31)
+    };
+    bits.to_be_bytes().to_vec()
+}
+
+//TopDocs keeps the greatest ones, so the one ranks first is the greater
 impl PartialOrd for FieldScore {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        for (i, v) in other.fields.iter().enumerate() {
-            let asc = self.asc[i];
-            let order = FieldScore::cmp(&self.fields[i], v);
-            if order != Ordering::Equal {
-                if asc {
-                    return Some(order);
-                } else {
-                    return Some(order.reverse());
-                }
-            }
+        if self.skip != other.skip {
+            return Some(other.skip.cmp(&self.skip));
         }
-        return Some(Ordering::Equal);
+        Some(FieldScore::cmp_by_order(&self.fields, &other.fields, &self.asc).reverse())
     }
 }
 
@@ -103,25 +126,31 @@ struct FieldSort {
     signed: bool,
 }
 
-pub struct FieldSorts(Vec<FieldSort>);
+pub struct FieldSorts {
+    sorts: Vec<FieldSort>,
+    id_field: Field,
+    partition_id: u32,
+    after: Arc<Vec<Vec<u8>>>,
+}
 
 impl FieldSorts {
-    pub fn new(cap: usize) -> FieldSorts {
-        FieldSorts(Vec::with_capacity(cap))
+    pub fn new(cap: usize, id_field: Field, partition_id: u32, after: Vec<Vec<u8>>) -> FieldSorts {
+        FieldSorts {
+            sorts: Vec::with_capacity(cap),
+            id_field,
+            partition_id,
+            after: Arc::new(after),
+        }
     }
 
     pub fn push(&mut self, field: Field, asc: bool, signed: bool) {
-        self.0.push(FieldSort { field, asc, signed });
+        self.sorts.push(FieldSort { field, asc, signed });
     }
-}
 
-impl CustomScorer<FieldScore> for FieldSorts {
-    type Child = FieldFastReader;
-
-    fn segment_scorer(&self, segment_reader: &SegmentReader) -> tantivy::Result<Self::Child> {
-        let mut ffr = FieldFastReader::with_capacity(self.0.len());
+    fn fast_reader(&self, segment_reader: &SegmentReader) -> tantivy::Result<FieldFastReader> {
+        let mut readers = Vec::with_capacity(self.sorts.len());
 
-        for fs in self.0.iter() {
+        for fs in self.sorts.iter() {
             let reader = segment_reader
                 .fast_fields()
                 .bytes(fs.field)
@@ -132,14 +161,45 @@ impl CustomScorer<FieldScore> for FieldSorts {
                     ))
                 })?;
 
-            ffr.push(FieldReader {
+            readers.push(FieldReader {
                 reader,
                 asc: fs.asc,
                 signed: fs.signed,
             });
         }
 
-        Ok(ffr)
+        let id_reader = segment_reader
+            .fast_fields()
+            .bytes(self.id_field)
+            .ok_or_else(|| {
+                tantivy::TantivyError::SchemaError(format!(
+                    "Field requested ({:?}) is not a bytes fast field.",
+                    self.id_field
+                ))
+            })?;
+
+        Ok(FieldFastReader {
+            readers,
+            id_reader,
+            partition_id: u32_slice(self.partition_id),
+            after: self.after.clone(),
+        })
+    }
+}
+
+impl CustomScorer<FieldScore> for FieldSorts {
+    type Child = FieldFastReader;
+
+    fn segment_scorer(&self, segment_reader: &SegmentReader) -> tantivy::Result<Self::Child> {
+        self.fast_reader(segment_reader)
+    }
+}
+
+impl ScoreTweaker<FieldScore> for FieldSorts {
+    type Child = FieldFastReader;
+
+    fn segment_tweaker(&self, segment_reader: &SegmentReader) -> tantivy::Result<Self::Child> {
+        self.fast_reader(segment_reader)
     }
 }
 
@@ -149,19 +209,58 @@ pub struct FieldReader {
     signed: bool,
 }
 
-//the second param is signed , for datetime or i64 sort
-type FieldFastReader = Vec<FieldReader>;
+//the signed of reader is for datetime or i64 sort
+pub struct FieldFastReader {
+    readers: Vec<FieldReader>,
+    id_reader: BytesFastFieldReader,
+    partition_id: [u8; 4],
+    after: Arc<Vec<Vec<u8>>>,
+}
 
-impl CustomSegmentScorer<FieldScore> for FieldFastReader {
-    fn score(&self, doc: DocId) -> FieldScore {
-        let mut fs = FieldScore::new(self.len());
-        for fr in self.iter() {
+impl FieldFastReader {
+    //if there is no sort field, the hits are ordered by score desc
+    fn field_score(&self, doc: DocId, score: Score) -> FieldScore {
+        let mut fs = FieldScore::new(self.readers.len() + 2);
+        if self.readers.len() == 0 {
+            fs.push(f32_slice(score).to_vec(), false);
+        }
+        for fr in self.readers.iter() {
             let mut v = fr.reader.get_bytes(doc).to_vec();
             if fr.signed && v.len() > 0 {
                 v[0] = v[0] ^ 128;
             }
             fs.push(v, fr.asc);
         }
+
+        fs.fields.push(tie_coding(
+            &self.partition_id,
+            self.id_reader.get_bytes(doc),
+        ));
+
+        if self.after.len() > 0 {
+            fs.skip =
+                FieldScore::cmp_by_order(&fs.fields, &self.after, &fs.asc) != Ordering::Greater;
+        }
         fs
     }
 }
+
+impl CustomSegmentScorer<FieldScore> for FieldFastReader {
+    fn score(&self, doc: DocId) -> FieldScore {
+        self.field_score(doc, 0.0)
+    }
+}
+
+impl ScoreSegmentTweaker<FieldScore> for FieldFastReader {
+    fn score(&self, doc: DocId, score: Score) -> FieldScore {
+        self.field_score(doc, score)
+    }
+}
+
+#[test]
+fn score_coding_test() {
+    let scores = vec![-10.5, -1.0, -0.0, 0.0, 0.3, 1.0, 20.0];
+    for w in scores.windows(2) {
+        assert!(score_coding(w[0]) <= score_coding(w[1]));
+    }
+}
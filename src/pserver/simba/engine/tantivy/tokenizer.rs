@@ -0,0 +1,235 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use crate::util::{
+    entity::{Analyzer, Collection, Field},
+    error::*,
+};
+use tantivy::{
+    tokenizer::{
+        BoxTokenStream, Language, LowerCaser, NgramTokenizer, RemoveLongFilter, SimpleTokenizer,
+        Stemmer, TextAnalyzer, Token, TokenStream, Tokenizer,
+    },
+    Index,
+};
+
+const MAX_TOKEN_LEN: usize = 40;
+
+//register the analyzers of text fields to index, the query parser finds them by the field
+pub fn register(index: &Index, collection: &Collection) -> ASResult<()> {
+    for i in collection.scalar_field_index.iter() {
+        if let Field::text(f) = &collection.fields[*i] {
+            let analyzer = f.analyzer()?;
+            let name = analyzer.tokenizer_name();
+            if index.tokenizers().get(name.as_str()).is_none() {
+                index
+                    .tokenizers()
+                    .register(name.as_str(), text_analyzer(&analyzer));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn text_analyzer(analyzer: &Analyzer) -> TextAnalyzer {
+    match analyzer {
+        Analyzer::Default => TextAnalyzer::from(SimpleTokenizer)
+            .filter(RemoveLongFilter::limit(MAX_TOKEN_LEN))
+            .filter(LowerCaser),
+        Analyzer::Raw => TextAnalyzer::from(tantivy::tokenizer::RawTokenizer),
+        Analyzer::Whitespace => TextAnalyzer::from(WhitespaceTokenizer)
+            .filter(RemoveLongFilter::limit(MAX_TOKEN_LEN))
+            .filter(LowerCaser),
+        Analyzer::Ngram(min, max) => {
+            TextAnalyzer::from(NgramTokenizer::new(*min, *max, false)).filter(LowerCaser)
+        }
+        Analyzer::CjkBigram => TextAnalyzer::from(CjkBigramTokenizer)
+            .filter(RemoveLongFilter::limit(MAX_TOKEN_LEN))
+            .filter(LowerCaser),
+        Analyzer::Stem(lang) => TextAnalyzer::from(SimpleTokenizer)
+            .filter(RemoveLongFilter::limit(MAX_TOKEN_LEN))
+            .filter(LowerCaser)
+            .filter(Stemmer::new(language(lang.as_str()))),
+    }
+}
+
+//the lang is validated by Analyzer::parse
+fn language(lang: &str) -> Language {
+    match lang {
+        "da" => Language::Danish,
+        "de" => Language::German,
+        "es" => Language::Spanish,
+        "fi" => Language::Finnish,
+        "fr" => Language::French,
+        "hu" => Language::Hungarian,
+        "it" => Language::Italian,
+        "nl" => Language::Dutch,
+        "pt" => Language::Portuguese,
+        "ro" => Language::Romanian,
+        "ru" => Language::Russian,
+        "sv" => Language::Swedish,
+        "tr" => Language::Turkish,
+        _ => Language::English,
+    }
+}
+
+//the tokens are made at once, it is for the tokenizers not need to be lazy
+struct VecTokenStream {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl VecTokenStream {
+    fn new(tokens: Vec<Token>) -> VecTokenStream {
+        VecTokenStream {
+            tokens: tokens,
+            index: 0,
+        }
+    }
+}
+
+impl TokenStream for VecTokenStream {
+    fn advance(&mut self) -> bool {
+        if self.index < self.tokens.len() {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.index - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.index - 1]
+    }
+}
+
+fn make_token(text: &str, from: usize, to: usize, position: usize) -> Token {
+    Token {
+        offset_from: from,
+        offset_to: to,
+        position: position,
+        text: text[from..to].to_string(),
+        ..Token::default()
+    }
+}
+
+//split by whitespace
+#[derive(Clone)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        for (i, c) in text.char_indices() {
+            match (c.is_whitespace(), start) {
+                (true, Some(from)) => {
+                    tokens.push(make_token(text, from, i, tokens.len()));
+                    start = None;
+                }
+                (false, None) => start = Some(i),
+                _ => {}
+            }
+        }
+        if let Some(from) = start {
+            tokens.push(make_token(text, from, text.len(), tokens.len()));
+        }
+        BoxTokenStream::from(VecTokenStream::new(tokens))
+    }
+}
+
+//the continuous cjk chars are split to overlapping bigrams, a single one is a token by itself.
+//other alphanumeric chars are split as words
+#[derive(Clone)]
+pub struct CjkBigramTokenizer;
+
+impl Tokenizer for CjkBigramTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let mut tokens = Vec::new();
+        //the (offset, char) of current cjk run
+        let mut cjk: Vec<(usize, char)> = Vec::new();
+        let mut word = None;
+
+        for (i, c) in text.char_indices() {
+            if is_cjk(c) {
+                if let Some(from) = word.take() {
+                    tokens.push(make_token(text, from, i, tokens.len()));
+                }
+                cjk.push((i, c));
+                continue;
+            }
+
+            flush_cjk(text, &mut cjk, &mut tokens);
+
+            if c.is_alphanumeric() {
+                if word.is_none() {
+                    word = Some(i);
+                }
+            } else if let Some(from) = word.take() {
+                tokens.push(make_token(text, from, i, tokens.len()));
+            }
+        }
+
+        flush_cjk(text, &mut cjk, &mut tokens);
+        if let Some(from) = word {
+            tokens.push(make_token(text, from, text.len(), tokens.len()));
+        }
+
+        BoxTokenStream::from(VecTokenStream::new(tokens))
+    }
+}
+
+fn flush_cjk(text: &str, cjk: &mut Vec<(usize, char)>, tokens: &mut Vec<Token>) {
+    match cjk.len() {
+        0 => return,
+        1 => {
+            let (from, c) = cjk[0];
+            tokens.push(make_token(text, from, from + c.len_utf8(), tokens.len()));
+        }
+        _ => {
+            for w in cjk.windows(2) {
+                let (from, _) = w[0];
+                let (last, c) = w[1];
+                tokens.push(make_token(text, from, last + c.len_utf8(), tokens.len()));
+            }
+        }
+    }
+    cjk.clear();
+}
+
+fn is_cjk(c: char) -> bool {
+    match c as u32 {
+        0x4E00..=0x9FFF       // CJK Unified Ideographs
+        | 0x3400..=0x4DBF     // CJK Unified Ideographs Extension A
+        | 0x20000..=0x2CEAF   // CJK Unified Ideographs Extension B-E
+        | 0xF900..=0xFAFF     // CJK Compatibility Ideographs
+        | 0x3040..=0x30FF     // Hiragana and Katakana
+        | 0xAC00..=0xD7AF     // Hangul Syllables
+        => true,
+        _ => false,
+    }
+}
+
+#[test]
+fn cjk_bigram_tokenizer() {
+    let mut stream = CjkBigramTokenizer.token_stream("中国银行 Bank of 中");
+    let mut texts = Vec::new();
+    while stream.advance() {
+        texts.push(stream.token().text.clone());
+    }
+    assert_eq!(vec!["中国", "国银", "银行", "Bank", "of", "中"], texts);
+}
@@ -0,0 +1,138 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use crate::pserver::simba::engine::tantivy::sort::{score_coding, tie_coding, FieldScore};
+use crate::pserverpb::*;
+use crate::util::{coding::u32_slice, error::*};
+use crate::*;
+use std::collections::HashMap;
+
+const DEFAULT_RRF_K: u32 = 60;
+
+// Fusion mixes the hits of text query and vector query in one partition. the fused
+// score is set to the sort of hit as relevance, so the partitions can be merged by it
+pub struct Fusion {
+    mode: HybridMode,
+    text_weight: f32,
+    vector_weight: f32,
+    rrf_k: f32,
+}
+
+impl Fusion {
+    pub fn new(hq: &HybridQuery) -> ASResult<Fusion> {
+        let mode = match HybridMode::from_i32(hq.mode) {
+            Some(m) => m,
+            None => return result!(Code::ParamError, "hybrid mode:{} not support", hq.mode),
+        };
+
+        if hq.text_weight < 0.0 || hq.vector_weight < 0.0 {
+            return result!(
+                Code::ParamError,
+                "hybrid weight can not be negative, text:{} vector:{}",
+                hq.text_weight,
+                hq.vector_weight
+            );
+        }
+
+        let (text_weight, vector_weight) = if hq.text_weight == 0.0 && hq.vector_weight == 0.0 {
+            (1.0, 1.0)
+        } else {
+            (hq.text_weight, hq.vector_weight)
+        };
+
+        Ok(Fusion {
+            mode,
+            text_weight,
+            vector_weight,
+            rrf_k: if hq.rrf_k == 0 {
+                DEFAULT_RRF_K
+            } else {
+                hq.rrf_k
+            } as f32,
+        })
+    }
+
+    // the hits are ordered by rank and the doc of them is iid. the score of text hit
+    // is bm25, the vector one is similarity. it returns the top size hits and the number
+    // of candidates
+    pub fn fuse(
+        &self,
+        text: Vec<Hit>,
+        vector: Vec<Hit>,
+        partition_id: u32,
+        size: usize,
+    ) -> (Vec<Hit>, u64) {
+        let mut candidates: HashMap<Vec<u8>, (f32, Hit)> = HashMap::new();
+
+        for (rank, hit) in text.into_iter().enumerate() {
+            let score = match self.mode {
+                HybridMode::Linear => self.text_weight * hit.score / (1.0 + hit.score),
+                HybridMode::Rrf => self.text_weight / (self.rrf_k + rank as f32 + 1.0),
+            };
+            candidates.entry(hit.doc.clone()).or_insert((0.0, hit)).0 += score;
+        }
+
+        for (rank, hit) in vector.into_iter().enumerate() {
+            let score = match self.mode {
+                HybridMode::Linear => self.vector_weight * hit.score,
+                HybridMode::Rrf => self.vector_weight / (self.rrf_k + rank as f32 + 1.0),
+            };
+            candidates.entry(hit.doc.clone()).or_insert((0.0, hit)).0 += score;
+        }
+
+        let total = candidates.len() as u64;
+        let partition_id = u32_slice(partition_id);
+
+        let mut hits: Vec<Hit> = candidates
+            .into_iter()
+            .map(|(_, (score, mut hit))| {
+                hit.score = score;
+                hit.sort = vec![score_coding(score), tie_coding(&partition_id, &hit.doc)];
+                hit
+            })
+            .collect();
+
+        hits.sort_by(|a, b| FieldScore::cmp_by_order(&a.sort, &b.sort, &[false]));
+        hits.truncate(size);
+
+        (hits, total)
+    }
+}
+
+#[test]
+fn fuse_rrf() {
+    let hit = |iid: u32, score: f32| Hit {
+        collection_name: String::default(),
+        score: score,
+        doc: u32_slice(iid).to_vec(),
+        sort: vec![],
+        highlights: HashMap::new(),
+    };
+
+    let fusion = Fusion::new(&HybridQuery {
+        mode: HybridMode::Rrf as i32,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let (hits, total) = fusion.fuse(
+        vec![hit(1, 9.0), hit(2, 5.0)],
+        vec![hit(2, 0.9), hit(3, 0.8)],
+        0,
+        2,
+    );
+
+    assert_eq!(3, total);
+    assert_eq!(u32_slice(2).to_vec(), hits[0].doc);
+    assert_eq!(u32_slice(1).to_vec(), hits[1].doc);
+}
@@ -11,9 +11,10 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
 // implied. See the License for the specific language governing
 // permissions and limitations under the License.
-use crate::util::error::*;
-use crate::*;
-use std::sync::Mutex;
+use async_std::sync::{Mutex, MutexGuard};
+
+// the locks of slots, a write holds the lock of its slot until it is applied by raft.
+// so the read-modify-write of documents in one slot are serial
 pub struct Latch {
     locks: Vec<Mutex<usize>>,
     size: usize,
@@ -30,14 +31,28 @@ impl Latch {
             size: size,
         }
     }
-    pub fn latch_lock<'a>(&'a self, slot: u32) -> &'a Mutex<usize> {
-        &(self.locks[slot as usize % self.size])
+
+    pub async fn lock(&self, slot: u32) -> MutexGuard<'_, usize> {
+        self.locks[slot as usize % self.size].lock().await
+    }
+
+    //the locks are taken in order of index, so two batches not dead lock
+    pub async fn lock_all(&self, slots: impl Iterator<Item = u32>) -> Vec<MutexGuard<'_, usize>> {
+        let mut indexs: Vec<usize> = slots.map(|s| s as usize % self.size).collect();
+        indexs.sort();
+        indexs.dedup();
+
+        let mut guards = Vec::with_capacity(indexs.len());
+        for i in indexs {
+            guards.push(self.locks[i].lock().await);
+        }
+        guards
     }
 
-    pub fn latch<T>(&self, slot: u32, f: impl FnOnce() -> ASResult<T>) -> ASResult<T> {
-        match self.locks[slot as usize % self.size].lock() {
-            Ok(_) => f(),
-            Err(e) => result_def!("get latch lock has err:{}", e),
+    //wait the writes which hold locks now, the locks are released at once
+    pub async fn wait_all(&self) {
+        for lock in self.locks.iter() {
+            let _ = lock.lock().await;
         }
     }
 }
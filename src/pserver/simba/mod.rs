@@ -13,5 +13,8 @@
 // permissions and limitations under the License.
 pub mod aggregation;
 pub mod engine;
+pub mod hybrid;
 pub mod latch;
+pub mod operator;
+pub mod projection;
 pub mod simba;
@@ -0,0 +1,263 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use crate::util::error::*;
+use crate::*;
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+
+const SET: &'static str = "$set";
+const SET_IF_MISSING: &'static str = "$set_if_missing";
+const INC: &'static str = "$inc";
+const APPEND: &'static str = "$append";
+const REMOVE: &'static str = "$remove";
+const UNSET: &'static str = "$unset";
+
+// the update body is operators if its keys start with '$', example:
+// {"$inc": {"count": 1}, "$append": {"tags": ["a", "b"]}, "$unset": ["info.old"]}
+pub fn is_operators(body: &Value) -> bool {
+    match body {
+        Value::Object(map) => map.keys().any(|k| k.starts_with('$')),
+        _ => false,
+    }
+}
+
+// apply the operators to source, the path is split by '.' for the nested objects.
+// a path can only be in one operator, so the order of operators not matters.
+// the source is not changed if any operator fails
+pub fn apply(source: &mut Value, body: Value) -> ASResult<()> {
+    let mut target = match source {
+        Value::Object(map) => map.clone(),
+        v => return result!(Code::FieldValueErr, "the source:{} is not object", v),
+    };
+
+    let ops = match body {
+        Value::Object(map) => map,
+        v => return result!(Code::ParamError, "the operators:{} is not object", v),
+    };
+
+    let mut paths = HashSet::new();
+
+    for (op, args) in ops {
+        let args: Vec<(Value, Value)> = match (op.as_str(), args) {
+            (UNSET, Value::Array(arr)) => arr.into_iter().map(|p| (p, Value::Null)).collect(),
+            (UNSET, v) => return result!(Code::ParamError, "{} need array of paths:{}", op, v),
+            (_, Value::Object(map)) => map
+                .into_iter()
+                .map(|(p, v)| (Value::String(p), v))
+                .collect(),
+            (_, v) => return result!(Code::ParamError, "{} need object of paths:{}", op, v),
+        };
+
+        for (path, arg) in args {
+            let path = match path {
+                Value::String(p) => p,
+                p => return result!(Code::ParamError, "{} path:{} is not string", op, p),
+            };
+            if !paths.insert(path.clone()) {
+                return result!(
+                    Code::ParamError,
+                    "path:{} is in more than one operator",
+                    path
+                );
+            }
+
+            let names: Vec<&str> = path.split('.').collect();
+            let (last, parents) = names.split_last().unwrap();
+            let parent = match parent(&mut target, parents, op != UNSET)? {
+                Some(p) => p,
+                None => continue,
+            };
+
+            let old = parent.remove(*last);
+            let new = match op.as_str() {
+                SET => Some(arg),
+                SET_IF_MISSING => match old {
+                    None | Some(Value::Null) => Some(arg),
+                    old => old,
+                },
+                INC => Some(inc(&path, old, arg)?),
+                APPEND => Some(append(&path, old, arg)?),
+                REMOVE => remove(&path, old, arg)?,
+                UNSET => None,
+                _ => return result!(Code::ParamError, "update operator:{} not support", op),
+            };
+
+            if let Some(v) = new {
+                parent.insert(last.to_string(), v);
+            }
+        }
+    }
+
+    *source = Value::Object(target);
+    Ok(())
+}
+
+//the object of names, the missing ones are made if create. none if not found
+fn parent<'a>(
+    map: &'a mut Map<String, Value>,
+    names: &[&str],
+    create: bool,
+) -> ASResult<Option<&'a mut Map<String, Value>>> {
+    if names.len() == 0 {
+        return Ok(Some(map));
+    }
+    match map.get(names[0]) {
+        None | Some(Value::Null) if !create => return Ok(None),
+        None | Some(Value::Null) => {
+            map.insert(names[0].to_string(), Value::Object(Map::new()));
+        }
+        Some(Value::Object(_)) => {}
+        Some(v) => {
+            return result!(
+                Code::FieldValueErr,
+                "field:{} value:{} is not object",
+                names[0],
+                v
+            )
+        }
+    }
+    match map.get_mut(names[0]) {
+        Some(Value::Object(sub)) => parent(sub, &names[1..], create),
+        _ => Ok(None),
+    }
+}
+
+//the missing field is 0, the integers keep integer if not overflow
+fn inc(path: &str, old: Option<Value>, by: Value) -> ASResult<Value> {
+    let old = match old {
+        None | Some(Value::Null) => Value::from(0),
+        Some(v) => v,
+    };
+
+    if let (Some(a), Some(b)) = (old.as_i64(), by.as_i64()) {
+        return match a.checked_add(b) {
+            Some(v) => Ok(Value::from(v)),
+            None => result!(Code::FieldValueErr, "{} field:{} overflow", INC, path),
+        };
+    }
+
+    match (old.as_f64(), by.as_f64()) {
+        (Some(a), Some(b)) => match Number::from_f64(a + b) {
+            Some(v) => Ok(Value::Number(v)),
+            None => result!(Code::FieldValueErr, "{} field:{} overflow", INC, path),
+        },
+        _ => result!(
+            Code::FieldValueErr,
+            "{} field:{} value:{} by:{} is not number",
+            INC,
+            path,
+            old,
+            by
+        ),
+    }
+}
+
+//the elements of array arg are appended one by one
+fn append(path: &str, old: Option<Value>, arg: Value) -> ASResult<Value> {
+    let mut arr = match old {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(arr)) => arr,
+        Some(v) => {
+            return result!(
+                Code::FieldValueErr,
+                "{} field:{} value:{} is not array",
+                APPEND,
+                path,
+                v
+            )
+        }
+    };
+    match arg {
+        Value::Array(items) => arr.extend(items),
+        v => arr.push(v),
+    }
+    Ok(Value::Array(arr))
+}
+
+//remove all elements equal to arg, or any of arg if it is array
+fn remove(path: &str, old: Option<Value>, arg: Value) -> ASResult<Option<Value>> {
+    let arr = match old {
+        None => return Ok(None),
+        Some(Value::Null) => return Ok(Some(Value::Null)),
+        Some(Value::Array(arr)) => arr,
+        Some(v) => {
+            return result!(
+                Code::FieldValueErr,
+                "{} field:{} value:{} is not array",
+                REMOVE,
+                path,
+                v
+            )
+        }
+    };
+    let items = match arg {
+        Value::Array(items) => items,
+        v => vec![v],
+    };
+    Ok(Some(Value::Array(
+        arr.into_iter().filter(|v| !items.contains(v)).collect(),
+    )))
+}
+
+#[test]
+fn update_operators() {
+    use serde_json::json;
+
+    let mut source = json!({
+        "count": 1,
+        "score": 1.5,
+        "tags": ["a", "b", "a"],
+        "info": {"city": "bj", "old": 1},
+    });
+
+    assert!(!is_operators(&source));
+    let body = json!({
+        "$inc": {"count": -3, "score": 1, "info.visit": 2},
+        "$append": {"tags": ["c", "d"], "list": "x"},
+        "$remove": {"none": "a"},
+        "$set": {"info.geo.lat": 1},
+        "$set_if_missing": {"info.city": "sh", "name": "n"},
+        "$unset": ["info.old", "none.x"],
+    });
+    assert!(is_operators(&body));
+    apply(&mut source, body).unwrap();
+    assert_eq!(
+        json!({
+            "count": -2,
+            "score": 2.5,
+            "tags": ["a", "b", "a", "c", "d"],
+            "list": ["x"],
+            "name": "n",
+            "info": {"city": "bj", "visit": 2, "geo": {"lat": 1}},
+        }),
+        source
+    );
+
+    apply(&mut source, json!({"$remove": {"tags": "a"}})).unwrap();
+    assert_eq!(json!(["b", "c", "d"]), source["tags"]);
+
+    assert!(apply(&mut source, json!({"$inc": {"name": 1}})).is_err());
+    assert_eq!(json!("n"), source["name"]);
+    assert!(apply(&mut source, json!({"$append": {"count": 1}})).is_err());
+    assert!(apply(&mut source, json!({"$set": {"count.x": 1}})).is_err());
+    assert!(apply(&mut source, json!({"$push": {"tags": 1}})).is_err());
+    assert!(apply(
+        &mut source,
+        json!({"$inc": {"count": 1}, "$set": {"count": 1}})
+    )
+    .is_err());
+
+    let mut source = json!({"count": i64::max_value()});
+    assert!(apply(&mut source, json!({"$inc": {"count": 1}})).is_err());
+}
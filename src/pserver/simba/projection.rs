@@ -0,0 +1,102 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+use serde_json::{Map, Value};
+
+// keep the paths of includes and then remove the paths of excludes from source. the
+// path is split by '.' for the nested objects, and a path keeps or removes all under it.
+// empty includes keeps all, the source which is not object is not changed
+pub fn project(source: Value, includes: &[String], excludes: &[String]) -> Value {
+    let mut source = match source {
+        Value::Object(map) => map,
+        v => return v,
+    };
+
+    if includes.len() > 0 {
+        let mut result = Map::new();
+        for path in includes {
+            let path: Vec<&str> = path.split('.').collect();
+            if let Some(v) = take(&mut source, &path) {
+                put(&mut result, &path, v);
+            }
+        }
+        source = result;
+    }
+
+    for path in excludes {
+        let path: Vec<&str> = path.split('.').collect();
+        take(&mut source, &path);
+    }
+
+    Value::Object(source)
+}
+
+//remove the value of path from map
+fn take(map: &mut Map<String, Value>, path: &[&str]) -> Option<Value> {
+    if path.len() == 1 {
+        return map.remove(path[0]);
+    }
+    match map.get_mut(path[0]) {
+        Some(Value::Object(sub)) => take(sub, &path[1..]),
+        _ => None,
+    }
+}
+
+//put the value to path of map, the objects on path are made if not exist
+fn put(map: &mut Map<String, Value>, path: &[&str], value: Value) {
+    if path.len() == 1 {
+        map.insert(path[0].to_string(), value);
+        return;
+    }
+    let sub = map
+        .entry(path[0])
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Value::Object(sub) = sub {
+        put(sub, &path[1..], value);
+    }
+}
+
+#[test]
+fn project_paths() {
+    let source = serde_json::json!({
+        "name": "a",
+        "age": 1,
+        "info": {"city": "bj", "tags": ["x"], "geo": {"lat": 1, "lon": 2}},
+    });
+    let paths = |ps: &[&str]| ps.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+
+    assert_eq!(source, project(source.clone(), &[], &[]));
+
+    assert_eq!(
+        serde_json::json!({"name": "a", "info": {"city": "bj", "geo": {"lat": 1}}}),
+        project(
+            source.clone(),
+            &paths(&["name", "info.city", "info.geo.lat", "none.x"]),
+            &[]
+        )
+    );
+
+    assert_eq!(
+        serde_json::json!({"name": "a", "info": {"tags": ["x"]}}),
+        project(
+            source.clone(),
+            &paths(&["name", "info"]),
+            &paths(&["info.city", "info.geo", "age"])
+        )
+    );
+
+    assert_eq!(
+        serde_json::json!(0),
+        project(serde_json::json!(0), &paths(&["name"]), &[])
+    );
+}
@@ -12,47 +12,71 @@
 // implied. See the License for the specific language governing
 // permissions and limitations under the License.
 use crate::pserver::raft::*;
-#[cfg(vector)]
+#[cfg(feature = "vector")]
 use crate::pserver::simba::engine::faiss::Faiss;
-#[cfg(not(vecotr))]
-use crate::pserver::simba::engine::faiss_empty::Faiss;
+#[cfg(not(feature = "vector"))]
+use crate::pserver::simba::engine::knn::Faiss;
 use crate::pserver::simba::engine::{
     engine::{BaseEngine, Engine},
     rocksdb::RocksDB,
     tantivy::Event as TantivyEvent,
-    tantivy::Tantivy,
+    tantivy::{Tantivy, INDEX_DIR_NAME},
 };
+use crate::pserver::simba::hybrid::Fusion;
 use crate::pserver::simba::latch::Latch;
+use crate::pserver::simba::{operator, projection};
 use crate::pserverpb::*;
 use crate::sleep;
 use crate::util::{
-    coding::{doc_key, field_coding, iid_coding, key_coding, slice_slice},
+    coding::{
+        doc_key, expire_coding, field_coding, hash_str, iid_coding, key_coding, key_prefix_coding,
+        slice_slice, slice_u32, slice_u64, version_coding, version_expire_coding,
+        version_value_coding, version_value_decoding, FENCE_KEY,
+    },
     config,
     entity::*,
     error::*,
     time::current_millis,
 };
 use crate::*;
+use async_std::sync::{channel, Receiver, Sender};
 use log::{debug, error, info, warn};
 use prost::Message;
 use raft4rs::{error::RaftError, raft::Raft};
 use roaring::RoaringBitmap;
-use rocksdb::WriteBatch;
+use rocksdb::{Direction, IteratorMode, WriteBatch};
 use serde_json::Value;
+use std::collections::HashSet;
 use std::sync::{
     atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering::SeqCst},
-    Arc, RwLock,
+    Arc, Mutex, RwLock,
 };
+//commit the building index every this number of documents when filling
+const FILL_COMMIT_SIZE: u64 = 100000;
+//the expired documents are deleted by raft in batches of this size
+const REAP_BATCH_SIZE: usize = 1000;
+//the number of raft entries a subscriber can fall behind, it is closed if more
+const SUBSCRIBE_BUFFER: usize = 1000;
+
 pub struct Simba {
     pub base: Arc<BaseEngine>,
     latch: Latch,
     raft_index: AtomicU64,
     max_iid: AtomicU32,
     del_map: RwLock<RoaringBitmap>,
+    //the document which slot >= fence can not be written or read, it has been moved by split
+    write_fence: AtomicU64,
+    read_fence: AtomicU64,
     //engins
     rocksdb: Arc<RocksDB>,
-    tantivy: Arc<Tantivy>,
+    tantivy: RwLock<Arc<Tantivy>>,
+    //the index in rebuilding, the writes are sent to it too, it replaces tantivy when done
+    building: RwLock<Option<Arc<Tantivy>>>,
+    rebuilding: AtomicBool,
+    progress: IndexProgress,
     faiss: Faiss,
+    //the changes of applied writes are sent to them
+    subscribers: Mutex<Vec<Sender<Arc<Vec<Change>>>>>,
 }
 
 impl Simba {
@@ -61,30 +85,62 @@ impl Simba {
         collection: Arc<Collection>,
         partition: Arc<Partition>,
     ) -> ASResult<Arc<Simba>> {
-        let base = Arc::new(BaseEngine {
-            conf: conf.clone(),
-            collection: collection.clone(),
-            partition: partition.clone(),
-            stoped: AtomicBool::new(false),
-        });
+        let base = Arc::new(BaseEngine::new(
+            conf.clone(),
+            collection.clone(),
+            partition.clone(),
+        ));
 
         let rocksdb = Arc::new(RocksDB::new(base.clone())?);
-        let tantivy = Tantivy::new(rocksdb.clone(), base.clone())?;
+
+        //the index made by old schema is used until the new one is filled and swapped in
+        let index_dir = rocksdb
+            .read_index_dir()?
+            .unwrap_or(String::from(INDEX_DIR_NAME));
+        let refill = Tantivy::schema_changed(&base, &collection, index_dir.as_str())?;
+        remove_other_index(&base, index_dir.as_str());
+
+        let tantivy = Tantivy::new(
+            rocksdb.clone(),
+            base.clone(),
+            collection.clone(),
+            index_dir.as_str(),
+        )?;
         let faiss = Faiss::new(rocksdb.clone(), base.clone())?;
 
         let raft_index = rocksdb.read_raft_index()?;
         let max_iid = rocksdb.find_max_iid();
+        let slot_end = slot_end(&collection, partition.id);
+        //the fence of split not done is kept, the slots not in partition are fenced too
+        let (write_fence, read_fence) = match rocksdb.read_fence()? {
+            Some((w, r)) => (std::cmp::min(w, slot_end), std::cmp::min(r, slot_end)),
+            None => (slot_end, slot_end),
+        };
         let simba = Arc::new(Simba {
             base: base,
             latch: Latch::new(50000),
             raft_index: AtomicU64::new(raft_index),
             max_iid: AtomicU32::new(max_iid),
             del_map: RwLock::new(RoaringBitmap::new()),
+            write_fence: AtomicU64::new(write_fence),
+            read_fence: AtomicU64::new(read_fence),
             rocksdb: rocksdb,
-            tantivy: tantivy,
+            tantivy: RwLock::new(tantivy),
+            building: RwLock::new(None),
+            rebuilding: AtomicBool::new(false),
+            progress: IndexProgress::default(),
             faiss: faiss,
+            subscribers: Mutex::new(Vec::new()),
         });
 
+        if refill {
+            warn!(
+                "the schema of index:{} in partition:{} has been changed, to rebuild it",
+                index_dir, partition.id
+            );
+            simba.rebuild_index(false)?;
+        }
+
         let simba_flush = simba.clone();
 
         std::thread::spawn(move || {
@@ -107,8 +163,168 @@ impl Simba {
 
         Ok(simba)
     }
-    pub fn get(&self, id: &str, sort_key: &str) -> ASResult<Vec<u8>> {
-        Ok(self.get_by_key(key_coding(id, sort_key).as_ref())?.1)
+    pub fn get(
+        &self,
+        id: &str,
+        sort_key: &str,
+        filter: Option<&SourceFilter>,
+    ) -> ASResult<Vec<u8>> {
+        let slot = hash_str(id) as u32;
+        if slot as u64 >= self.read_fence.load(SeqCst) {
+            return result!(
+                Code::SlotMoved,
+                "the slot:{} of id:{} has been moved out of partition:{}",
+                slot,
+                id,
+                self.base.partition.id
+            );
+        }
+        let (iid, doc) = self.get_by_key(key_coding(id, sort_key).as_ref())?;
+        if is_expired(&Document::decode(&doc[..])?, current_millis()) {
+            return result!(Code::RocksDBNotFound, "the document:{} has expired", id);
+        }
+        self.project(&iid, doc, filter)
+    }
+
+    //apply the source filter to document, the vectors are read by iid from their keys
+    fn project(
+        &self,
+        iid: &[u8],
+        doc: Vec<u8>,
+        filter: Option<&SourceFilter>,
+    ) -> ASResult<Vec<u8>> {
+        let filter = match filter {
+            Some(f) if f.vectors || f.includes.len() > 0 || f.excludes.len() > 0 => f,
+            _ => return Ok(doc),
+        };
+
+        let mut pbdoc: Document = Message::decode(prost::bytes::Bytes::from(doc))?;
+        let mut source: Value = serde_json::from_slice(pbdoc.source.as_slice())?;
+
+        if filter.vectors {
+            let collection = self.base.collection();
+            let iid = slice_u32(iid);
+            if let Some(obj) = source.as_object_mut() {
+                for i in collection.vector_field_index.iter() {
+                    let name = collection.fields[*i].name();
+                    if let Some(v) = self.rocksdb.db.get(field_coding(name, iid))? {
+                        let vector: &[f32] = slice_slice(&v);
+                        obj.insert(name.to_string(), serde_json::json!(vector));
+                    }
+                }
+            }
+        }
+
+        let source = projection::project(source, &filter.includes, &filter.excludes);
+        pbdoc.source = serde_json::to_vec(&source)?;
+
+        let mut buf = Vec::new();
+        if let Err(error) = pbdoc.encode(&mut buf) {
+            return Err(error.into());
+        }
+        Ok(buf)
+    }
+
+    //scan documents under id by sort_key, return the documents and token for next
+    pub fn scan(&self, req: &ScanRequest) -> ASResult<(Vec<Vec<u8>>, String)> {
+        let slot = hash_str(req.id.as_str()) as u32;
+        if slot as u64 >= self.read_fence.load(SeqCst) {
+            return result!(
+                Code::SlotMoved,
+                "the slot:{} of id:{} has been moved out of partition:{}",
+                slot,
+                req.id,
+                self.base.partition.id
+            );
+        }
+
+        let (start, end, sk_prefix, token) = (
+            req.start.as_bytes(),
+            req.end.as_bytes(),
+            req.prefix.as_bytes(),
+            req.token.as_bytes(),
+        );
+        let limit = if req.limit == 0 {
+            100
+        } else {
+            req.limit as usize
+        };
+
+        let prefix = key_prefix_coding(req.id.as_str(), 0);
+        let mut seek = prefix.clone();
+        let direction = if req.reverse {
+            if token.len() > 0 {
+                seek.extend_from_slice(token);
+            } else if end.len() > 0 {
+                seek.extend_from_slice(end);
+            } else {
+                seek.extend_from_slice(sk_prefix);
+                seek.push(0xFF);
+            }
+            Direction::Reverse
+        } else {
+            if token.len() > 0 {
+                seek.extend_from_slice(token);
+            } else {
+                seek.extend_from_slice(std::cmp::max(start, sk_prefix));
+            }
+            Direction::Forward
+        };
+
+        let mut docs = Vec::new();
+        let mut next = String::new();
+        let now = current_millis();
+
+        let iter = self
+            .rocksdb
+            .db
+            .iterator(IteratorMode::From(&seek, direction));
+
+        for (k, iid) in iter {
+            if !k.starts_with(&prefix[..]) {
+                break;
+            }
+            let sk = &k[prefix.len()..];
+
+            //token is exclusive
+            if token.len() > 0 && ((req.reverse && sk >= token) || (!req.reverse && sk <= token)) {
+                continue;
+            }
+
+            if end.len() > 0 && sk >= end {
+                if req.reverse {
+                    continue;
+                }
+                break;
+            }
+
+            if sk < start {
+                if req.reverse {
+                    break;
+                }
+                continue;
+            }
+
+            if !sk.starts_with(sk_prefix) {
+                if (sk < sk_prefix) == req.reverse {
+                    break;
+                }
+                continue;
+            }
+
+            match self.rocksdb.get_doc_by_id(&iid)? {
+                Some(doc) if is_expired(&Document::decode(&doc[..])?, now) => continue,
+                Some(doc) => docs.push(doc),
+                None => error!("not found doc by id :{:?}", &iid),
+            }
+
+            if docs.len() >= limit {
+                next = String::from_utf8_lossy(sk).to_string();
+                break;
+            }
+        }
+
+        Ok((docs, next))
     }
 
     fn get_by_key(&self, key: &Vec<u8>) -> ASResult<(Vec<u8>, Vec<u8>)> {
@@ -128,7 +344,7 @@ impl Simba {
             code: Code::Success as i32,
             estimate_count: self.rocksdb.estimate_count()?,
             db_count: self.rocksdb.count()?,
-            index_count: self.tantivy.count()?,
+            index_count: self.tantivy().count()?,
             vectors_count: Vec::new(),
             message: String::default(),
         };
@@ -144,13 +360,22 @@ impl Simba {
     }
 
     pub fn search(&self, sdreq: Arc<QueryRequest>) -> SearchDocumentResponse {
-        let mut resp = if sdreq.vector_query.is_none() {
-            match self.tantivy.query(sdreq) {
+        if sdreq.vector_query.is_some() && sdreq.search_after.len() > 0 {
+            return err!(Code::ParamError, "search_after not support vector query").into();
+        }
+
+        let mut resp = if let Some(hybrid) = sdreq.hybrid.as_ref() {
+            match self.hybrid_search(&sdreq, hybrid) {
+                Ok(r) => r,
+                Err(e) => e.into(),
+            }
+        } else if sdreq.vector_query.is_none() {
+            match self.tantivy().query(sdreq) {
                 Ok(r) => r,
                 Err(e) => e.into(),
             }
         } else {
-            let (bitmap, total) = match self.tantivy.filter(sdreq.clone()) {
+            let (bitmap, total) = match self.tantivy().filter(sdreq.clone()) {
                 Ok(b) => b,
                 Err(e) => return e.into(),
             };
@@ -160,149 +385,395 @@ impl Simba {
             }
         };
 
-        for hit in resp.hits.iter_mut() {
-            match self.rocksdb.get_doc_by_id(&hit.doc) {
-                Ok(v) => match v {
-                    Some(v) => hit.doc = v,
-                    None => error!("not found doc by id :{:?}", &hit.doc),
-                },
-                Err(e) => return e.into(),
+        let filter = sdreq.source_filter.as_ref();
+        if let Err(e) = self.fill_docs(&mut resp.hits, filter) {
+            return e.into();
+        }
+        for list in resp.batch.iter_mut() {
+            if let Err(e) = self.fill_docs(&mut list.hits, filter) {
+                return e.into();
             }
         }
 
         return resp;
     }
 
+    //replace the iid of hits by the document
+    fn fill_docs(&self, hits: &mut Vec<Hit>, filter: Option<&SourceFilter>) -> ASResult<()> {
+        for hit in hits.iter_mut() {
+            match self.rocksdb.get_doc_by_id(&hit.doc)? {
+                Some(v) => hit.doc = self.project(&hit.doc, v, filter)?,
+                None => error!("not found doc by id :{:?}", &hit.doc),
+            }
+        }
+
+        //the moved documents may be not cleaned and the expired ones may be not reaped, skip them
+        let fence = self.read_fence.load(SeqCst);
+        let now = current_millis();
+        hits.retain(|hit| match Document::decode(&hit.doc[..]) {
+            Ok(doc) => (doc.slot as u64) < fence && !is_expired(&doc, now),
+            Err(_) => true,
+        });
+        Ok(())
+    }
+
+    //the text query and vector query both recall a window of candidates, and they are fused by score
+    fn hybrid_search(
+        &self,
+        sdreq: &QueryRequest,
+        hybrid: &HybridQuery,
+    ) -> ASResult<SearchDocumentResponse> {
+        let fusion = Fusion::new(hybrid)?;
+
+        let vq = match sdreq.vector_query.as_ref() {
+            Some(vq) => vq,
+            None => return result!(Code::ParamError, "hybrid search need vector_query"),
+        };
+
+        if sdreq.sort.len() > 0 {
+            return result!(Code::ParamError, "hybrid search not support sort");
+        }
+
+        let field = match self
+            .base
+            .collection()
+            .fields
+            .iter()
+            .find(|f| f.name() == vq.field)
+        {
+            Some(f) => f.vector()?,
+            None => return result!(Code::ParamError, "not found vector field:{}", vq.field),
+        };
+
+        if vq.vector.len() != field.dimension as usize {
+            return result!(
+                Code::ParamError,
+                "hybrid search need one query vector, the len:{} of vector not match the dimension:{}",
+                vq.vector.len(),
+                field.dimension
+            );
+        }
+
+        let mut req = sdreq.clone();
+        if hybrid.window > 0 {
+            req.size = hybrid.window;
+        }
+        let req = Arc::new(req);
+
+        //the vector recall is filtered by the query as the vector search
+        let tantivy = self.tantivy();
+        let text = tantivy.query(req.clone())?;
+        let (bitmap, total) = tantivy.filter(req.clone())?;
+        let vector = self.faiss.search(req, bitmap, total)?;
+
+        let (hits, total) = fusion.fuse(
+            text.hits,
+            vector.hits,
+            self.base.partition.id,
+            sdreq.size as usize,
+        );
+
+        Ok(SearchDocumentResponse {
+            code: Code::Success as i32,
+            total: total,
+            hits: hits,
+            info: None,
+            batch: Vec::new(),
+        })
+    }
+
     pub fn agg(&self, ar: Arc<QueryRequest>) -> AggregationResponse {
-        match self.tantivy.agg(ar) {
+        match self.tantivy().agg(ar) {
             Ok(r) => r,
             Err(e) => e.into(),
         }
     }
 
-    pub async fn write(&self, req: WriteDocumentRequest, raft: Arc<Raft>) -> ASResult<()> {
-        let (doc, write_type) = (req.doc.unwrap(), WriteType::from_i32(req.write_type));
-        match write_type {
-            Some(WriteType::Put) => self._put(doc, raft).await,
-            Some(WriteType::Create) => self._create(doc, raft).await,
-            Some(WriteType::Update) => self._update(doc, raft).await,
-            Some(WriteType::Upsert) => self._upsert(doc, raft).await,
-            Some(WriteType::Delete) => self._delete(doc, raft).await,
-            Some(_) | None => {
-                return result_def!("can not do the handler:{:?}", write_type);
+    //it returns the version of document after write, the latch is held until raft applied it
+    pub async fn write(&self, req: WriteDocumentRequest, raft: Arc<Raft>) -> ASResult<i64> {
+        let _guard = self
+            .latch
+            .lock(req.doc.as_ref().map(|d| d.slot).unwrap_or(0))
+            .await;
+        let (event, version) = self.make_event(req)?;
+        self.raft_write(event, raft).await?;
+        Ok(version)
+    }
+
+    // batch_write checks every document by itself, the passed ones are submitted
+    // to raft as one entry. the result is in the same order of reqs
+    pub async fn batch_write(
+        &self,
+        reqs: Vec<WriteDocumentRequest>,
+        raft: Arc<Raft>,
+    ) -> Vec<ASResult<i64>> {
+        let mut results = Vec::with_capacity(reqs.len());
+        let mut events = Vec::new();
+        let mut pending = Vec::new();
+        let mut keys = HashSet::new();
+
+        let _guards = self
+            .latch
+            .lock_all(reqs.iter().filter_map(|r| r.doc.as_ref()).map(|d| d.slot))
+            .await;
+
+        for req in reqs {
+            //the check of a document must see the one before it, so submit them first
+            if let Some(doc) = req.doc.as_ref() {
+                if !keys.insert(doc_key(doc)) {
+                    self.batch_submit(&mut events, &mut pending, &mut results, raft.clone())
+                        .await;
+                    keys.clear();
+                    keys.insert(doc_key(doc));
+                }
+            }
+            match self.make_event(req) {
+                Ok((event, version)) => {
+                    events.push(event);
+                    pending.push(results.len());
+                    results.push(Ok(version));
+                }
+                Err(e) => results.push(Err(e)),
             }
         }
-    }
 
-    async fn _create(&self, mut doc: Document, raft: Arc<Raft>) -> ASResult<()> {
-        let key = doc_key(&doc);
-        doc.version = 1;
-        let buf1 = self.doc_encoding(&mut doc)?;
+        self.batch_submit(&mut events, &mut pending, &mut results, raft)
+            .await;
 
-        let _lock = self.latch.latch_lock(doc.slot);
+        results
+    }
 
-        if let Err(e) = self.get_by_key(&key) {
-            if e.code() != Code::RocksDBNotFound {
-                return Err(e);
-            }
-        } else {
-            return result_def!("the document:{:?} already exists", key);
+    async fn batch_submit(
+        &self,
+        events: &mut Vec<Event>,
+        pending: &mut Vec<usize>,
+        results: &mut Vec<ASResult<i64>>,
+        raft: Arc<Raft>,
+    ) {
+        if events.len() == 0 {
+            return;
         }
 
-        self.raft_write(Event::Create(key, buf1), raft).await
+        let events = std::mem::replace(events, Vec::new());
+        if let Err(e) = self.raft_write(Event::Batch(events), raft).await {
+            for i in pending.iter() {
+                results[*i] = Err(ASError::Error(e.code(), e.message()));
+            }
+        }
+        pending.clear();
     }
 
-    async fn _update(&self, mut doc: Document, raft: Arc<Raft>) -> ASResult<()> {
-        let (old_version, key) = (doc.version, doc_key(&doc));
+    // transaction checks all the writes under their latches, and submits them to raft as
+    // one entry only if all of them passed, so it is all or nothing. a document can only be
+    // written once in it. it returns the versions in the same order of reqs
+    pub async fn transaction(
+        &self,
+        reqs: Vec<WriteDocumentRequest>,
+        raft: Arc<Raft>,
+    ) -> ASResult<Vec<i64>> {
+        if reqs.len() == 0 {
+            return result!(Code::ParamError, "transaction not has write");
+        }
 
-        let _lock = self.latch.latch_lock(doc.slot);
+        let _guards = self
+            .latch
+            .lock_all(reqs.iter().filter_map(|r| r.doc.as_ref()).map(|d| d.slot))
+            .await;
+
+        let mut keys = HashSet::new();
+        let mut events = Vec::with_capacity(reqs.len());
+        let mut versions = Vec::with_capacity(reqs.len());
+
+        for (i, req) in reqs.into_iter().enumerate() {
+            if let Some(doc) = req.doc.as_ref() {
+                if !keys.insert(doc_key(doc)) {
+                    return result!(
+                        Code::ParamError,
+                        "the document:{} is written more than once in transaction",
+                        doc.id
+                    );
+                }
+            }
+            match self.make_event(req) {
+                Ok((event, version)) => {
+                    events.push(event);
+                    versions.push(version);
+                }
+                Err(e) => {
+                    return result!(
+                        e.code(),
+                        "the write:{} of transaction failed:{}",
+                        i,
+                        e.message()
+                    )
+                }
+            }
+        }
 
-        let (old_iid, old) =
-            self.get_by_key(&key_coding(doc.id.as_str(), doc.sort_key.as_str()))?;
-        let old: Document = Message::decode(prost::bytes::Bytes::from(old))?;
-        if old_version > 0 && old.version != old_version {
+        self.raft_write(Event::Batch(events), raft).await?;
+        Ok(versions)
+    }
+
+    // the version of document is ignored, if_version checks the current one before write.
+    // put with if_absent is the same as create. ttl is taken before the expire_at of document
+    fn make_event(&self, req: WriteDocumentRequest) -> ASResult<(Event, i64)> {
+        let write_type = WriteType::from_i32(req.write_type);
+        let mut doc = match req.doc {
+            Some(doc) => doc,
+            None => return result!(Code::ParamError, "write request not has document"),
+        };
+        if doc.slot as u64 >= self.write_fence.load(SeqCst) {
             return result!(
-                Code::VersionErr,
-                "the document:{} version not right expected:{} found:{}",
+                Code::SlotMoved,
+                "the slot:{} of id:{} has been moved out of partition:{}",
+                doc.slot,
                 doc.id,
-                old_version,
-                old.version
+                self.base.partition.id
             );
         }
+        if req.if_absent && write_type != Some(WriteType::Put) {
+            return result!(Code::ParamError, "if_absent only support put");
+        }
+        if req.if_version < 0 || (req.if_version > 0 && write_type == Some(WriteType::Create)) {
+            return result!(
+                Code::ParamError,
+                "if_version:{} not support by {:?}",
+                req.if_version,
+                write_type
+            );
+        }
+        doc.version = 0;
+        if req.ttl > 0 {
+            doc.expire_at = current_millis() + req.ttl * 1000;
+        } else if doc.expire_at > 0 && doc.expire_at <= current_millis() {
+            return result!(Code::ParamError, "expire_at:{} is past", doc.expire_at);
+        }
+        match write_type {
+            Some(WriteType::Put) if req.if_absent => self._create(doc),
+            Some(WriteType::Put) => self._put(doc, req.if_version),
+            Some(WriteType::Create) => self._create(doc),
+            Some(WriteType::Update) => self._update(doc, req.if_version),
+            Some(WriteType::Upsert) => self._upsert(doc, req.if_version),
+            Some(WriteType::Delete) => self._delete(doc, req.if_version),
+            Some(_) | None => {
+                return result_def!("can not do the handler:{:?}", write_type);
+            }
+        }
+    }
 
-        merge_doc(&mut doc, old)?;
-        doc.version += old_version + 1;
+    fn _create(&self, mut doc: Document) -> ASResult<(Event, i64)> {
+        let key = doc_key(&doc);
+        let (iid, old, last) = self.get_old(&key)?;
+        if old.is_some() {
+            return result!(Code::AlreadyExists, "the document:{:?} already exists", key);
+        }
 
+        doc.version = last + 1;
+        self.default_expire(&mut doc);
         let buf1 = self.doc_encoding(&mut doc)?;
-        self.raft_write(Event::Update(old_iid, key, buf1), raft)
-            .await
+
+        Ok((write_event(iid, key, buf1), doc.version))
     }
 
-    async fn _upsert(&self, mut doc: Document, raft: Arc<Raft>) -> ASResult<()> {
+    fn _update(&self, mut doc: Document, if_version: i64) -> ASResult<(Event, i64)> {
         let key = doc_key(&doc);
-        let _lock = self.latch.latch_lock(doc.slot);
-        let old = match self.get_by_key(key.as_ref()) {
-            Ok(o) => Some(o),
-            Err(e) => {
-                if e.code() == Code::RocksDBNotFound {
-                    None
-                } else {
-                    return Err(e);
-                }
-            }
+
+        let (old_iid, old) = match self.get_old(&key)? {
+            (Some(iid), Some(old), _) => (iid, old),
+            _ => return result!(Code::RocksDBNotFound, "the document:{} not found", doc.id),
         };
+        check_version(&doc, if_version, Some(&old))?;
+
+        merge_doc(&mut doc, old)?;
+        doc.version += 1;
+
+        let buf1 = self.doc_encoding(&mut doc)?;
+        Ok((Event::Update(old_iid, key, buf1), doc.version))
+    }
+
+    fn _upsert(&self, mut doc: Document, if_version: i64) -> ASResult<(Event, i64)> {
+        let key = doc_key(&doc);
+        let (iid, old, last) = self.get_old(&key)?;
+        check_version(&doc, if_version, old.as_ref())?;
 
-        let event: Event;
-        if let Some((iid, old)) = old {
-            let old: Document = Message::decode(prost::bytes::Bytes::from(old))?;
+        if let Some(old) = old {
             merge_doc(&mut doc, old)?;
             doc.version += 1;
-            let buf1 = self.doc_encoding(&mut doc)?;
-
-            event = Event::Update(iid, key, buf1);
         } else {
-            doc.version = 1;
-            let buf1 = self.doc_encoding(&mut doc)?;
-            event = Event::Create(key, buf1);
+            //the operators are applied to an empty document
+            let empty = Document {
+                source: b"{}".to_vec(),
+                ..Default::default()
+            };
+            merge_doc(&mut doc, empty)?;
+            doc.version = last + 1;
+            self.default_expire(&mut doc);
         }
-
-        self.raft_write(event, raft).await
+        let buf1 = self.doc_encoding(&mut doc)?;
+        Ok((write_event(iid, key, buf1), doc.version))
     }
 
-    async fn _delete(&self, doc: Document, raft: Arc<Raft>) -> ASResult<()> {
+    //the version of delete is the next one of deleted document
+    fn _delete(&self, doc: Document, if_version: i64) -> ASResult<(Event, i64)> {
         let key = doc_key(&doc);
-        let _lock = self.latch.latch_lock(doc.slot);
-
-        let iid = match self.rocksdb.db.get(&key) {
-            Ok(ov) => match ov {
-                Some(v) => v,
-                None => return result!(Code::RocksDBNotFound, "id:{:?} not found!", key,),
-            },
-            Err(e) => return result_def!("get key has err:{}", e),
+        let (iid, old) = match self.get_old(&key)? {
+            (Some(iid), Some(old), _) => (iid, old),
+            _ => return result!(Code::RocksDBNotFound, "id:{:?} not found!", key,),
         };
+        check_version(&doc, if_version, Some(&old))?;
 
-        self.raft_write(Event::Delete(iid, key), raft).await
+        Ok((Event::Delete(iid, key), old.version + 1))
     }
 
-    async fn _put(&self, mut doc: Document, raft: Arc<Raft>) -> ASResult<()> {
+    fn _put(&self, mut doc: Document, if_version: i64) -> ASResult<(Event, i64)> {
         let key = doc_key(&doc);
-        doc.version = 1;
+        let (iid, old, last) = self.get_old(&key)?;
+        check_version(&doc, if_version, old.as_ref())?;
+
+        doc.version = last + 1;
+        self.default_expire(&mut doc);
         let buf1 = self.doc_encoding(&mut doc)?;
-        let _lock = self.latch.latch_lock(doc.slot);
-        let iid = match self.rocksdb.db.get(&key) {
-            Ok(iid) => iid,
-            Err(_) => None,
-        };
+        Ok((write_event(iid, key, buf1), doc.version))
+    }
+
+    //the iid, document and last version of key. the expired document is taken as not found,
+    //but its iid is returned, so the write replaces it. the last version of a deleted or
+    //expired one is kept, so the versions of key never go back
+    fn get_old(&self, key: &Vec<u8>) -> ASResult<(Option<Vec<u8>>, Option<Document>, i64)> {
+        match self.get_by_key(key) {
+            Ok((iid, old)) => {
+                let old: Document = Message::decode(prost::bytes::Bytes::from(old))?;
+                if is_expired(&old, current_millis()) {
+                    //same as the version of the delete by reaper
+                    Ok((Some(iid), None, old.version + 1))
+                } else {
+                    let version = old.version;
+                    Ok((Some(iid), Some(old), version))
+                }
+            }
+            Err(e) if e.code() == Code::RocksDBNotFound => {
+                let last = match self.rocksdb.db.get(version_coding(key)).map_err(cast)? {
+                    Some(v) => version_value_decoding(&v).0,
+                    None => 0,
+                };
+                Ok((None, None, last))
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-        match iid {
-            Some(id) => self.raft_write(Event::Update(id, key, buf1), raft).await,
-            None => self.raft_write(Event::Create(key, buf1), raft).await,
+    //the document not set expire_at lives the ttl of collection
+    fn default_expire(&self, doc: &mut Document) {
+        let ttl = self.base.collection().ttl;
+        if doc.expire_at == 0 && ttl > 0 {
+            doc.expire_at = current_millis() + ttl * 1000;
         }
     }
 
     pub fn doc_encoding(&self, doc: &mut Document) -> ASResult<Vec<u8>> {
         let mut buf = Vec::new();
-        if self.base.collection.fields.len() == 0 {
+        let collection = self.base.collection();
+        if collection.fields.len() == 0 {
             if let Err(error) = doc.encode(&mut buf) {
                 return Err(error.into());
             }
@@ -311,12 +782,12 @@ impl Simba {
 
         let mut source: Value = serde_json::from_slice(doc.source.as_slice())?;
 
-        for i in self.base.collection.scalar_field_index.iter() {
-            let field = &self.base.collection.fields[*i];
+        for i in collection.scalar_field_index.iter() {
+            let field = &collection.fields[*i];
             field.validate(source.get(field.name()))?;
         }
 
-        if self.base.collection.vector_field_index.len() == 0 {
+        if collection.vector_field_index.len() == 0 {
             if let Err(error) = doc.encode(&mut buf) {
                 return Err(error.into());
             }
@@ -325,10 +796,10 @@ impl Simba {
 
         let map = source.as_object_mut().unwrap();
 
-        let mut vectors = Vec::with_capacity(self.base.collection.vector_field_index.len());
+        let mut vectors = Vec::with_capacity(collection.vector_field_index.len());
 
-        for i in self.base.collection.vector_field_index.iter() {
-            let field = match &self.base.collection.fields[*i] {
+        for i in collection.vector_field_index.iter() {
+            let field = match &collection.fields[*i] {
                 Field::vector(field) => field,
                 _ => panic!(format!("vector field index has not field index:{}", *i)),
             };
@@ -344,7 +815,7 @@ impl Simba {
         }
 
         doc.vectors = vectors;
-        doc.source = serde_json::to_vec(&0)?;
+        doc.source = serde_json::to_vec(&source)?;
 
         if let Err(error) = doc.encode(&mut buf) {
             return Err(error.into());
@@ -362,27 +833,99 @@ impl Simba {
         }
     }
 
+    //the events of a batch are written to rocksdb in one write batch, so all or none of
+    //them are applied. the index is written after it
     pub fn do_write(&self, raft_index: u64, data: &[u8], check: bool) -> ASResult<()> {
+        let mut batch = WriteBatch::default();
+        let mut index_events = Vec::new();
+        let (origin, events) = Event::decode_origin(data);
+        let split = origin == ChangeOrigin::Split as u8;
+        if Event::is_batch(events) {
+            for data in Event::decode_batch(events) {
+                self._do_write(data, check, split, &mut batch, &mut index_events)?;
+            }
+        } else {
+            self._do_write(events, check, split, &mut batch, &mut index_events)?;
+        }
+
+        self.rocksdb.write_batch(batch)?;
+
+        for event in index_events {
+            let old_iid = match event {
+                TantivyEvent::Delete(iid) | TantivyEvent::Update(iid, _) => iid,
+                _ => 0,
+            };
+            self.write_index(event)?;
+            if old_iid > 0 {
+                self.del_map.write().unwrap().insert(old_iid);
+            }
+        }
+
+        self.raft_index.store(raft_index, SeqCst);
+        self.publish(raft_index, data);
+
+        Ok(())
+    }
+
+    //the documents deleted by split have been moved, so their versions are not kept
+    fn _do_write(
+        &self,
+        data: &[u8],
+        check: bool,
+        split: bool,
+        batch: &mut WriteBatch,
+        index_events: &mut Vec<TantivyEvent>,
+    ) -> ASResult<()> {
         let (event, old_iid, key, value) = Event::decode(data);
 
+        if event == EventType::Fence {
+            batch.put(FENCE_KEY, key);
+            self.write_fence.store(slice_u64(&key[..8]), SeqCst);
+            self.read_fence.store(slice_u64(&key[8..16]), SeqCst);
+            return Ok(());
+        }
+
         if event == EventType::Delete {
-            self.rocksdb.delete(key)?;
-            self.tantivy.write(TantivyEvent::Delete(old_iid))?;
-            self.del_map.write().unwrap().insert(old_iid as u32);
-        } else {
-            let general_id = self.general_id();
-            let iid = iid_coding(general_id);
-            let mut batch = WriteBatch::default();
-            batch.put(key, &iid);
-            if self.base.collection.fields.len() == 0 {
-                batch.put_cf(self.rocksdb.id_cf(), iid, value);
-                return self.rocksdb.write_batch(batch);
+            batch.delete(key);
+            if split {
+                index_events.push(TantivyEvent::Delete(old_iid));
+                return Ok(());
+            }
+            //the next one of key continues from the version of deleted, it is kept for
+            //version_retention_sec and removed by reap_versions
+            if let Some(old) = self.rocksdb.get_doc_by_id(&iid_coding(old_iid))? {
+                let old: Document = Message::decode(prost::bytes::Bytes::from(old))?;
+                let now = current_millis();
+                batch.put(
+                    version_coding(key),
+                    version_value_coding(old.version + 1, now),
+                );
+                batch.put(version_expire_coding(now, old.slot, key), b"");
             }
+            index_events.push(TantivyEvent::Delete(old_iid));
+            return Ok(());
+        }
 
-            if self.base.collection.vector_field_index.len() > 0 {
-                let mut pbdoc: Document =
-                    Message::decode(prost::bytes::Bytes::from(value.to_vec()))?;
+        let general_id = self.general_id();
+        let iid = iid_coding(general_id);
+        let mut pbdoc: Document = Message::decode(prost::bytes::Bytes::from(value.to_vec()))?;
+        let collection = self.base.collection();
+
+        //the document is in rocksdb already if check, when the log is replayed
+        if !check
+            || self
+                .rocksdb
+                .get_doc_by_id(&iid_coding(general_id))?
+                .is_none()
+        {
+            batch.put(key, &iid);
+            batch.delete(version_coding(key));
+            //the reaper finds the expired document by it
+            if pbdoc.expire_at > 0 {
+                batch.put(expire_coding(pbdoc.expire_at, general_id), b"");
+            }
 
+            if collection.fields.len() > 0 && collection.vector_field_index.len() > 0 {
                 let vectors = pbdoc.vectors;
                 pbdoc.vectors = Vec::new();
                 for v in vectors {
@@ -398,32 +941,523 @@ impl Simba {
             } else {
                 batch.put_cf(self.rocksdb.id_cf(), iid, value);
             }
+        }
+
+        if collection.fields.len() == 0 {
+            return Ok(());
+        }
+
+        if !check || !self.tantivy().exist(general_id)? {
+            index_events.push(TantivyEvent::Update(old_iid, general_id));
+        }
+
+        return Ok(());
+    }
+
+    pub fn readonly(&self) -> bool {
+        return false; //TODO: FIX ME
+    }
+
+    fn tantivy(&self) -> Arc<Tantivy> {
+        self.tantivy.read().unwrap().clone()
+    }
+
+    //the event is sent to the building index too, the lock makes it not lost when swapping
+    fn write_index(&self, event: TantivyEvent) -> ASResult<()> {
+        let building = self.building.read().unwrap();
+        if let Some(index) = building.as_ref() {
+            index.write(event.clone())?;
+        }
+        self.tantivy().write(event)
+    }
+}
+
+//for schema change and rebuild
+impl Simba {
+    //the collection has new fields, the index is rebuilt by it
+    pub fn update_schema(self: &Arc<Self>, collection: Arc<Collection>) -> ASResult<()> {
+        if collection.version <= self.base.collection().version {
+            return Ok(());
+        }
+
+        info!(
+            "partition:{} update schema to version:{}",
+            self.base.partition.id, collection.version
+        );
+
+        self.base.set_collection(collection);
+
+        match self.rebuild_index(false) {
+            //it will be rebuilt again by the new schema when the running one done
+            Err(e) if e.code() == Code::LockedAlready => Ok(()),
+            result => result,
+        }
+    }
+
+    //rebuild the index from rocksdb in a new dir. the writes are sent to both indexes
+    //while building, and the new one replaces the old when it is done. the vector
+    //indexes are rebuilt after it if vector is true
+    pub fn rebuild_index(self: &Arc<Self>, vector: bool) -> ASResult<()> {
+        if self
+            .rebuilding
+            .compare_exchange(false, true, SeqCst, SeqCst)
+            .is_err()
+        {
+            return result!(
+                Code::LockedAlready,
+                "the index of partition:{} is rebuilding",
+                self.base.partition.id
+            );
+        }
+
+        let building = match Tantivy::new(
+            self.rocksdb.clone(),
+            self.base.clone(),
+            self.base.collection(),
+            format!("{}_{}", INDEX_DIR_NAME, current_millis()).as_str(),
+        ) {
+            Ok(index) => index,
+            Err(e) => {
+                self.rebuilding.store(false, SeqCst);
+                return Err(e);
+            }
+        };
+
+        self.progress.reset();
+        *self.building.write().unwrap() = Some(building.clone());
 
-            if !check
-                || self
-                    .rocksdb
-                    .get_doc_by_id(&iid_coding(general_id))?
-                    .is_none()
+        let simba = self.clone();
+        std::thread::spawn(move || {
+            let pid = simba.base.partition.id;
+            match simba.rocksdb.count() {
+                Ok(c) => simba.progress.total.store(c, SeqCst),
+                Err(e) => warn!("partition:{} count documents has err:{:?}", pid, e),
+            }
+
+            let result = match simba.fill_index(&building) {
+                Ok(_) => {
+                    let old = {
+                        let mut b = simba.building.write().unwrap();
+                        let old = std::mem::replace(
+                            &mut *simba.tantivy.write().unwrap(),
+                            building.clone(),
+                        );
+                        *b = None;
+                        old
+                    };
+                    old.stop();
+                    match simba.rocksdb.write_index_dir(building.dir_name().as_str()) {
+                        Ok(_) => old.remove_dir(),
+                        Err(e) => error!("partition:{} write index dir has err:{:?}", pid, e),
+                    }
+                    info!(
+                        "partition:{} rebuild index:{} done",
+                        pid,
+                        building.dir_name()
+                    );
+                    if vector {
+                        simba.faiss.rebuild(|n| {
+                            simba.progress.vectors.fetch_add(n, SeqCst);
+                        })
+                    } else {
+                        Ok(())
+                    }
+                }
+                Err(e) => {
+                    *simba.building.write().unwrap() = None;
+                    building.stop();
+                    building.remove_dir();
+                    Err(e)
+                }
+            };
+
+            if let Err(e) = result {
+                error!("partition:{} rebuild index has err:{:?}", pid, e);
+                *simba.progress.err.write().unwrap() = Some(e.to_string());
+            }
+            simba.rebuilding.store(false, SeqCst);
+
+            if simba.base.runing()
+                && simba.tantivy().schema_version() != simba.base.collection().version
             {
-                self.rocksdb.write_batch(batch)?;
+                if let Err(e) = simba.rebuild_index(false) {
+                    error!("partition:{} rebuild index again has err:{:?}", pid, e);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    //return the progress of last rebuilding, it is kept after done
+    pub fn reindex_progress(&self) -> ReindexResponse {
+        let (code, message) = match self.progress.err.read().unwrap().as_ref() {
+            Some(e) => (Code::InternalErr, e.clone()),
+            None => (Code::Success, String::from("success")),
+        };
+        ReindexResponse {
+            code: code as i32,
+            message: message,
+            running: self.rebuilding.load(SeqCst),
+            total: self.progress.total.load(SeqCst),
+            indexed: self.progress.indexed.load(SeqCst),
+            vectors: self.progress.vectors.load(SeqCst),
+        }
+    }
+
+    //fill index by all live documents in rocksdb
+    fn fill_index(&self, index: &Arc<Tantivy>) -> ASResult<()> {
+        let mut count = 0;
+        self.rocksdb.scan_docs(0, |iid, _| {
+            index.write(TantivyEvent::Fill(iid))?;
+            count += 1;
+            if count % FILL_COMMIT_SIZE == 0 {
+                index.wait_commit()?;
+            }
+            self.progress.indexed.fetch_add(1, SeqCst);
+            Ok(self.base.runing())
+        })?;
+
+        if !self.base.runing() {
+            return result!(
+                Code::EngineWillClose,
+                "partition:{} is stoped",
+                self.base.partition.id
+            );
+        }
+
+        index.wait_commit()
+    }
+}
+
+//the progress of rebuilding index
+#[derive(Default)]
+struct IndexProgress {
+    total: AtomicU64,
+    indexed: AtomicU64,
+    vectors: AtomicU64,
+    err: RwLock<Option<String>>,
+}
+
+impl IndexProgress {
+    fn reset(&self) {
+        self.total.store(0, SeqCst);
+        self.indexed.store(0, SeqCst);
+        self.vectors.store(0, SeqCst);
+        *self.err.write().unwrap() = None;
+    }
+}
+
+//for split
+impl Simba {
+    //the moved slots can not be written, they are still read before cleanup
+    pub async fn set_write_fence(&self, slot: u64, raft: Arc<Raft>) -> ASResult<()> {
+        self.fence(slot, self.read_fence.load(SeqCst), raft).await
+    }
+
+    pub async fn set_fence(&self, slot: u64, raft: Arc<Raft>) -> ASResult<()> {
+        self.fence(slot, slot, raft).await
+    }
+
+    //reset fence by the range of partition in collection
+    pub async fn reset_fence(&self, raft: Arc<Raft>) -> ASResult<()> {
+        let slot_end = slot_end(&self.base.collection(), self.base.partition.id);
+        self.fence(slot_end, slot_end, raft).await
+    }
+
+    //the fence is written by raft, so the replicas have it when they are leader and it is
+    //kept after restart. the writes which checked the old fence are waited by latch, so
+    //none of them is applied after it returns
+    async fn fence(&self, write_fence: u64, read_fence: u64, raft: Arc<Raft>) -> ASResult<()> {
+        self.raft_write(Event::Fence(write_fence, read_fence), raft)
+            .await?;
+        self.latch.wait_all().await;
+        Ok(())
+    }
+
+    //find documents which slot >= from_slot begin with from_iid, the vectors of them are filled,
+    //return the iid for next time, None means it is over
+    pub fn slot_docs(
+        &self,
+        from_iid: u32,
+        from_slot: u32,
+        limit: usize,
+    ) -> ASResult<(Vec<Document>, Option<u32>)> {
+        let mut docs = Vec::with_capacity(limit);
+        let mut next = None;
+        let collection = self.base.collection();
+        self.rocksdb.scan_docs(from_iid, |iid, mut doc| {
+            if docs.len() >= limit {
+                next = Some(iid);
+                return Ok(false);
             }
+            if doc.slot < from_slot {
+                return Ok(true);
+            }
+            for i in collection.vector_field_index.iter() {
+                let name = collection.fields[*i].name();
+                if let Some(v) = self.rocksdb.db.get(field_coding(name, iid))? {
+                    doc.vectors.push(Vector {
+                        name: name.to_string(),
+                        vector: slice_slice(&v).to_vec(),
+                    });
+                }
+            }
+            docs.push(doc);
+            Ok(true)
+        })?;
+        Ok((docs, next))
+    }
 
-            if !check || !self.tantivy.exist(general_id)? {
-                self.tantivy
-                    .write(TantivyEvent::Update(old_iid, general_id))?;
-                if old_iid > 0 {
-                    self.del_map.write().unwrap().insert(old_iid);
+    // export iterates the documents matched by query on a rocksdb snapshot
+    pub fn export(
+        &self,
+        query: Arc<QueryRequest>,
+        mut f: impl FnMut(Document) -> ASResult<bool>,
+    ) -> ASResult<()> {
+        let (bitmap, _) = self.tantivy().filter(query)?;
+        let fence = self.read_fence.load(SeqCst);
+
+        let collection = self.base.collection();
+        let vector_fields = collection
+            .vector_field_index
+            .iter()
+            .map(|i| collection.fields[*i].name().to_string())
+            .collect();
+
+        self.rocksdb.snapshot_docs(&vector_fields, |iid, doc| {
+            if doc.slot as u64 >= fence {
+                return Ok(true);
+            }
+            if let Some(bitmap) = bitmap.as_ref() {
+                if !bitmap.contains(iid) {
+                    return Ok(true);
                 }
             }
+            f(doc)
+        })
+    }
+
+    //write the documents from other partition, they have been validated so not check again
+    pub async fn import(&self, docs: Vec<Document>, raft: Arc<Raft>) -> ASResult<()> {
+        let mut events = Vec::with_capacity(docs.len());
+        for doc in docs {
+            let key = doc_key(&doc);
+            let mut buf = Vec::new();
+            if let Err(error) = doc.encode(&mut buf) {
+                return Err(error.into());
+            }
+            match self.rocksdb.db.get(&key).map_err(cast)? {
+                Some(iid) => events.push(Event::Update(iid, key, buf)),
+                None => events.push(Event::Create(key, buf)),
+            }
+        }
+        if events.len() == 0 {
+            return Ok(());
         }
+        let event = Event::Origin(ChangeOrigin::Import as u8, Box::new(Event::Batch(events)));
+        self.raft_write(event, raft).await
+    }
 
-        self.raft_index.store(raft_index, SeqCst);
+    //delete all documents which slot >= from_slot
+    pub async fn delete_slot_docs(&self, from_slot: u32, raft: Arc<Raft>) -> ASResult<()> {
+        let mut from_iid = 0;
+        loop {
+            let mut events = Vec::new();
+            let mut next = None;
+            self.rocksdb.scan_docs(from_iid, |iid, doc| {
+                if events.len() >= 1000 {
+                    next = Some(iid);
+                    return Ok(false);
+                }
+                if doc.slot >= from_slot {
+                    events.push(Event::Delete(iid_coding(iid).to_vec(), doc_key(&doc)));
+                }
+                Ok(true)
+            })?;
 
-        return Ok(());
+            if events.len() > 0 {
+                let event =
+                    Event::Origin(ChangeOrigin::Split as u8, Box::new(Event::Batch(events)));
+                self.raft_write(event, raft.clone()).await?;
+            }
+
+            match next {
+                Some(iid) => from_iid = iid,
+                None => return Ok(()),
+            }
+        }
     }
 
-    pub fn readonly(&self) -> bool {
-        return false; //TODO: FIX ME
+    // the expired documents are deleted by raft on leader, and every replica removes the expire
+    // keys of the replaced or deleted documents. it returns the number of deleted documents
+    pub async fn reap_expired(&self, raft: Option<Arc<Raft>>) -> ASResult<usize> {
+        let mut count = 0;
+        loop {
+            let mut stale = WriteBatch::default();
+            let mut expired = Vec::new();
+            self.rocksdb
+                .scan_expired(current_millis(), |expire_key, iid| {
+                    match self.live_key(iid)? {
+                        Some((key, slot)) => {
+                            if raft.is_some() {
+                                expired.push((iid, key, slot));
+                            }
+                        }
+                        None => stale.delete(expire_key),
+                    }
+                    Ok(expired.len() < REAP_BATCH_SIZE)
+                })?;
+            self.rocksdb.write_batch(stale)?;
+
+            let raft = match raft.as_ref() {
+                Some(raft) if expired.len() > 0 => raft,
+                _ => return Ok(count),
+            };
+            let full = expired.len() >= REAP_BATCH_SIZE;
+
+            let _guards = self.latch.lock_all(expired.iter().map(|e| e.2)).await;
+            let mut events = Vec::with_capacity(expired.len());
+            for (iid, key, _) in expired {
+                //it may be written again before the latch
+                if let Some(v) = self.rocksdb.db.get(&key)? {
+                    if v.as_slice() == &iid_coding(iid)[..] {
+                        events.push(Event::Delete(iid_coding(iid).to_vec(), key));
+                    }
+                }
+            }
+
+            count += events.len();
+            if events.len() > 0 {
+                let event =
+                    Event::Origin(ChangeOrigin::Expire as u8, Box::new(Event::Batch(events)));
+                self.raft_write(event, raft.clone()).await?;
+            }
+            if !full {
+                return Ok(count);
+            }
+        }
+    }
+
+    // the versions of deleted documents are removed after retention, the new one of key
+    // restarts from 1 then. every replica removes its own, and the latch of slot makes it
+    // not remove the version of a delete just applied. it returns the number of removed
+    pub async fn reap_versions(&self, retention_ms: u64) -> ASResult<usize> {
+        let before = current_millis().saturating_sub(retention_ms);
+        let mut count = 0;
+        loop {
+            let mut stale = Vec::new();
+            self.rocksdb
+                .scan_versions(before, |expire_key, deleted_at, slot, key| {
+                    stale.push((expire_key.to_vec(), deleted_at, slot, key.to_vec()));
+                    Ok(stale.len() < REAP_BATCH_SIZE)
+                })?;
+            let full = stale.len() >= REAP_BATCH_SIZE;
+
+            let _guards = self.latch.lock_all(stale.iter().map(|s| s.2)).await;
+            let mut batch = WriteBatch::default();
+            for (expire_key, deleted_at, _, key) in stale {
+                batch.delete(expire_key);
+                //it may be written or deleted again after it
+                if let Some(v) = self.rocksdb.db.get(version_coding(&key))? {
+                    if version_value_decoding(&v).1 == deleted_at {
+                        batch.delete(version_coding(&key));
+                        count += 1;
+                    }
+                }
+            }
+            self.rocksdb.write_batch(batch)?;
+
+            if !full {
+                return Ok(count);
+            }
+        }
+    }
+
+    //the key and slot of document if it is not replaced or deleted
+    fn live_key(&self, iid: u32) -> ASResult<Option<(Vec<u8>, u32)>> {
+        let doc: Document = match self.rocksdb.get_doc_by_id(iid_coding(iid))? {
+            Some(v) => Message::decode(prost::bytes::Bytes::from(v))?,
+            None => return Ok(None),
+        };
+        let key = doc_key(&doc);
+        match self.rocksdb.db.get(&key)? {
+            Some(v) if v.as_slice() == &iid_coding(iid)[..] => Ok(Some((key, doc.slot))),
+            _ => Ok(None),
+        }
+    }
+}
+
+//for change data capture
+impl Simba {
+    //the changes applied after it are sent to the receiver, it is closed if the receiver
+    //falls behind too much or the partition stopped
+    pub fn subscribe(&self) -> Receiver<Arc<Vec<Change>>> {
+        let (tx, rx) = channel(SUBSCRIBE_BUFFER);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    //it is called after raft_index stored, so a subscriber misses nothing between reading
+    //the raft log and subscribe
+    fn publish(&self, raft_index: u64, data: &[u8]) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.len() == 0 {
+            return;
+        }
+        let changes = match self.changes(raft_index, data) {
+            Ok(changes) if changes.len() == 0 => return,
+            Ok(changes) => Arc::new(changes),
+            Err(e) => {
+                error!("make changes of raft index:{} has err:{:?}", raft_index, e);
+                subscribers.clear();
+                return;
+            }
+        };
+        subscribers.retain(|tx| tx.try_send(changes.clone()).is_ok());
+    }
+
+    //the changes of a raft entry. the deleted document is read by its iid, it is kept in
+    //rocksdb after deleted, and the version of delete is the next one of it
+    pub fn changes(&self, raft_index: u64, data: &[u8]) -> ASResult<Vec<Change>> {
+        let (origin, data) = Event::decode_origin(data);
+        let datas = if Event::is_batch(data) {
+            Event::decode_batch(data)
+        } else {
+            vec![data]
+        };
+
+        let mut changes = Vec::with_capacity(datas.len());
+        for data in datas {
+            let (event, old_iid, _, value) = Event::decode(data);
+            let (change_type, doc) = match event {
+                EventType::Delete => match self.rocksdb.get_doc_by_id(iid_coding(old_iid))? {
+                    Some(v) => {
+                        let mut doc: Document = Message::decode(prost::bytes::Bytes::from(v))?;
+                        doc.version += 1;
+                        doc.source = Vec::new();
+                        (ChangeType::Delete, doc)
+                    }
+                    None => {
+                        error!("not found deleted doc by iid:{}", old_iid);
+                        continue;
+                    }
+                },
+                EventType::Create => (ChangeType::Create, Document::decode(value)?),
+                EventType::Fence => continue,
+                _ => (ChangeType::Update, Document::decode(value)?),
+            };
+            changes.push(Change {
+                raft_index: raft_index,
+                change_type: change_type as i32,
+                id: doc.id,
+                sort_key: doc.sort_key,
+                version: doc.version,
+                source: doc.source,
+                origin: origin as i32,
+            });
+        }
+        Ok(changes)
     }
 }
 
@@ -444,10 +1478,16 @@ impl Simba {
 
             let index = self.raft_index.load(SeqCst);
 
-            if let Err(e) = self.tantivy.flush() {
+            if let Err(e) = self.tantivy().flush() {
                 error!("flush tantivy has err :{:?}", e);
             };
 
+            if let Some(index) = self.building.read().unwrap().as_ref() {
+                if let Err(e) = index.flush() {
+                    error!("flush building tantivy has err :{:?}", e);
+                };
+            }
+
             if let Err(e) = self.faiss.flush() {
                 error!("flush faiss has err :{:?}", e);
             };
@@ -469,6 +1509,7 @@ impl Simba {
 
     pub fn stop(&self) {
         self.base.stoped.store(true, SeqCst);
+        self.subscribers.lock().unwrap().clear();
     }
 
     pub fn release(&self) {
@@ -476,12 +1517,15 @@ impl Simba {
             panic!("call release mut take stop function before");
         }
         self.rocksdb.release();
-        self.tantivy.release();
+        self.tantivy().release();
+        if let Some(index) = self.building.write().unwrap().take() {
+            index.stop();
+        }
 
         while Arc::strong_count(&self.rocksdb) > self.rocksdb.arc_count.load(SeqCst) as usize {
             info!(
                 "wait release collection:{} partition:{} now is :{}/{}",
-                self.base.collection.id,
+                self.base.partition.collection_id,
                 self.base.partition.id,
                 Arc::strong_count(&self.rocksdb),
                 self.rocksdb.arc_count.load(SeqCst)
@@ -508,6 +1552,41 @@ impl Simba {
     }
 }
 
+//remove the index dirs which are not in use, they are left by rebuild
+fn remove_other_index(base: &BaseEngine, dir_name: &str) {
+    let entries = match std::fs::read_dir(base.base_path()) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("read dir:{:?} has err:{:?}", base.base_path(), e);
+            return;
+        }
+    };
+
+    let prefix = format!("{}_", INDEX_DIR_NAME);
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == dir_name || (name != INDEX_DIR_NAME && !name.starts_with(prefix.as_str())) {
+            continue;
+        }
+        warn!("to remove unused index dir:{:?}", entry.path());
+        if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+            error!("remove index dir:{:?} has err:{:?}", entry.path(), e);
+        }
+    }
+}
+
+//the end of slot range for partition, it is not included
+fn slot_end(collection: &Collection, partition_id: u32) -> u64 {
+    match collection
+        .partitions
+        .iter()
+        .position(|pid| *pid == partition_id)
+    {
+        Some(i) if i + 1 < collection.slots.len() => collection.slots[i + 1] as u64,
+        _ => u32::max_value() as u64 + 1,
+    }
+}
+
 fn merge(a: &mut Value, b: Value) {
     match (a, b) {
         (a @ &mut Value::Object(_), Value::Object(b)) => {
@@ -520,11 +1599,54 @@ fn merge(a: &mut Value, b: Value) {
     }
 }
 
+//the current version must be if_version when it is set
+fn check_version(doc: &Document, if_version: i64, old: Option<&Document>) -> ASResult<()> {
+    if if_version == 0 {
+        return Ok(());
+    }
+    match old {
+        Some(old) if old.version == if_version => Ok(()),
+        Some(old) => result!(
+            Code::VersionErr,
+            "the document:{} version not right expected:{} found:{}",
+            doc.id,
+            if_version,
+            old.version
+        ),
+        None => result!(
+            Code::VersionErr,
+            "the document:{} version not right expected:{} but not found",
+            doc.id,
+            if_version
+        ),
+    }
+}
+
+//the new source is merged to old one, or it is applied as operators
 fn merge_doc(new: &mut Document, old: Document) -> ASResult<()> {
     let new_src: Value = serde_json::from_slice(new.source.as_slice())?;
     let mut old_src: Value = serde_json::from_slice(old.source.as_slice())?;
-    merge(&mut old_src, new_src);
+    if operator::is_operators(&new_src) {
+        operator::apply(&mut old_src, new_src)?;
+    } else {
+        merge(&mut old_src, new_src);
+    }
     new.source = serde_json::to_vec(&old_src)?;
     new.version = old.version;
+    if new.expire_at == 0 {
+        new.expire_at = old.expire_at;
+    }
     Ok(())
 }
+
+//the event replaces the document of old iid if it exists
+fn write_event(old_iid: Option<Vec<u8>>, key: Vec<u8>, value: Vec<u8>) -> Event {
+    match old_iid {
+        Some(iid) => Event::Update(iid, key, value),
+        None => Event::Create(key, value),
+    }
+}
+
+fn is_expired(doc: &Document, now: u64) -> bool {
+    doc.expire_at > 0 && doc.expire_at <= now
+}
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::sync::{mpsc::Sender, Arc};
 
-use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web::{error::ErrorInternalServerError, web, App, HttpRequest, HttpResponse, HttpServer};
+use async_std::{stream::StreamExt, sync::channel};
 use log::{error, info};
 use prost::Message;
 use serde::{Deserialize, Serialize};
@@ -22,7 +24,10 @@ use crate::*;
 // permissions and limitations under the License.
 use crate::pserverpb::*;
 use crate::router::service::RouterService;
-use crate::util::{config, error::*};
+use crate::util::{coding, config, error::*};
+
+//the version of document after write
+const VERSION_HEADER: &'static str = "X-Version";
 
 #[actix_rt::main]
 pub async fn start(tx: Sender<String>, conf: Arc<config::Config>) -> std::io::Result<()> {
@@ -42,11 +47,19 @@ pub async fn start(tx: Sender<String>, conf: Arc<config::Config>) -> std::io::Re
             .data(arc_service.clone())
             .route("/", web::get().to(domain))
             .route("/get/{collection_name}/{id}", web::get().to(get))
+            .route("/scan/{collection_name}/{id}", web::get().to(scan))
+            .route("/export/{collection_name}", web::get().to(export))
+            .route("/changes/{collection_name}", web::get().to(changes))
             .route("/put/{collection_name}/{id}", web::post().to(put))
             .route("/update/{collection_name}/{id}", web::post().to(update))
             .route("/upsert/{collection_name}/{id}", web::post().to(upsert))
             .route("/create/{collection_name}/{id}", web::post().to(create))
             .route("/delete/{collection_name}/{id}", web::delete().to(delete))
+            .route("/_bulk/{collection_name}", web::post().to(bulk))
+            .route(
+                "/_transaction/{collection_name}",
+                web::post().to(transaction),
+            )
             .route("/search/{collection_names}", web::get().to(search_by_get))
             .route("/search/{collection_names}", web::post().to(search_by_post))
             .route("/agg/{collection_names}", web::get().to(agg_by_get))
@@ -73,8 +86,17 @@ async fn domain() -> HttpResponse {
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DocumentQuery {
+    //the old name of if_version
     pub version: Option<i64>,
+    pub if_version: Option<i64>,
+    pub if_absent: Option<bool>,
+    //the seconds to live, or the unix millis to expire
+    pub ttl: Option<u64>,
+    pub expire_at: Option<u64>,
     pub sort_key: Option<String>,
+    pub includes: Option<serde_json::Value>,
+    pub excludes: Option<serde_json::Value>,
+    pub vectors: Option<bool>,
 }
 
 async fn write(
@@ -102,13 +124,18 @@ async fn write(
             collection_name,
             id,
             query.sort_key.unwrap_or(String::default()),
-            query.version.unwrap_or(0),
+            query.if_version.or(query.version).unwrap_or(0),
+            query.if_absent.unwrap_or(false),
+            query.ttl.unwrap_or(0),
+            query.expire_at.unwrap_or(0),
             bytes,
             wt,
         )
         .await
     {
-        Ok(s) => HttpResponse::build(Code::Success.http_code()).json(gr_to_json(s)),
+        Ok(s) => HttpResponse::build(Code::Success.http_code())
+            .header(VERSION_HEADER, s.version.to_string())
+            .json(gr_to_json(s)),
         Err(e) => HttpResponse::build(e.code().http_code())
             .content_type("application/json")
             .body(e.to_json()),
@@ -187,6 +214,170 @@ async fn delete(
     write(rs, req, None, query.into_inner(), WriteType::Delete as i32).await
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct BulkLine {
+    #[serde(rename = "type")]
+    pub write_type: String,
+    pub id: String,
+    pub sort_key: Option<String>,
+    pub version: Option<i64>,
+    pub if_version: Option<i64>,
+    pub if_absent: Option<bool>,
+    pub ttl: Option<u64>,
+    pub expire_at: Option<u64>,
+    pub doc: Option<Value>,
+}
+
+// the body is ndjson, every line is a BulkLine, example:
+// {"type":"put","id":"1","doc":{"name":"zhangsan"}}
+// {"type":"delete","id":"2"}
+async fn bulk(
+    rs: web::Data<Arc<RouterService>>,
+    req: HttpRequest,
+    bytes: web::Bytes,
+) -> HttpResponse {
+    let collection_name: String = req
+        .match_info()
+        .get("collection_name")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    //the line which can not parse has result in here, others wait for bulk result
+    let mut results: Vec<Option<GeneralResponse>> = Vec::new();
+    let mut writes = Vec::new();
+
+    for line in bytes.split(|b| *b == b'\n') {
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            continue;
+        }
+        match parse_bulk_line(line) {
+            Ok(w) => {
+                writes.push(w);
+                results.push(None);
+            }
+            Err(e) => results.push(Some(e.into())),
+        }
+    }
+
+    let mut bulk_results = match rs.bulk(collection_name, writes).await {
+        Ok(v) => v.into_iter(),
+        Err(e) => {
+            return HttpResponse::build(e.code().http_code())
+                .content_type("application/json")
+                .body(e.to_json())
+        }
+    };
+
+    let mut errors = 0;
+    let items = results
+        .into_iter()
+        .map(|r| r.unwrap_or_else(|| bulk_results.next().unwrap()))
+        .map(|gr| {
+            if gr.code != Code::Success as i32 {
+                errors += 1;
+            }
+            gr_to_json(gr)
+        })
+        .collect::<Vec<Value>>();
+
+    HttpResponse::build(Code::Success.http_code()).json(json!({
+        "code": Code::Success as i32,
+        "errors": errors,
+        "items": items,
+    }))
+}
+
+// the body is the same as bulk, but all the lines are applied or none of them. the
+// documents must be in one partition, it returns the error of the failed line
+async fn transaction(
+    rs: web::Data<Arc<RouterService>>,
+    req: HttpRequest,
+    bytes: web::Bytes,
+) -> HttpResponse {
+    let collection_name: String = req
+        .match_info()
+        .get("collection_name")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let mut writes = Vec::new();
+    for line in bytes.split(|b| *b == b'\n') {
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            continue;
+        }
+        match parse_bulk_line(line) {
+            Ok(w) => writes.push(w),
+            Err(e) => {
+                let e = err!(
+                    e.code(),
+                    "the line:{} of transaction failed:{}",
+                    writes.len(),
+                    e.message()
+                );
+                return HttpResponse::build(e.code().http_code())
+                    .content_type("application/json")
+                    .body(e.to_json());
+            }
+        }
+    }
+
+    match rs.transaction(collection_name, writes).await {
+        Ok(v) => HttpResponse::build(Code::Success.http_code()).json(json!({
+            "code": Code::Success as i32,
+            "items": v.into_iter().map(gr_to_json).collect::<Vec<Value>>(),
+        })),
+        Err(e) => HttpResponse::build(e.code().http_code())
+            .content_type("application/json")
+            .body(e.to_json()),
+    }
+}
+
+fn parse_bulk_line(line: &[u8]) -> ASResult<WriteDocumentRequest> {
+    let bl: BulkLine = match serde_json::from_slice(line) {
+        Ok(v) => v,
+        Err(e) => return result!(Code::ParamError, "bulk line parse has err:{:?}", e),
+    };
+
+    let wt = match bl.write_type.as_str() {
+        "put" => WriteType::Put,
+        "create" => WriteType::Create,
+        "update" => WriteType::Update,
+        "upsert" => WriteType::Upsert,
+        "delete" => WriteType::Delete,
+        _ => {
+            return result!(
+                Code::ParamError,
+                "bulk type:{} only support put, create, update, upsert or delete",
+                bl.write_type
+            )
+        }
+    };
+
+    let source = match bl.doc {
+        Some(v) => serde_json::to_vec(&v)?,
+        None if wt == WriteType::Delete => Vec::default(),
+        None => return result!(Code::ParamError, "bulk type:{} must set doc", bl.write_type),
+    };
+
+    Ok(WriteDocumentRequest {
+        collection_id: 0,
+        partition_id: 0,
+        doc: Some(Document {
+            id: bl.id,
+            sort_key: bl.sort_key.unwrap_or(String::default()),
+            source: source,
+            expire_at: bl.expire_at.unwrap_or(0),
+            ..Default::default()
+        }),
+        write_type: wt as i32,
+        if_version: bl.if_version.or(bl.version).unwrap_or(0),
+        if_absent: bl.if_absent.unwrap_or(false),
+        ttl: bl.ttl.unwrap_or(0),
+    })
+}
+
 async fn get(
     rs: web::Data<Arc<RouterService>>,
     req: HttpRequest,
@@ -200,11 +391,22 @@ async fn get(
         .unwrap();
     let id: String = req.match_info().get("id").unwrap().parse().unwrap();
 
+    let query = query.into_inner();
+    let source_filter = match source_filter(query.includes, query.excludes, query.vectors) {
+        Ok(f) => f,
+        Err(e) => {
+            return HttpResponse::build(e.code().http_code())
+                .content_type("application/json")
+                .body(e.to_json())
+        }
+    };
+
     match rs
         .get(
             collection_name,
             id,
-            query.into_inner().sort_key.unwrap_or(String::default()),
+            query.sort_key.unwrap_or(String::default()),
+            source_filter,
         )
         .await
     {
@@ -215,6 +417,267 @@ async fn get(
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ScanQuery {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub prefix: Option<String>,
+    pub limit: Option<u32>,
+    pub reverse: Option<bool>,
+    pub token: Option<String>,
+}
+
+async fn scan(
+    rs: web::Data<Arc<RouterService>>,
+    req: HttpRequest,
+    query: web::Query<ScanQuery>,
+) -> HttpResponse {
+    let collection_name: String = req
+        .match_info()
+        .get("collection_name")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let id: String = req.match_info().get("id").unwrap().parse().unwrap();
+
+    let query = query.into_inner();
+
+    let limit = query.limit.unwrap_or(100);
+    if limit == 0 || limit > 10000 {
+        return HttpResponse::build(Code::ParamError.http_code())
+            .content_type("application/json")
+            .body(
+                err!(
+                    Code::ParamError,
+                    "scan limit:{} must between 1 and 10000",
+                    limit
+                )
+                .to_json(),
+            );
+    }
+
+    let sr = ScanRequest {
+        collection_id: 0,
+        partition_id: 0,
+        id: id,
+        start: query.start.unwrap_or(String::default()),
+        end: query.end.unwrap_or(String::default()),
+        prefix: query.prefix.unwrap_or(String::default()),
+        limit: limit,
+        reverse: query.reverse.unwrap_or(false),
+        token: query.token.unwrap_or(String::default()),
+    };
+
+    match rs.scan(collection_name, sr).await {
+        Ok(s) => HttpResponse::build(Code::Success.http_code()).json(scan_to_json(s)),
+        Err(e) => HttpResponse::build(e.code().http_code())
+            .content_type("application/json")
+            .body(e.to_json()),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ExportQuery {
+    pub query: Option<String>,
+    pub def_fields: Option<String>,
+}
+
+// export writes all the documents matched by query as ndjson, one document per line
+async fn export(
+    rs: web::Data<Arc<RouterService>>,
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+) -> HttpResponse {
+    let collection_name: String = req
+        .match_info()
+        .get("collection_name")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let query = query.into_inner();
+
+    let def_fields = match query.def_fields {
+        Some(dfs) => dfs.split(",").map(|s| s.to_string()).collect(),
+        None => Vec::default(),
+    };
+
+    let (tx, rx) = channel(8);
+
+    let rs = rs.get_ref().clone();
+    actix_rt::spawn(async move {
+        rs.export(
+            collection_name,
+            query.query.unwrap_or(String::from("*")),
+            def_fields,
+            tx,
+        )
+        .await
+    });
+
+    HttpResponse::build(Code::Success.http_code())
+        .content_type("application/x-ndjson")
+        .streaming(rx.map(export_to_ndjson))
+}
+
+fn export_to_ndjson(resp: ASResult<ExportResponse>) -> Result<web::Bytes, actix_web::Error> {
+    let resp = match resp {
+        Ok(resp) => resp,
+        Err(e) => {
+            error!("export has err:{:?}", e);
+            return Err(ErrorInternalServerError(e.to_json()));
+        }
+    };
+
+    let mut buf = Vec::new();
+    for doc in resp.docs {
+        let doc: Document =
+            Message::decode(prost::bytes::Bytes::from(doc)).map_err(ErrorInternalServerError)?;
+        let value = document_to_json(doc).map_err(|e| ErrorInternalServerError(e.to_json()))?;
+        serde_json::to_writer(&mut buf, &value).map_err(ErrorInternalServerError)?;
+        buf.push(b'\n');
+    }
+
+    Ok(web::Bytes::from(buf))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ChangesQuery {
+    //the raft index to start from by partition, example: 1:100,2:80
+    pub from: Option<String>,
+    pub source: Option<bool>,
+}
+
+// changes writes the committed changes of all partitions as ndjson, the response keeps open for
+// the later changes. it ends with an error line when any partition fails
+async fn changes(
+    rs: web::Data<Arc<RouterService>>,
+    req: HttpRequest,
+    query: web::Query<ChangesQuery>,
+) -> HttpResponse {
+    let collection_name: String = req
+        .match_info()
+        .get("collection_name")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let query = query.into_inner();
+
+    let from = match parse_from(query.from) {
+        Ok(v) => v,
+        Err(e) => {
+            return HttpResponse::build(e.code().http_code())
+                .content_type("application/json")
+                .body(e.to_json())
+        }
+    };
+
+    let pids = match rs.partition_ids(collection_name.as_str()).await {
+        Ok(v) => v,
+        Err(e) => {
+            return HttpResponse::build(e.code().http_code())
+                .content_type("application/json")
+                .body(e.to_json())
+        }
+    };
+
+    let (tx, rx) = channel(8);
+
+    for pid in pids {
+        let rs = rs.get_ref().clone();
+        let tx = tx.clone();
+        let collection_name = collection_name.clone();
+        let from_index = from.get(&pid).cloned().unwrap_or(0);
+        let with_source = query.source.unwrap_or(false);
+        actix_rt::spawn(async move {
+            rs.subscribe(collection_name, pid, from_index, with_source, tx)
+                .await
+        });
+    }
+
+    HttpResponse::build(Code::Success.http_code())
+        .content_type("application/x-ndjson")
+        .streaming(rx.scan(false, |failed, resp| {
+            if *failed {
+                return None;
+            }
+            *failed = resp.is_err();
+            Some(changes_to_ndjson(resp))
+        }))
+}
+
+//the pairs of partition_id:raft_index split by ','
+fn parse_from(from: Option<String>) -> ASResult<HashMap<u32, u64>> {
+    let mut result = HashMap::new();
+    for pair in from.unwrap_or_default().split(',') {
+        if pair.is_empty() {
+            continue;
+        }
+        let mut kv = pair.splitn(2, ':');
+        match (
+            kv.next().and_then(|k| k.parse().ok()),
+            kv.next().and_then(|v| v.parse().ok()),
+        ) {
+            (Some(pid), Some(index)) => {
+                result.insert(pid, index);
+            }
+            _ => {
+                return result!(
+                    Code::ParamError,
+                    "from:{} must be partition_id:raft_index split by ','",
+                    pair
+                )
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn changes_to_ndjson(resp: ASResult<SubscribeResponse>) -> Result<web::Bytes, actix_web::Error> {
+    let resp = match resp {
+        Ok(resp) => resp,
+        Err(e) => {
+            error!("changes has err:{:?}", e);
+            let mut buf = serde_json::to_vec(&e.to_json()).map_err(ErrorInternalServerError)?;
+            buf.push(b'\n');
+            return Ok(web::Bytes::from(buf));
+        }
+    };
+
+    let mut buf = Vec::new();
+    for change in resp.changes {
+        let change_type = match ChangeType::from_i32(change.change_type) {
+            Some(ChangeType::Create) => "create",
+            Some(ChangeType::Update) => "update",
+            _ => "delete",
+        };
+        let origin = match ChangeOrigin::from_i32(change.origin) {
+            Some(ChangeOrigin::Split) => "split",
+            Some(ChangeOrigin::Import) => "import",
+            Some(ChangeOrigin::Expire) => "expire",
+            _ => "user",
+        };
+        let mut value = json!({
+            "partition_id": resp.partition_id,
+            "raft_index": change.raft_index,
+            "type": change_type,
+            "origin": origin,
+            "_id": change.id,
+            "_sort_key": change.sort_key,
+            "_version": change.version,
+        });
+        if change.source.len() > 0 {
+            value["_source"] = serde_json::from_slice(change.source.as_slice())
+                .map_err(ErrorInternalServerError)?;
+        }
+        serde_json::to_writer(&mut buf, &value).map_err(ErrorInternalServerError)?;
+        buf.push(b'\n');
+    }
+
+    Ok(web::Bytes::from(buf))
+}
+
 async fn count(rs: web::Data<Arc<RouterService>>, req: HttpRequest) -> HttpResponse {
     let collection_name: String = req
         .match_info()
@@ -236,7 +699,89 @@ async fn count(rs: web::Data<Arc<RouterService>>, req: HttpRequest) -> HttpRespo
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct TempVectorQuery {
     pub field: Option<String>,
+    #[serde(default)]
     pub vector: Vec<f32>,
+    //batch k-NN, every one is a query vector
+    #[serde(default)]
+    pub vectors: Vec<Vec<f32>>,
+    pub nprobe: Option<u32>,
+    pub ef_search: Option<u32>,
+    pub rerank: Option<u32>,
+    pub min_score: Option<f32>,
+    pub max_distance: Option<f32>,
+}
+
+impl TempVectorQuery {
+    fn into_vector_query(self) -> ASResult<VectorQuery> {
+        let field = match self.field {
+            Some(field) => field,
+            None => return result!(Code::ParamError, "vector query not set field"),
+        };
+        let mut vector = self.vector;
+        for v in self.vectors {
+            vector.extend(v);
+        }
+        Ok(VectorQuery {
+            field,
+            vector,
+            nprobe: self.nprobe.unwrap_or(0),
+            ef_search: self.ef_search.unwrap_or(0),
+            rerank: self.rerank.unwrap_or(0),
+            min_score: self.min_score.unwrap_or(0.0),
+            max_distance: self.max_distance.unwrap_or(0.0),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct TempHybridQuery {
+    pub mode: Option<String>, //linear|rrf
+    pub text_weight: Option<f32>,
+    pub vector_weight: Option<f32>,
+    pub rrf_k: Option<u32>,
+    pub window: Option<u32>,
+}
+
+impl TempHybridQuery {
+    fn into_hybrid(self) -> ASResult<HybridQuery> {
+        let mode = match self.mode.as_ref().map(|m| m.as_str()) {
+            None | Some("linear") => HybridMode::Linear,
+            Some("rrf") => HybridMode::Rrf,
+            Some(m) => return result!(Code::ParamError, "hybrid mode:{} not support", m),
+        };
+        Ok(HybridQuery {
+            mode: mode as i32,
+            text_weight: self.text_weight.unwrap_or(0.0),
+            vector_weight: self.vector_weight.unwrap_or(0.0),
+            rrf_k: self.rrf_k.unwrap_or(0),
+            window: self.window.unwrap_or(0),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct TempHighlight {
+    pub fields: Option<serde_json::Value>,
+    pub fragment_size: Option<u32>,
+    pub pre_tag: Option<String>,
+    pub post_tag: Option<String>,
+    pub encoder: Option<String>,
+}
+
+impl TempHighlight {
+    fn into_highlight(self) -> ASResult<Highlight> {
+        let fields = parse_paths(self.fields)?;
+        if fields.len() == 0 {
+            return result!(Code::ParamError, "highlight not set fields");
+        }
+        Ok(Highlight {
+            fields,
+            fragment_size: self.fragment_size.unwrap_or(0),
+            pre_tag: self.pre_tag.unwrap_or_default(),
+            post_tag: self.post_tag.unwrap_or_default(),
+            encoder: self.encoder.unwrap_or_default(),
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -246,8 +791,15 @@ struct Query {
     pub vector_query: Option<TempVectorQuery>,
     pub size: Option<u32>,
     pub sort: Option<String>, //name:asc|age:desc
+    pub cursor: Option<String>,
     pub fun: Option<String>,
     pub group: Option<String>,
+    pub dsl: Option<serde_json::Value>,
+    pub hybrid: Option<TempHybridQuery>,
+    pub includes: Option<serde_json::Value>,
+    pub excludes: Option<serde_json::Value>,
+    pub vectors: Option<bool>,
+    pub highlight: Option<TempHighlight>,
 }
 
 // search begin
@@ -331,15 +883,24 @@ async fn _search(
     };
 
     let vq = match query.vector_query {
-        Some(tvq) => Some(VectorQuery {
-            field: match tvq.field {
-                Some(field) => field,
-                None => {
-                    return result!(Code::ParamError, "vector query not set field");
-                }
-            },
-            vector: tvq.vector,
-        }),
+        Some(tvq) => Some(tvq.into_vector_query()?),
+        None => None,
+    };
+
+    let hybrid = match query.hybrid {
+        Some(hq) => Some(hq.into_hybrid()?),
+        None => None,
+    };
+
+    let search_after = match query.cursor {
+        Some(cursor) if cursor.len() > 0 => coding::cursor_decoding(cursor.as_str())?,
+        _ => Vec::default(),
+    };
+
+    let source_filter = source_filter(query.includes, query.excludes, query.vectors)?;
+
+    let highlight = match query.highlight {
+        Some(th) => Some(th.into_highlight()?),
         None => None,
     };
 
@@ -350,6 +911,11 @@ async fn _search(
         vq,
         query.size.unwrap_or(20),
         sort,
+        search_after,
+        dsl_string(query.dsl),
+        hybrid,
+        source_filter,
+        highlight,
     )
     .await
 }
@@ -435,15 +1001,7 @@ async fn _agg(
     };
 
     let vq = match query.vector_query {
-        Some(tvq) => Some(VectorQuery {
-            field: match tvq.field {
-                Some(field) => field,
-                None => {
-                    return result!(Code::ParamError, "vector query not set field");
-                }
-            },
-            vector: tvq.vector,
-        }),
+        Some(tvq) => Some(tvq.into_vector_query()?),
         None => None,
     };
 
@@ -456,10 +1014,58 @@ async fn _agg(
         query.group.unwrap_or(String::from("")),
         query.fun.unwrap_or(String::from("")),
         sort,
+        dsl_string(query.dsl),
     )
     .await
 }
 
+//the dsl is a json object in post body, or a json string in get params
+fn dsl_string(dsl: Option<serde_json::Value>) -> String {
+    match dsl {
+        Some(serde_json::Value::String(s)) => s,
+        Some(serde_json::Value::Null) | None => String::default(),
+        Some(v) => v.to_string(),
+    }
+}
+
+//the paths are array or string split by ','
+fn parse_paths(v: Option<serde_json::Value>) -> ASResult<Vec<String>> {
+    match v {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(s)) => Ok(s
+            .split(",")
+            .filter(|p| p.len() > 0)
+            .map(|p| p.to_string())
+            .collect()),
+        Some(Value::Array(arr)) => arr
+            .into_iter()
+            .map(|p| match p {
+                Value::String(s) => Ok(s),
+                _ => result!(Code::ParamError, "path:{} is not string", p),
+            })
+            .collect(),
+        Some(v) => result!(Code::ParamError, "paths:{} is not array", v),
+    }
+}
+
+//it is none if nothing to filter
+fn source_filter(
+    includes: Option<serde_json::Value>,
+    excludes: Option<serde_json::Value>,
+    vectors: Option<bool>,
+) -> ASResult<Option<SourceFilter>> {
+    let filter = SourceFilter {
+        includes: parse_paths(includes)?,
+        excludes: parse_paths(excludes)?,
+        vectors: vectors.unwrap_or(false),
+    };
+
+    if filter.includes.len() == 0 && filter.excludes.len() == 0 && !filter.vectors {
+        return Ok(None);
+    }
+    Ok(Some(filter))
+}
+
 fn agg_to_json(adr: AggregationResponse) -> serde_json::value::Value {
     let (success, error, message) = match adr.info {
         Some(i) => (i.success, i.error, i.message),
@@ -502,31 +1108,53 @@ fn agg_to_json(adr: AggregationResponse) -> serde_json::value::Value {
     });
 }
 
+fn hits_to_json(hits: Vec<Hit>) -> ASResult<Vec<serde_json::value::Value>> {
+    let mut result = Vec::with_capacity(hits.len());
+    for hit in hits {
+        result.push(hit_to_json(hit)?);
+    }
+    Ok(result)
+}
+
 fn search_to_json(sdr: SearchDocumentResponse) -> serde_json::value::Value {
     let (success, error, message) = match sdr.info {
         Some(i) => (i.success, i.error, i.message),
         None => (1, 0, String::default()),
     };
 
-    let mut hits = Vec::new();
-    for hit in sdr.hits {
-        match hit_to_json(hit) {
-            Ok(v) => hits.push(v),
-            Err(e) => {
-                return json!({
-                    "code": Code::InternalErr as i32 ,
-                    "info": {
-                        "message":format!("document decoding failed:{}", e.to_string())
-                    },
-                })
-            }
+    //the cursor for next page
+    let cursor = match sdr.hits.last() {
+        Some(hit) if hit.sort.len() > 0 => coding::cursor_coding(&hit.sort),
+        _ => String::default(),
+    };
+
+    let decode_err = |e: ASError| {
+        json!({
+            "code": Code::InternalErr as i32 ,
+            "info": {
+                "message":format!("document decoding failed:{}", e.to_string())
+            },
+        })
+    };
+
+    let hits = match hits_to_json(sdr.hits) {
+        Ok(v) => v,
+        Err(e) => return decode_err(e),
+    };
+
+    let mut batch = Vec::with_capacity(sdr.batch.len());
+    for list in sdr.batch {
+        match hits_to_json(list.hits) {
+            Ok(v) => batch.push(Value::Array(v)),
+            Err(e) => return decode_err(e),
         }
     }
 
-    return json!({
+    let mut result = json!({
         "code": sdr.code ,
         "total": sdr.total ,
         "hits":hits,
+        "cursor": cursor,
         "info":{
             "success": success ,
             "error": error ,
@@ -534,6 +1162,12 @@ fn search_to_json(sdr: SearchDocumentResponse) -> serde_json::value::Value {
         }
 
     });
+
+    if batch.len() > 0 {
+        result["batch"] = Value::Array(batch);
+    }
+
+    result
 }
 
 fn hit_to_json(hit: Hit) -> ASResult<serde_json::value::Value> {
@@ -559,7 +1193,7 @@ fn hit_to_json(hit: Hit) -> ASResult<serde_json::value::Value> {
         }
     };
 
-    Ok(json!({
+    let mut result = json!({
         "score": hit.score ,
         "doc":{
             "_id": doc.id,
@@ -567,7 +1201,21 @@ fn hit_to_json(hit: Hit) -> ASResult<serde_json::value::Value> {
             "_version": doc.version,
             "_source":source,
         },
-    }))
+    });
+    if doc.expire_at > 0 {
+        result["doc"]["_expire_at"] = json!(doc.expire_at);
+    }
+
+    if hit.highlights.len() > 0 {
+        let highlight: serde_json::Map<String, Value> = hit
+            .highlights
+            .into_iter()
+            .map(|(name, f)| (name, json!(f.fragments)))
+            .collect();
+        result["highlight"] = Value::Object(highlight);
+    }
+
+    Ok(result)
 }
 
 fn doc_to_json(dr: DocumentResponse) -> serde_json::value::Value {
@@ -598,7 +1246,7 @@ fn doc_to_json(dr: DocumentResponse) -> serde_json::value::Value {
         }
     };
 
-    json!({
+    let mut result = json!({
         "code": dr.code ,
         "message": dr.message,
         "doc":{
@@ -607,10 +1255,85 @@ fn doc_to_json(dr: DocumentResponse) -> serde_json::value::Value {
             "_version": doc.version,
             "_source": source,
         },
+    });
+    if doc.expire_at > 0 {
+        result["doc"]["_expire_at"] = json!(doc.expire_at);
+    }
+    result
+}
+
+fn scan_to_json(sr: ScanResponse) -> serde_json::value::Value {
+    let mut docs = Vec::with_capacity(sr.docs.len());
+
+    for doc in sr.docs {
+        let doc: Document = match Message::decode(prost::bytes::Bytes::from(doc)) {
+            Ok(d) => d,
+            Err(e) => {
+                return json!({
+                    "code": Code::InternalErr as i32 ,
+                    "message": format!("document decoding failed:{}", e.to_string()),
+                });
+            }
+        };
+
+        match document_to_json(doc) {
+            Ok(v) => docs.push(v),
+            Err(e) => {
+                return json!({
+                    "code": e.code() as i32 ,
+                    "message": e.message(),
+                });
+            }
+        }
+    }
+
+    json!({
+        "code": sr.code ,
+        "message": sr.message,
+        "docs": docs,
+        "token": sr.token,
     })
 }
 
+//the vectors of document are put back to source by field name
+fn document_to_json(doc: Document) -> ASResult<serde_json::value::Value> {
+    let mut source: Value = match serde_json::from_slice(doc.source.as_slice()) {
+        Ok(v) => v,
+        Err(e) => {
+            return result!(
+                Code::InternalErr,
+                "source decoding failed:{}",
+                e.to_string()
+            );
+        }
+    };
+
+    if let Some(obj) = source.as_object_mut() {
+        for v in doc.vectors {
+            obj.insert(v.name, json!(v.vector));
+        }
+    }
+
+    let mut result = json!({
+        "_id": doc.id,
+        "_sort_key": doc.sort_key,
+        "_version": doc.version,
+        "_source": source,
+    });
+    if doc.expire_at > 0 {
+        result["_expire_at"] = json!(doc.expire_at);
+    }
+    Ok(result)
+}
+
 fn gr_to_json(gr: GeneralResponse) -> serde_json::value::Value {
+    if gr.version > 0 {
+        return json!({
+            "code": gr.code ,
+            "message": gr.message,
+            "version": gr.version,
+        });
+    }
     json!({
         "code": gr.code ,
         "message": gr.message,
@@ -14,6 +14,7 @@
 use crate::client::ps_client::PsClient;
 use crate::pserverpb::*;
 use crate::util::{config::Config, error::*};
+use async_std::sync::Sender;
 use std::sync::Arc;
 
 pub struct RouterService {
@@ -32,22 +33,58 @@ impl RouterService {
         collection_name: String,
         id: String,
         sort_key: String,
-        version: i64,
+        if_version: i64,
+        if_absent: bool,
+        ttl: u64,
+        expire_at: u64,
         source: Vec<u8>,
         wt: i32,
     ) -> ASResult<GeneralResponse> {
         self.ps_client
-            .write(collection_name, id, sort_key, version, source, wt)
+            .write(
+                collection_name,
+                id,
+                sort_key,
+                if_version,
+                if_absent,
+                ttl,
+                expire_at,
+                source,
+                wt,
+            )
             .await
     }
 
+    pub async fn bulk(
+        &self,
+        collection_name: String,
+        writes: Vec<WriteDocumentRequest>,
+    ) -> ASResult<Vec<GeneralResponse>> {
+        self.ps_client.bulk(collection_name, writes).await
+    }
+
+    pub async fn transaction(
+        &self,
+        collection_name: String,
+        writes: Vec<WriteDocumentRequest>,
+    ) -> ASResult<Vec<GeneralResponse>> {
+        self.ps_client.transaction(collection_name, writes).await
+    }
+
     pub async fn get(
         &self,
         collection_name: String,
         id: String,
         sort_key: String,
+        source_filter: Option<SourceFilter>,
     ) -> ASResult<DocumentResponse> {
-        self.ps_client.get(collection_name, id, sort_key).await
+        self.ps_client
+            .get(collection_name, id, sort_key, source_filter)
+            .await
+    }
+
+    pub async fn scan(&self, collection_name: String, req: ScanRequest) -> ASResult<ScanResponse> {
+        self.ps_client.scan(collection_name, req).await
     }
 
     pub async fn search(
@@ -58,6 +95,11 @@ impl RouterService {
         vector_query: Option<VectorQuery>,
         size: u32,
         sort: Vec<Order>,
+        search_after: Vec<Vec<u8>>,
+        dsl: String,
+        hybrid: Option<HybridQuery>,
+        source_filter: Option<SourceFilter>,
+        highlight: Option<Highlight>,
     ) -> ASResult<SearchDocumentResponse> {
         self.ps_client
             .search(
@@ -71,6 +113,11 @@ impl RouterService {
                     sort: sort,
                     fun: Default::default(),
                     group: Default::default(),
+                    search_after: search_after,
+                    dsl: dsl,
+                    hybrid: hybrid,
+                    source_filter: source_filter,
+                    highlight: highlight,
                 },
             )
             .await
@@ -86,6 +133,7 @@ impl RouterService {
         group: String,
         fun: String,
         sort: Vec<Order>,
+        dsl: String,
     ) -> ASResult<AggregationResponse> {
         self.ps_client
             .agg(
@@ -99,11 +147,51 @@ impl RouterService {
                     group,
                     fun,
                     sort,
+                    search_after: vec![],
+                    dsl,
+                    hybrid: None,
+                    source_filter: None,
+                    highlight: None,
                 },
             )
             .await
     }
 
+    pub async fn export(
+        &self,
+        collection_name: String,
+        query: String,
+        def_fields: Vec<String>,
+        tx: Sender<ASResult<ExportResponse>>,
+    ) {
+        self.ps_client
+            .export(collection_name.as_str(), query, def_fields, tx)
+            .await
+    }
+
+    pub async fn partition_ids(&self, collection_name: &str) -> ASResult<Vec<u32>> {
+        self.ps_client.partition_ids(collection_name).await
+    }
+
+    pub async fn subscribe(
+        &self,
+        collection_name: String,
+        partition_id: u32,
+        from_index: u64,
+        with_source: bool,
+        tx: Sender<ASResult<SubscribeResponse>>,
+    ) {
+        self.ps_client
+            .subscribe(
+                collection_name.as_str(),
+                partition_id,
+                from_index,
+                with_source,
+                tx,
+            )
+            .await
+    }
+
     pub async fn count(&self, collection_name: String) -> ASResult<CountDocumentResponse> {
         self.ps_client.count(collection_name.as_str()).await
     }
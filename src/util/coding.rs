@@ -12,6 +12,8 @@
 // implied. See the License for the specific language governing
 // permissions and limitations under the License.
 use crate::pserverpb::Document;
+use crate::util::error::*;
+use crate::*;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::mem::size_of;
@@ -140,6 +142,39 @@ pub fn base64(value: &Vec<u8>) -> String {
     base64::encode(value)
 }
 
+// cursor is the sort values of a hit, coding as base64(u32(len) + value ...)
+pub fn cursor_coding(values: &Vec<Vec<u8>>) -> String {
+    let mut arr = Vec::new();
+    for v in values {
+        arr.extend_from_slice(&u32_slice(v.len() as u32));
+        arr.extend_from_slice(v);
+    }
+    base64::encode_config(&arr, base64::URL_SAFE_NO_PAD)
+}
+
+pub fn cursor_decoding(cursor: &str) -> ASResult<Vec<Vec<u8>>> {
+    let arr = match base64::decode_config(cursor, base64::URL_SAFE_NO_PAD) {
+        Ok(arr) => arr,
+        Err(e) => return result!(Code::ParamError, "cursor:{} decoding failed:{}", cursor, e),
+    };
+
+    let mut values = Vec::new();
+    let mut offset = 0;
+    while offset < arr.len() {
+        if offset + 4 > arr.len() {
+            return result!(Code::ParamError, "cursor:{} is invalid", cursor);
+        }
+        let len = slice_u32(&arr[offset..offset + 4]) as usize;
+        offset += 4;
+        if offset + len > arr.len() {
+            return result!(Code::ParamError, "cursor:{} is invalid", cursor);
+        }
+        values.push(arr[offset..offset + len].to_vec());
+        offset += len;
+    }
+    Ok(values)
+}
+
 /**
  * id coding has two model
  * 0. doc id : u32(iid) = proto(document) //first must 0
@@ -147,12 +182,19 @@ pub fn base64(value: &Vec<u8>) -> String {
  * 2. doc key: u8(2) + str(id) = field key
  * 3. doc key: u8(3) + hash_str(id) + id + 0 + sort_key = field key
  * 4. field key : u8(4) + str(field_name) + 0 + u32(iid) = field_value
+ * 5. expire key : u8(5) + u64(expire_at) + u32(iid) = empty
+ * 6. version key : u8(6) + doc key = i64(version of delete) + u64(deleted_at)
+ * 7. version expire key : u8(7) + u64(deleted_at) + u32(slot) + doc key = empty
  * if sort_key is "" it use to 1.
  * if sort_key is not "" , it use 2.
  * the id  for routing partition
  *
  */
 pub const RAFT_INDEX_KEY: &'static [u8; 2] = &[1, 1];
+//the dir name of tantivy index in use, it is changed by rebuild
+pub const INDEX_DIR_KEY: &'static [u8; 2] = &[1, 2];
+//the write fence and read fence of split, they are written by raft
+pub const FENCE_KEY: &'static [u8; 2] = &[1, 3];
 
 pub fn key_type(key: &Vec<u8>) -> &'static str {
     match key[0] {
@@ -161,6 +203,9 @@ pub fn key_type(key: &Vec<u8>) -> &'static str {
         2 => "doc_id",
         3 => "doc_id",
         4 => "field",
+        5 => "expire",
+        6 => "version",
+        7 => "version_expire",
         _ => "unknow",
     }
 }
@@ -178,6 +223,55 @@ pub fn field_coding(field_name: &str, iid: u32) -> Vec<u8> {
     arr
 }
 
+//the expire keys are ordered by time, so the expired ones are the range before now
+pub fn expire_coding(expire_at: u64, iid: u32) -> Vec<u8> {
+    let mut arr = Vec::with_capacity(13);
+    arr.push(5);
+    arr.extend_from_slice(&u64_slice(expire_at));
+    arr.extend_from_slice(&u32_slice(iid));
+    arr
+}
+
+//the expire time and iid of expire key
+pub fn expire_decoding(key: &[u8]) -> (u64, u32) {
+    (slice_u64(&key[1..9]), slice_u32(&key[9..13]))
+}
+
+//the last version of deleted document, the new one of the key continues from it
+pub fn version_coding(key: &[u8]) -> Vec<u8> {
+    let mut arr = Vec::with_capacity(1 + key.len());
+    arr.push(6);
+    arr.extend_from_slice(key);
+    arr
+}
+
+pub fn version_value_coding(version: i64, deleted_at: u64) -> Vec<u8> {
+    let mut arr = Vec::with_capacity(16);
+    arr.extend_from_slice(&i64_slice(version));
+    arr.extend_from_slice(&u64_slice(deleted_at));
+    arr
+}
+
+//the version and delete time of version value
+pub fn version_value_decoding(value: &[u8]) -> (i64, u64) {
+    (slice_i64(&value[..8]), slice_u64(&value[8..16]))
+}
+
+//the version expire keys are ordered by delete time, the versions before retention are removed
+pub fn version_expire_coding(deleted_at: u64, slot: u32, key: &[u8]) -> Vec<u8> {
+    let mut arr = Vec::with_capacity(13 + key.len());
+    arr.push(7);
+    arr.extend_from_slice(&u64_slice(deleted_at));
+    arr.extend_from_slice(&u32_slice(slot));
+    arr.extend_from_slice(key);
+    arr
+}
+
+//the delete time, slot and doc key of version expire key
+pub fn version_expire_decoding(key: &[u8]) -> (u64, u32, &[u8]) {
+    (slice_u64(&key[1..9]), slice_u32(&key[9..13]), &key[13..])
+}
+
 pub fn key_coding(id: &str, sort_key: &str) -> Vec<u8> {
     if sort_key.is_empty() {
         let mut arr = Vec::with_capacity(1 + id.len());
@@ -186,13 +280,19 @@ pub fn key_coding(id: &str, sort_key: &str) -> Vec<u8> {
         return arr;
     }
 
-    let mut arr = Vec::with_capacity(6 + id.len() + sort_key.len());
+    let mut arr = key_prefix_coding(id, sort_key.len());
+    arr.extend_from_slice(sort_key.as_bytes());
+
+    arr
+}
+
+//the prefix of keys which has sort_key under the id, cap is the capacity for sort_key
+pub fn key_prefix_coding(id: &str, cap: usize) -> Vec<u8> {
+    let mut arr = Vec::with_capacity(10 + id.len() + cap);
     arr.push(3);
     arr.extend(hash_str(id).to_be_bytes().to_vec());
     arr.extend_from_slice(id.as_bytes());
     arr.push(0);
-    arr.extend_from_slice(sort_key.as_bytes());
-
     arr
 }
 
@@ -326,6 +426,25 @@ pub fn test_coding_u32() {
     assert_eq!(a, b);
 }
 
+#[test]
+pub fn test_expire_coding() {
+    let a = expire_coding(1000, 7);
+    let b = expire_coding(1001, 1);
+    assert!(a < b);
+    assert!(expire_coding(0, 0) > key_coding("id", "sk"));
+    assert_eq!((1000, 7), expire_decoding(&a));
+    assert!(version_coding(&key_coding("id", "sk")) > expire_coding(u64::MAX, 0));
+
+    let key = key_coding("id", "sk");
+    let a = version_expire_coding(1000, 3, &key);
+    assert!(a < version_expire_coding(1001, 0, &key));
+    assert_eq!((1000, 3, key.as_slice()), version_expire_decoding(&a));
+    assert_eq!(
+        (5, 1000),
+        version_value_decoding(&version_value_coding(5, 1000))
+    );
+}
+
 #[test]
 pub fn test_split_u32() {
     let v: u64 = 2132391239123;
@@ -349,6 +468,14 @@ pub fn test_split_u32() {
     assert_eq!(pid, partition_id);
 }
 
+#[test]
+pub fn test_cursor_coding() {
+    let values = vec![vec![1, 2, 3], vec![], f32_slice(1.5).to_vec()];
+    let cursor = cursor_coding(&values);
+    assert_eq!(values, cursor_decoding(cursor.as_str()).unwrap());
+    assert!(cursor_decoding("AAAA").is_err());
+}
+
 #[test]
 pub fn string_arr_coding_decoding() {
     let arr = vec![
@@ -79,6 +79,14 @@ pub struct PS {
     pub data: String,
     pub rpc_port: u16,
     pub flush_sleep_sec: Option<u64>,
+    // interval of heartbeat to master
+    pub heartbeat_sec: Option<u64>,
+    // how often to snapshot the vector index, it is loaded on startup instead of training
+    pub vector_snapshot_sec: Option<u64>,
+    // how often to delete the expired documents
+    pub reap_expired_sec: Option<u64>,
+    // how long to keep the version of deleted documents, the new one of key continues from it
+    pub version_retention_sec: Option<u64>,
     pub raft: RaftConf,
 }
 
@@ -103,6 +111,9 @@ pub struct Master {
     #[serde(default = "false_bool")]
     pub is_self: bool,
     pub data: String,
+    // pserver not heartbeat in the time will be marked down, and its partitions will be recovered
+    #[serde(default = "default_down_timeout_sec")]
+    pub down_timeout_sec: u64,
 }
 
 impl Config {
@@ -227,6 +238,10 @@ fn _load_config(conf_path: &str, ip: Option<&str>) -> Config {
                 data: String::from("data/ps"),
                 rpc_port: 9090,
                 flush_sleep_sec: Some(3),
+                heartbeat_sec: Some(3),
+                vector_snapshot_sec: Some(600),
+                reap_expired_sec: Some(60),
+                version_retention_sec: Some(86400),
                 raft: RaftConf {
                     heartbeat_port: 12130,
                     replicate_port: 12131,
@@ -242,6 +257,7 @@ fn _load_config(conf_path: &str, ip: Option<&str>) -> Config {
                 http_port: 7070,
                 is_self: true,
                 data: String::from("data/"),
+                down_timeout_sec: default_down_timeout_sec(),
             }],
         };
     }
@@ -265,6 +281,10 @@ fn _load_config(conf_path: &str, ip: Option<&str>) -> Config {
     return config;
 }
 
+fn default_down_timeout_sec() -> u64 {
+    30
+}
+
 fn empty_str() -> String {
     "".to_string()
 }
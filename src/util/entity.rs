@@ -11,6 +11,7 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
 // implied. See the License for the specific language governing
 // permissions and limitations under the License.
+use crate::pserver::simba::engine::tantivy::sort::FieldScore;
 use crate::pserverpb::*;
 use crate::util::error::*;
 use crate::util::time::*;
@@ -18,6 +19,7 @@ use crate::*;
 use async_graphql::{Enum, InputObject};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 pub const ID_BYTES: &'static str = "_iid_bytes";
@@ -96,6 +98,111 @@ pub struct TextField {
     )]
     #[serde(default = "default_false")]
     pub value: bool,
+    #[field(
+        desc = "the analyzer for index and query: default, raw, whitespace, ngram(min,max), cjk_bigram, stem(lang)",
+        default = "default"
+    )]
+    #[serde(default = "default_analyzer")]
+    pub analyzer: String,
+}
+
+fn default_analyzer() -> String {
+    String::from("default")
+}
+
+impl TextField {
+    pub fn analyzer(&self) -> ASResult<Analyzer> {
+        Analyzer::parse(self.analyzer.as_str())
+    }
+}
+
+//the languages can be stemmed
+pub const STEM_LANGUAGES: [&'static str; 14] = [
+    "da", "de", "en", "es", "fi", "fr", "hu", "it", "nl", "pt", "ro", "ru", "sv", "tr",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Analyzer {
+    //split by non alphanumeric and lowercase
+    Default,
+    //the whole value as one token
+    Raw,
+    //split by whitespace and lowercase
+    Whitespace,
+    //ngram(min, max) of chars
+    Ngram(usize, usize),
+    //the cjk chars as overlapping bigrams, others as default
+    CjkBigram,
+    //default and stemmed by language
+    Stem(String),
+}
+
+impl Analyzer {
+    pub fn parse(s: &str) -> ASResult<Analyzer> {
+        let s = s.trim();
+        let (name, args) = match s.find('(') {
+            Some(i) if s.ends_with(')') => (
+                s[..i].trim(),
+                s[i + 1..s.len() - 1]
+                    .split(',')
+                    .map(|a| a.trim())
+                    .filter(|a| *a != "")
+                    .collect::<Vec<&str>>(),
+            ),
+            Some(_) => return result!(Code::ParamError, "analyzer:{} is invalid", s),
+            None => (s, vec![]),
+        };
+
+        let analyzer = match (name, args.len()) {
+            ("", 0) | ("default", 0) => Analyzer::Default,
+            ("raw", 0) => Analyzer::Raw,
+            ("whitespace", 0) => Analyzer::Whitespace,
+            ("cjk_bigram", 0) => Analyzer::CjkBigram,
+            ("ngram", 2) => {
+                let min: usize = args[0]
+                    .parse()
+                    .map_err(|_| err!(Code::ParamError, "ngram min:{} is not number", args[0]))?;
+                let max: usize = args[1]
+                    .parse()
+                    .map_err(|_| err!(Code::ParamError, "ngram max:{} is not number", args[1]))?;
+                if min == 0 || min > max {
+                    return result!(
+                        Code::ParamError,
+                        "ngram({},{}) need 0 < min <= max",
+                        min,
+                        max
+                    );
+                }
+                Analyzer::Ngram(min, max)
+            }
+            ("stem", 1) => {
+                if !STEM_LANGUAGES.contains(&args[0]) {
+                    return result!(
+                        Code::ParamError,
+                        "stem language:{} not support, it must in {:?}",
+                        args[0],
+                        STEM_LANGUAGES
+                    );
+                }
+                Analyzer::Stem(args[0].to_string())
+            }
+            _ => return result!(Code::ParamError, "analyzer:{} not support", s),
+        };
+
+        Ok(analyzer)
+    }
+
+    //the name of tokenizer registered in index
+    pub fn tokenizer_name(&self) -> String {
+        match self {
+            Analyzer::Default => String::from("default"),
+            Analyzer::Raw => String::from("raw"),
+            Analyzer::Whitespace => String::from("whitespace"),
+            Analyzer::Ngram(min, max) => format!("ngram_{}_{}", min, max),
+            Analyzer::CjkBigram => String::from("cjk_bigram"),
+            Analyzer::Stem(lang) => format!("stem_{}", lang),
+        }
+    }
 }
 
 #[InputObject]
@@ -132,6 +239,62 @@ pub struct DateField {
 pub enum MetricType {
     L2 = 1,
     InnerProduct = 2,
+    //the vectors are normalized, so it is the inner product of them
+    Cosine = 3,
+}
+
+impl MetricType {
+    //the score of hit, the greater is the nearer for all metrics
+    pub fn similarity(&self, distance: f32) -> f32 {
+        match self {
+            MetricType::L2 => 1.0 / (1.0 + distance),
+            MetricType::InnerProduct | MetricType::Cosine => distance,
+        }
+    }
+
+    //the min similarity of hits by min_score and max_distance of query
+    pub fn min_similarity(&self, vq: &VectorQuery) -> ASResult<Option<f32>> {
+        if vq.max_distance < 0.0 {
+            return result!(
+                Code::ParamError,
+                "max_distance:{} can not be negative",
+                vq.max_distance
+            );
+        }
+
+        let mut min = if vq.min_score != 0.0 {
+            Some(vq.min_score)
+        } else {
+            None
+        };
+
+        if vq.max_distance > 0.0 {
+            let s = match self {
+                MetricType::L2 => self.similarity(vq.max_distance),
+                //the cosine distance is 1 - similarity
+                MetricType::Cosine => 1.0 - vq.max_distance,
+                MetricType::InnerProduct => {
+                    return result!(
+                        Code::ParamError,
+                        "max_distance only for L2 and Cosine, use min_score for InnerProduct"
+                    )
+                }
+            };
+            min = Some(min.map_or(s, |m: f32| m.max(s)));
+        }
+
+        Ok(min)
+    }
+
+    //the same as faiss, L2 is squared
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            MetricType::L2 => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum(),
+            MetricType::InnerProduct | MetricType::Cosine => {
+                a.iter().zip(b).map(|(x, y)| x * y).sum()
+            }
+        }
+    }
 }
 
 #[InputObject]
@@ -149,12 +312,151 @@ pub struct VectorField {
     //dimension: dimension of the input vectors
     pub dimension: i32,
     // A constructor
+    #[field(
+        desc = "the faiss factory string, it is made by index_params if set",
+        default = ""
+    )]
+    #[serde(default)]
     pub description: String,
     // the type of metric
     pub metric_type: MetricType,
+    #[field(desc = "structured index params instead of description")]
+    #[serde(default)]
+    pub index_params: Option<IndexParams>,
+}
+
+#[InputObject]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct IndexParams {
+    #[field(desc = "the type of index: flat, ivf_flat, ivf_sq8, ivf_pq, pq, hnsw")]
+    pub index_type: String,
+    #[field(desc = "the number of inverted lists for ivf", default = 0)]
+    #[serde(default)]
+    pub nlist: i32,
+    #[field(desc = "the number of sub quantizers for pq", default = 0)]
+    #[serde(default)]
+    pub m: i32,
+    #[field(desc = "the bits of every pq code, 8 if not set", default = 0)]
+    #[serde(default)]
+    pub nbits: i32,
+    #[field(
+        desc = "the neighbors of every node in hnsw, 32 if not set",
+        default = 0
+    )]
+    #[serde(default)]
+    pub hnsw_m: i32,
+    #[field(
+        desc = "reduce the dimension by pca before index, 0 is not",
+        default = 0
+    )]
+    #[serde(default)]
+    pub pca: i32,
+    #[field(desc = "the default nprobe of ivf search", default = 0)]
+    #[serde(default)]
+    pub nprobe: i32,
+    #[field(desc = "the default efSearch of hnsw search", default = 0)]
+    #[serde(default)]
+    pub ef_search: i32,
+}
+
+impl IndexParams {
+    pub fn is_ivf(&self) -> bool {
+        self.index_type.starts_with("ivf_")
+    }
+
+    pub fn is_hnsw(&self) -> bool {
+        self.index_type == "hnsw"
+    }
+
+    //make the faiss factory string, the vectors has dimension before pca
+    pub fn description(&self, dimension: i32, train_size: i32) -> ASResult<String> {
+        if self.pca < 0 || self.pca >= dimension {
+            return result!(
+                Code::ParamError,
+                "pca:{} must be in [0, dimension:{})",
+                self.pca,
+                dimension
+            );
+        }
+
+        let dimension = if self.pca > 0 { self.pca } else { dimension };
+
+        let pq = |m: i32, nbits: i32| -> ASResult<String> {
+            if m <= 0 || dimension % m != 0 {
+                return result!(
+                    Code::ParamError,
+                    "pq m:{} must be a divisor of dimension:{}",
+                    m,
+                    dimension
+                );
+            }
+            match nbits {
+                0 | 8 => Ok(format!("PQ{}", m)),
+                1..=16 => Ok(format!("PQ{}x{}", m, nbits)),
+                _ => result!(Code::ParamError, "pq nbits:{} must be in [1, 16]", nbits),
+            }
+        };
+
+        if self.is_ivf() && self.nlist <= 0 {
+            return result!(Code::ParamError, "{} need nlist", self.index_type);
+        }
+
+        let index = match self.index_type.as_str() {
+            "flat" => String::from("Flat"),
+            "ivf_flat" => format!("IVF{},Flat", self.nlist),
+            "ivf_sq8" => format!("IVF{},SQ8", self.nlist),
+            "ivf_pq" => format!("IVF{},{}", self.nlist, pq(self.m, self.nbits)?),
+            "pq" => pq(self.m, self.nbits)?,
+            "hnsw" => format!("HNSW{}", if self.hnsw_m > 0 { self.hnsw_m } else { 32 }),
+            _ => {
+                return result!(
+                    Code::ParamError,
+                    "index_type:{} not support",
+                    self.index_type
+                )
+            }
+        };
+
+        //the index need train by the vectors
+        let need_train = self.pca > 0 || self.is_ivf() || self.index_type == "pq";
+        if need_train && train_size < std::cmp::max(self.nlist, 1) {
+            return result!(
+                Code::ParamError,
+                "{} need train_size not less than nlist:{}, found:{}",
+                self.index_type,
+                self.nlist,
+                train_size
+            );
+        }
+
+        if self.nprobe < 0 || self.ef_search < 0 {
+            return result!(Code::ParamError, "nprobe and ef_search can not be negative");
+        }
+
+        if self.pca > 0 {
+            Ok(format!("PCA{},{}", self.pca, index))
+        } else {
+            Ok(index)
+        }
+    }
 }
 
 impl VectorField {
+    //the description made by index params, or the one set by user
+    pub fn index_description(&self) -> ASResult<String> {
+        match &self.index_params {
+            Some(params) => params.description(self.dimension, self.train_size),
+            None if self.description.is_empty() => {
+                result!(
+                    Code::ParamError,
+                    "field:{} need description or index_params",
+                    self.name
+                )
+            }
+            None => Ok(self.description.clone()),
+        }
+    }
+
     pub fn validate(&self, v: Option<Value>) -> ASResult<Vec<f32>> {
         let none = v.is_none();
         if none && self.none {
@@ -188,8 +490,33 @@ impl VectorField {
             }
         }
 
+        let mut value = value;
+        self.normalize(&mut value)?;
+
         return Ok(value);
     }
+
+    //the vectors of cosine are normalized to unit length, both stored and query ones
+    pub fn normalize(&self, vectors: &mut [f32]) -> ASResult<()> {
+        match self.metric_type {
+            MetricType::Cosine => {}
+            _ => return Ok(()),
+        }
+        for v in vectors.chunks_mut(self.dimension as usize) {
+            let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm == 0.0 || !norm.is_finite() {
+                return result!(
+                    Code::ParamError,
+                    "the field:{} is cosine, it not support zero vector",
+                    self.name
+                );
+            }
+            for x in v.iter_mut() {
+                *x /= norm;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -396,6 +723,26 @@ pub struct Collection {
     pub modify_time: u64,
     pub vector_field_index: Vec<usize>,
     pub scalar_field_index: Vec<usize>,
+    //it is increased when the fields changed
+    pub version: u64,
+    //the default seconds to live of documents, 0 is never expired
+    pub ttl: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PServerStatus {
+    //heartbeat in time
+    ACTIVE = 0,
+    //missed the heartbeat deadline, its partitions are in recovering
+    DOWN = 1,
+    //all partitions of it have been moved to others
+    RECOVERED = 2,
+}
+
+impl Default for PServerStatus {
+    fn default() -> Self {
+        PServerStatus::ACTIVE
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -440,6 +787,8 @@ pub struct PServer {
     pub zone: String,
     #[serde(default = "current_millis")]
     pub modify_time: u64,
+    #[serde(default)]
+    pub status: PServerStatus,
 }
 
 impl PServer {
@@ -450,6 +799,7 @@ impl PServer {
             write_partitions: Vec::default(),
             addr: addr,
             modify_time: 0,
+            status: PServerStatus::ACTIVE,
         }
     }
 
@@ -482,16 +832,32 @@ pub fn msg_for_resp(info: &Option<SearchInfo>) -> String {
     }
 }
 
+// the hits of dist and src are both sorted, merge them and keep the top size
 pub fn merge_search_document_response(
     mut dist: SearchDocumentResponse,
     mut src: SearchDocumentResponse,
+    asc: &Vec<bool>,
+    size: usize,
 ) -> SearchDocumentResponse {
     if src.code != Code::Success as i32 {
         dist.code = src.code;
     }
 
     dist.total = src.total + dist.total;
-    dist.hits.append(&mut src.hits);
+
+    let d = std::mem::replace(&mut dist.hits, Vec::default());
+    let s = std::mem::replace(&mut src.hits, Vec::default());
+    dist.hits = merge_hits(d, s, asc, size);
+
+    //the batch of error response is empty, so it takes the other one
+    if dist.batch.len() == 0 {
+        dist.batch = std::mem::replace(&mut src.batch, Vec::default());
+    } else if src.batch.len() == dist.batch.len() {
+        for (d, s) in dist.batch.iter_mut().zip(src.batch.into_iter()) {
+            let hits = std::mem::replace(&mut d.hits, Vec::default());
+            d.hits = merge_hits(hits, s.hits, asc, size);
+        }
+    }
 
     dist.info = {
         let mut d = dist.info.unwrap_or(SearchInfo {
@@ -518,6 +884,40 @@ pub fn merge_search_document_response(
     dist
 }
 
+fn merge_hits(dist: Vec<Hit>, src: Vec<Hit>, asc: &Vec<bool>, size: usize) -> Vec<Hit> {
+    let mut hits = Vec::with_capacity(std::cmp::min(size, dist.len() + src.len()));
+    let mut d = dist.into_iter().peekable();
+    let mut s = src.into_iter().peekable();
+
+    while hits.len() < size {
+        let take_dist = match (d.peek(), s.peek()) {
+            (Some(dh), Some(sh)) => {
+                FieldScore::cmp_by_order(&dh.sort, &sh.sort, asc) != Ordering::Greater
+            }
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
+        };
+        if take_dist {
+            hits.push(d.next().unwrap());
+        } else {
+            hits.push(s.next().unwrap());
+        }
+    }
+    hits
+}
+
+// the order of every sort value, hits are ordered by score desc if there is no sort
+pub fn sort_orders(sort: &Vec<Order>) -> Vec<bool> {
+    if sort.len() == 0 {
+        vec![false]
+    } else {
+        sort.iter()
+            .map(|o| o.order.eq_ignore_ascii_case("asc"))
+            .collect()
+    }
+}
+
 pub fn merge_aggregation_response(
     mut dist: AggregationResponse,
     result: &mut HashMap<String, AggValues>,
@@ -680,3 +1080,106 @@ pub mod entity_key {
         format!("META/LOCK/{}", key)
     }
 }
+
+#[test]
+fn analyzer_parse() {
+    assert_eq!(Analyzer::Default, Analyzer::parse("").unwrap());
+    assert_eq!(Analyzer::Raw, Analyzer::parse("raw").unwrap());
+    assert_eq!(
+        Analyzer::Ngram(1, 3),
+        Analyzer::parse("ngram( 1, 3)").unwrap()
+    );
+    assert_eq!(
+        Analyzer::Stem(String::from("en")),
+        Analyzer::parse("stem(en)").unwrap()
+    );
+    assert_eq!(
+        "ngram_2_2",
+        Analyzer::parse("ngram(2,2)").unwrap().tokenizer_name()
+    );
+    assert!(Analyzer::parse("ngram(3,1)").is_err());
+    assert!(Analyzer::parse("ngram(1)").is_err());
+    assert!(Analyzer::parse("stem(xx)").is_err());
+    assert!(Analyzer::parse("jieba").is_err());
+}
+
+#[test]
+fn index_params_description() {
+    let params = |index_type: &str, nlist: i32, m: i32, pca: i32| IndexParams {
+        index_type: index_type.to_string(),
+        nlist,
+        m,
+        pca,
+        ..Default::default()
+    };
+    assert_eq!("Flat", params("flat", 0, 0, 0).description(128, 0).unwrap());
+    assert_eq!(
+        "PCA32,IVF100,PQ8",
+        params("ivf_pq", 100, 8, 32).description(128, 1000).unwrap()
+    );
+    assert_eq!(
+        "HNSW32",
+        params("hnsw", 0, 0, 0).description(128, 0).unwrap()
+    );
+    assert!(params("ivf_flat", 0, 0, 0).description(128, 1000).is_err());
+    assert!(params("ivf_pq", 100, 7, 0).description(128, 1000).is_err());
+    assert!(params("ivf_flat", 100, 0, 0).description(128, 10).is_err());
+}
+
+#[test]
+fn metric_min_similarity() {
+    let vq = |min_score: f32, max_distance: f32| VectorQuery {
+        min_score,
+        max_distance,
+        ..Default::default()
+    };
+    assert_eq!(None, MetricType::L2.min_similarity(&vq(0.0, 0.0)).unwrap());
+    assert_eq!(
+        Some(0.5),
+        MetricType::L2.min_similarity(&vq(0.2, 1.0)).unwrap()
+    );
+    assert_eq!(
+        Some(0.8),
+        MetricType::L2.min_similarity(&vq(0.8, 1.0)).unwrap()
+    );
+    assert_eq!(
+        Some(0.7),
+        MetricType::Cosine.min_similarity(&vq(0.0, 0.3)).unwrap()
+    );
+    assert_eq!(
+        Some(-0.5),
+        MetricType::InnerProduct
+            .min_similarity(&vq(-0.5, 0.0))
+            .unwrap()
+    );
+    assert!(MetricType::InnerProduct
+        .min_similarity(&vq(0.0, 1.0))
+        .is_err());
+    assert!(MetricType::L2.min_similarity(&vq(0.0, -1.0)).is_err());
+    assert!(MetricType::L2.similarity(1.0) > MetricType::L2.similarity(2.0));
+}
+
+#[test]
+fn vector_normalize() {
+    let field = |metric_type: MetricType| VectorField {
+        name: String::from("photo"),
+        array: true,
+        none: false,
+        train_size: 0,
+        dimension: 2,
+        description: String::from("Flat"),
+        index_params: None,
+        metric_type,
+    };
+
+    let mut vectors = vec![3.0, 4.0, 0.0, 2.0];
+    field(MetricType::Cosine).normalize(&mut vectors).unwrap();
+    assert_eq!(vec![0.6, 0.8, 0.0, 1.0], vectors);
+
+    let mut vectors = vec![3.0, 4.0];
+    field(MetricType::L2).normalize(&mut vectors).unwrap();
+    assert_eq!(vec![3.0, 4.0], vectors);
+
+    let mut vectors = vec![3.0, 4.0, 0.0, 0.0];
+    assert!(field(MetricType::Cosine).normalize(&mut vectors).is_err());
+}
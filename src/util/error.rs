@@ -105,6 +105,7 @@ pub enum Code {
     EncodingErr,
     DencodingErr,
     Timeout,
+    SlotMoved,
 }
 
 impl Code {
@@ -192,6 +193,7 @@ impl Into<SearchDocumentResponse> for ASError {
                 success: 0,
                 message: self.to_string(),
             }),
+            batch: vec![],
         }
     }
 }
@@ -217,6 +219,62 @@ impl Into<GeneralResponse> for ASError {
         GeneralResponse {
             code: self.code().into(),
             message: self.to_string(),
+            version: 0,
+        }
+    }
+}
+
+impl Into<BatchWriteResponse> for ASError {
+    fn into(self) -> BatchWriteResponse {
+        BatchWriteResponse {
+            code: self.code().into(),
+            message: self.to_string(),
+            results: vec![],
+        }
+    }
+}
+
+impl Into<ExportResponse> for ASError {
+    fn into(self) -> ExportResponse {
+        ExportResponse {
+            code: self.code().into(),
+            message: self.to_string(),
+            docs: vec![],
+        }
+    }
+}
+
+impl Into<SubscribeResponse> for ASError {
+    fn into(self) -> SubscribeResponse {
+        SubscribeResponse {
+            code: self.code().into(),
+            message: self.to_string(),
+            partition_id: 0,
+            changes: vec![],
+        }
+    }
+}
+
+impl Into<ScanResponse> for ASError {
+    fn into(self) -> ScanResponse {
+        ScanResponse {
+            code: self.code().into(),
+            message: self.to_string(),
+            docs: vec![],
+            token: String::default(),
+        }
+    }
+}
+
+impl Into<ReindexResponse> for ASError {
+    fn into(self) -> ReindexResponse {
+        ReindexResponse {
+            code: self.code().into(),
+            message: self.to_string(),
+            running: false,
+            total: 0,
+            indexed: 0,
+            vectors: 0,
         }
     }
 }
@@ -20,6 +20,12 @@ pub mod http_client;
 pub mod net;
 pub mod time;
 
+use async_std::{
+    sync::{Sender, TrySendError},
+    task,
+};
+use std::time::Duration;
+
 #[macro_export]
 macro_rules! sleep {
     ($x:expr) => {{
@@ -28,6 +34,20 @@ macro_rules! sleep {
 }
 
 //println stack trace
+//send msg to tx, it returns false if the receiver has been closed
+pub async fn try_send_until_closed<T>(tx: &Sender<T>, mut msg: T) -> bool {
+    loop {
+        match tx.try_send(msg) {
+            Ok(_) => return true,
+            Err(TrySendError::Full(m)) => {
+                msg = m;
+                task::sleep(Duration::from_millis(10)).await;
+            }
+            Err(TrySendError::Disconnected(_)) => return false,
+        }
+    }
+}
+
 pub fn stack_trace() {
     use log::Level::Debug;
     use log::{debug, log_enabled};
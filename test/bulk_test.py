@@ -0,0 +1,241 @@
+import pytest
+import requests
+import json
+import random
+import config
+import threading
+import time
+
+
+def test_del_collection():
+    url = "http://" + config.MASTER + "/collection/delete/t1"
+    response = requests.delete(url)
+    print("collection_delete---\n" + response.text)
+
+    assert response.status_code == 200 or response.status_code == 555
+
+
+def test_create_collection():
+    url = "http://" + config.MASTER + "/collection/create"
+    headers = {"content-type": "application/json"}
+    data = {
+        "name": "t1",
+        "partition_num": 3,
+        "partition_replica_num": 1,
+        "fields": [
+            {"string": {"name": "name", "array": True, "none": False}},
+            {"int": {"name": "age", "none": False}},
+            {"text": {"name": "content", "none": False}}
+        ]
+    }
+    print(url + "---" + json.dumps(data))
+    response = requests.post(url, headers=headers, data=json.dumps(data))
+    print("space_create---\n" + response.text)
+    assert response.status_code == 200
+    time.sleep(5)  # TODO: FIX ME wait raft ok
+
+
+def test_bulk():
+    lines = []
+    for i in range(100):
+        lines.append(json.dumps({"type": "put", "id": str(i), "doc": {
+                     "name": ["ansj", "sun"], "age": i, "content": "hello tig"}}))
+    # bad document, age must be int
+    lines.append(json.dumps(
+        {"type": "create", "id": "bad", "doc": {"name": ["ansj"], "age": "a", "content": "bad"}}))
+    # bad line
+    lines.append("{this is not json")
+    lines.append(json.dumps({"type": "update", "id": "1", "doc": {"age": 1000}}))
+    lines.append(json.dumps({"type": "delete", "id": "2"}))
+
+    url = "http://" + config.ROUTER + "/_bulk/t1"
+    response = requests.post(url, data="\n".join(lines))
+    print("bulk---" + response.text)
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert v["errors"] == 2
+    assert len(v["items"]) == 104
+    for i in range(100):
+        assert v["items"][i]["code"] == 200
+    assert v["items"][100]["code"] != 200
+    assert v["items"][101]["code"] != 200
+    assert v["items"][102]["code"] == 200
+    assert v["items"][103]["code"] == 200
+
+    response = requests.get("http://"+config.ROUTER+"/get/t1/1")
+    print("get---" + response.text)
+    v = json.loads(response.text)
+    assert v["code"] == 200
+    assert v["doc"]["_source"]["age"] == 1000
+
+    response = requests.get("http://"+config.ROUTER+"/get/t1/2")
+    assert response.status_code != 200
+
+    response = requests.get("http://"+config.ROUTER+"/get/t1/bad")
+    assert response.status_code != 200
+
+
+def test_export():
+    time.sleep(3)
+    response = requests.get("http://"+config.ROUTER+"/export/t1", stream=True)
+    assert response.status_code == 200
+    ids = set()
+    for line in response.iter_lines():
+        if line:
+            ids.add(json.loads(line)["_id"])
+    assert len(ids) == 99
+    assert "2" not in ids
+
+    response = requests.get(
+        "http://"+config.ROUTER+"/export/t1?query=age:1000")
+    assert response.status_code == 200
+    lines = [json.loads(l) for l in response.text.split("\n") if l]
+    assert len(lines) == 1
+    assert lines[0]["_id"] == "1"
+
+
+def read_changes(response, num):
+    changes = []
+    for line in response.iter_lines():
+        if line:
+            v = json.loads(line)
+            assert "code" not in v
+            changes.append(v)
+            if len(changes) == num:
+                break
+    response.close()
+    return changes
+
+
+def test_changes():
+    url = "http://" + config.ROUTER
+    response = requests.get(
+        url + "/changes/t1?source=true", stream=True, timeout=10)
+    assert response.status_code == 200
+
+    headers = {"content-type": "application/json"}
+    assert requests.post(url + "/put/t1/c1", headers=headers, data=json.dumps(
+        {"name": ["c"], "age": 1, "content": "change"})).status_code == 200
+    assert requests.post(url + "/update/t1/c1", headers=headers,
+                         data=json.dumps({"age": 2})).status_code == 200
+    assert requests.delete(url + "/delete/t1/c1").status_code == 200
+
+    changes = read_changes(response, 3)
+    print("changes---" + json.dumps(changes))
+    assert [c["type"] for c in changes] == ["create", "update", "delete"]
+    assert all(c["origin"] == "user" for c in changes)
+    assert [c["_version"] for c in changes] == [1, 2, 3]
+    assert all(c["_id"] == "c1" for c in changes)
+    assert changes[0]["_source"]["age"] == 1
+    assert changes[1]["_source"]["age"] == 2
+    assert "_source" not in changes[2]
+    assert changes[0]["raft_index"] < changes[1]["raft_index"] < changes[2]["raft_index"]
+
+    # resume from the update
+    pid = changes[1]["partition_id"]
+    response = requests.get(url + "/changes/t1?from=%d:%d" %
+                            (pid, changes[1]["raft_index"]), stream=True, timeout=10)
+    assert response.status_code == 200
+    resumed = read_changes(response, 2)
+    assert [c["type"] for c in resumed] == ["update", "delete"]
+    assert "_source" not in resumed[0]
+
+    response = requests.get(url + "/changes/t1?from=bad")
+    assert response.status_code != 200
+
+
+def test_changes_resume_far_behind():
+    url = "http://" + config.ROUTER
+    headers = {"content-type": "application/json"}
+    response = requests.get(url + "/changes/t1", stream=True, timeout=10)
+    assert response.status_code == 200
+
+    # more raft entries than the live buffer of subscriber
+    num = 1500
+    for i in range(num):
+        assert requests.post(url + "/put/t1/far", headers=headers, data=json.dumps(
+            {"name": ["far"], "age": i, "content": "far"})).status_code == 200
+    first = read_changes(response, 1)[0]
+
+    # the writes go on while resuming from the first one
+    more = 300
+    def write():
+        for i in range(more):
+            requests.post(url + "/put/t1/far", headers=headers, data=json.dumps(
+                {"name": ["far"], "age": i, "content": "far"}))
+    writer = threading.Thread(target=write)
+    writer.start()
+    response = requests.get(url + "/changes/t1?from=%d:%d" %
+                            (first["partition_id"], first["raft_index"]), stream=True, timeout=30)
+    assert response.status_code == 200
+    changes = read_changes(response, num + more)
+    writer.join()
+
+    assert all(c["_id"] == "far" for c in changes)
+    versions = [c["_version"] for c in changes]
+    assert versions == list(range(first["_version"], first["_version"] + num + more))
+
+
+def test_transaction():
+    url = "http://" + config.ROUTER + "/_transaction/t1"
+    lines = [
+        {"type": "create", "id": "tx", "sort_key": "a",
+            "doc": {"name": ["a"], "age": 1, "content": "tx"}},
+        {"type": "put", "id": "tx", "sort_key": "b",
+            "doc": {"name": ["b"], "age": 2, "content": "tx"}},
+    ]
+    response = requests.post(url, data="\n".join(
+        [json.dumps(l) for l in lines]))
+    print("transaction---" + response.text)
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert [i["version"] for i in v["items"]] == [1, 1]
+
+    # the update of c fails, so a and b are not changed
+    lines = [
+        {"type": "update", "id": "tx", "sort_key": "a", "doc": {"age": 10}},
+        {"type": "delete", "id": "tx", "sort_key": "b"},
+        {"type": "update", "id": "tx", "sort_key": "c", "doc": {"age": 30}},
+    ]
+    response = requests.post(url, data="\n".join(
+        [json.dumps(l) for l in lines]))
+    print("transaction---" + response.text)
+    assert response.status_code == 555
+    assert "write:2" in json.loads(response.text)["message"]
+
+    get_url = "http://" + config.ROUTER + "/get/t1/tx?sort_key="
+    v = json.loads(requests.get(get_url + "a").text)
+    assert v["doc"]["_source"]["age"] == 1
+    assert v["doc"]["_version"] == 1
+    assert requests.get(get_url + "b").status_code == 200
+
+    # version conflict fails all
+    lines[2] = {"type": "update", "id": "tx",
+                "sort_key": "a", "if_version": 5, "doc": {"age": 30}}
+    lines[0] = {"type": "put", "id": "tx", "sort_key": "c",
+                "doc": {"name": ["c"], "age": 3, "content": "tx"}}
+    response = requests.post(url, data="\n".join(
+        [json.dumps(l) for l in lines]))
+    assert response.status_code == 557
+    assert requests.get(get_url + "c").status_code != 200
+
+    lines[2]["if_version"] = 1
+    response = requests.post(url, data="\n".join(
+        [json.dumps(l) for l in lines]))
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert [i["version"] for i in v["items"]] == [1, 2, 2]
+    assert requests.get(get_url + "b").status_code != 200
+    v = json.loads(requests.get(get_url + "a").text)
+    assert v["doc"]["_source"]["age"] == 30
+
+    # the same document twice
+    response = requests.post(url, data="\n".join(
+        [json.dumps(l) for l in [lines[0], lines[0]]]))
+    assert response.status_code == 552
+
+    # the documents in different partitions
+    lines = [json.dumps({"type": "put", "id": str(i), "doc": {
+                        "name": ["ansj"], "age": i, "content": "tx"}}) for i in range(20)]
+    response = requests.post(url, data="\n".join(lines))
+    assert response.status_code == 552
@@ -0,0 +1,77 @@
+import pytest
+import requests
+import json
+import random
+import config
+import time
+
+
+def test_del_collection():
+    url = "http://" + config.MASTER + "/collection/delete/t1"
+    response = requests.delete(url)
+    print("collection_delete---\n" + response.text)
+
+    assert response.status_code == 200 or response.status_code == 555
+
+
+def test_create_collection():
+    url = "http://" + config.MASTER + "/collection/create"
+    headers = {"content-type": "application/json"}
+    data = {
+        "name": "t1",
+        "partition_num": 3,
+        "partition_replica_num": 1,
+        "fields": [
+            {"int": {"name": "age", "none": False}}
+        ]
+    }
+    print(url + "---" + json.dumps(data))
+    response = requests.post(url, headers=headers, data=json.dumps(data))
+    print("space_create---\n" + response.text)
+    assert response.status_code == 200
+    time.sleep(5)  # TODO: FIX ME wait raft ok
+
+
+def test_put():
+    headers = {"content-type": "application/json"}
+    for i in range(30):
+        url = "http://" + config.ROUTER + "/put/t1/user"
+        data = {"sort_key": "k%02d" % i, "doc": {"age": i}}
+        response = requests.post(url, headers=headers, data=json.dumps(data))
+        assert response.status_code == 200
+    # other id must not be scanned
+    url = "http://" + config.ROUTER + "/put/t1/user1"
+    response = requests.post(url, headers=headers, data=json.dumps(
+        {"sort_key": "k00", "doc": {"age": 100}}))
+    assert response.status_code == 200
+
+
+def scan(params):
+    url = "http://" + config.ROUTER + "/scan/t1/user"
+    response = requests.get(url, params=params)
+    print("scan---" + response.text)
+    assert response.status_code == 200
+    return json.loads(response.text)
+
+
+def test_scan_range():
+    v = scan({"start": "k05", "end": "k10"})
+    assert [d["_sort_key"] for d in v["docs"]] == ["k%02d" % i for i in range(5, 10)]
+    assert v["token"] == ""
+
+
+def test_scan_prefix_reverse():
+    v = scan({"prefix": "k1", "reverse": "true"})
+    assert [d["_sort_key"] for d in v["docs"]] == ["k%02d" % i for i in range(19, 9, -1)]
+
+
+def test_scan_token():
+    keys = []
+    token = ""
+    while True:
+        v = scan({"limit": 7, "token": token})
+        keys.extend([d["_sort_key"] for d in v["docs"]])
+        token = v["token"]
+        if token == "":
+            break
+    assert keys == ["k%02d" % i for i in range(30)]
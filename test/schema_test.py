@@ -0,0 +1,82 @@
+import pytest
+import requests
+import json
+import random
+import config
+import time
+
+
+def test_del_collection():
+    url = "http://" + config.MASTER + "/collection/delete/t1"
+    response = requests.delete(url)
+    print("collection_delete---\n" + response.text)
+
+    assert response.status_code == 200 or response.status_code == 555
+
+
+def test_create_collection():
+    url = "http://" + config.MASTER + "/collection/create"
+    headers = {"content-type": "application/json"}
+    data = {
+        "name": "t1",
+        "partition_num": 1,
+        "partition_replica_num": 1,
+        "fields": [
+            {"int": {"name": "age", "none": False}}
+        ]
+    }
+    print(url + "---" + json.dumps(data))
+    response = requests.post(url, headers=headers, data=json.dumps(data))
+    print("space_create---\n" + response.text)
+    assert response.status_code == 200
+    time.sleep(5)  # TODO: FIX ME wait raft ok
+
+
+def test_put():
+    headers = {"content-type": "application/json"}
+    for i in range(20):
+        url = "http://" + config.ROUTER + "/put/t1/" + str(i)
+        data = {"doc": {"age": i, "tag": "red" if i % 2 == 0 else "blue"}}
+        response = requests.post(url, headers=headers, data=json.dumps(data))
+        assert response.status_code == 200
+
+
+def add_fields(fields):
+    url = "http://" + config.MASTER + "/collection/field/add"
+    headers = {"content-type": "application/json"}
+    data = {"collection_name": "t1", "fields": fields}
+    response = requests.post(url, headers=headers, data=json.dumps(data))
+    print("field_add---\n" + response.text)
+    return response
+
+
+def test_add_invalid_field():
+    # the old documents not have it
+    assert add_fields([{"string": {"name": "tag", "none": False}}]).status_code != 200
+    # exists already
+    assert add_fields([{"int": {"name": "age", "none": True}}]).status_code != 200
+
+
+def test_add_field():
+    response = add_fields([{"string": {"name": "tag", "none": True}}])
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert v["version"] == 1
+    time.sleep(5)
+
+    # the new document after add field
+    url = "http://" + config.ROUTER + "/put/t1/20"
+    response = requests.post(url, headers={"content-type": "application/json"},
+                             data=json.dumps({"doc": {"age": 20, "tag": "red"}}))
+    assert response.status_code == 200
+    time.sleep(2)
+
+
+def test_search_new_field():
+    response = requests.get(
+        "http://" + config.ROUTER + "/search/t1?query=red&size=100&def_fields=tag")
+    print("search---\n" + response.text)
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert v["code"] == 200
+    assert v["total"] == 11
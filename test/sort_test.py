@@ -172,3 +172,63 @@ def test_search():
     assert v["hits"][1]["doc"]["_source"]["birthday"] == "2016-06-07"
     assert v["hits"][2]["doc"]["_source"]["birthday"] == "2016-06-08"
     assert v["hits"][3]["doc"]["_source"]["birthday"] == "2016-07-07"
+
+
+def test_search_cursor():
+    response = requests.get(
+        "http://"+config.ROUTER+"/search/t1?size=100&sort=age:asc")
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    ids = [h["doc"]["_id"] for h in v["hits"]]
+
+    pages = []
+    cursor = ""
+    while True:
+        response = requests.get(
+            "http://"+config.ROUTER+"/search/t1?size=2&sort=age:asc&cursor="+cursor)
+        print("search_cursor---\n" + response.text)
+        assert response.status_code == 200
+        v = json.loads(response.text)
+        if len(v["hits"]) == 0:
+            break
+        pages.extend([h["doc"]["_id"] for h in v["hits"]])
+        cursor = v["cursor"]
+
+    assert pages == ids
+
+
+def search_dsl(dsl):
+    url = "http://"+config.ROUTER+"/search/t1"
+    headers = {"content-type": "application/json"}
+    data = {"dsl": dsl, "size": 10, "sort": "age:asc"}
+    response = requests.post(url, headers=headers, data=json.dumps(data))
+    print("search_dsl---\n" + response.text)
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert v["code"] == 200
+    return [h["doc"]["_source"]["age"] for h in v["hits"]]
+
+
+def test_search_dsl():
+    assert search_dsl({"range": {"age": {"gte": 35, "lt": 40}}}) == [35, 36, 37]
+    assert search_dsl({"bool": {"must": {"term": {"name": "ansj"}},
+                                "must_not": {"range": {"age": {"gt": 50}}}}}) == [35]
+    assert search_dsl({"match_phrase": {"content": "hello tig11"}}) == [33, 37]
+    assert search_dsl({"terms": {"name": ["ansj1", "ansj2"]}}) == [36, 37]
+    assert search_dsl({"prefix": {"name": "ansj"}}) == [33, 35, 36, 37, 72]
+    assert search_dsl({"wildcard": {"name": "ansj?"}}) == [33, 36, 37]
+    assert search_dsl({"exists": {"field": "price"}}) == [33, 35, 36, 37, 72]
+    assert search_dsl({"bool": {"must_not": {"term": {"age": 72}}}}) == [33, 35, 36, 37]
+
+    # the dsl and query both must match
+    response = requests.get("http://"+config.ROUTER+"/search/t1", params={
+        "query": "tig11", "def_fields": "content", "sort": "age:asc",
+        "dsl": json.dumps({"range": {"age": {"gt": 34}}})})
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert [h["doc"]["_source"]["age"] for h in v["hits"]] == [37]
+
+    url = "http://"+config.ROUTER+"/search/t1"
+    response = requests.post(url, headers={"content-type": "application/json"},
+                             data=json.dumps({"dsl": {"unknown": {"age": 1}}}))
+    assert response.status_code != 200
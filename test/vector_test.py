@@ -73,3 +73,216 @@ def test_search():
     v = json.loads(response.text)
     assert v["code"] == 200
     assert v["hits"][0]["doc"]["_source"]["name"][:5] == "name_"
+
+
+def test_hybrid_search():
+    headers = {"content-type": "application/json"}
+    for mode in ["linear", "rrf"]:
+        data = {
+            "query": "hello tig",
+            "def_fields": "content",
+            "vector_query": {"field": "photo", "vector": np.random.rand(128).tolist()},
+            "hybrid": {"mode": mode, "window": 50},
+            "size": 10
+        }
+        response = requests.post(
+            "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+        print("hybrid_search---\n" + response.text)
+        assert response.status_code == 200
+        v = json.loads(response.text)
+        assert v["code"] == 200
+        assert len(v["hits"]) == 10
+        scores = [h["score"] for h in v["hits"]]
+        assert scores == sorted(scores, reverse=True)
+
+    data["hybrid"]["mode"] = "max"
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    assert response.status_code != 200
+
+
+def test_batch_search():
+    headers = {"content-type": "application/json"}
+    data = {
+        "vector_query": {"field": "photo", "vectors": [np.random.rand(128).tolist() for _ in range(3)]},
+        "size": 5
+    }
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    print("batch_search---\n" + response.text)
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert v["code"] == 200
+    assert len(v["hits"]) == 0
+    assert len(v["batch"]) == 3
+    for hits in v["batch"]:
+        assert len(hits) == 5
+        scores = [h["score"] for h in hits]
+        assert scores == sorted(scores, reverse=True)
+
+    data["vector_query"]["vectors"] = [[0.1, 0.2]]
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    assert response.status_code != 200
+
+
+def test_search_params():
+    headers = {"content-type": "application/json"}
+    data = {
+        "vector_query": {"field": "photo", "vector": np.random.rand(128).tolist(), "nprobe": 16, "rerank": 3},
+        "size": 5
+    }
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    print("search_params---\n" + response.text)
+    assert response.status_code == 200
+    v = json.loads(response.text)
+    assert len(v["hits"]) == 5
+    scores = [h["score"] for h in v["hits"]]
+    assert scores == sorted(scores, reverse=True)
+
+    data["vector_query"]["ef_search"] = 64
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    assert response.status_code != 200
+
+
+def test_search_threshold():
+    headers = {"content-type": "application/json"}
+    data = {
+        "vector_query": {"field": "photo", "vector": np.random.rand(128).tolist()},
+        "size": 10
+    }
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    scores = [h["score"] for h in json.loads(response.text)["hits"]]
+    assert len(scores) == 10
+    for s in scores:
+        assert 0 < s <= 1
+
+    # keep the hits not weaker than the middle one
+    data["vector_query"]["min_score"] = scores[4]
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    print("search_threshold---\n" + response.text)
+    assert response.status_code == 200
+    hits = json.loads(response.text)["hits"]
+    assert 0 < len(hits) <= 10
+    for h in hits:
+        assert h["score"] >= scores[4]
+
+    data["vector_query"]["min_score"] = 0
+    data["vector_query"]["max_distance"] = 1 / scores[4] - 1
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    assert len(json.loads(response.text)["hits"]) <= 10
+
+
+def test_cosine():
+    headers = {"content-type": "application/json"}
+    requests.delete("http://" + config.MASTER + "/collection/delete/t_cosine")
+    data = {
+        "name": "t_cosine",
+        "partition_num": 1,
+        "partition_replica_num": 1,
+        "fields": [
+            {"string": {"name": "name"}},
+            {
+                "vector": {
+                    "name": "photo",
+                    "train_size": 0,
+                    "dimension": 3,
+                    "description": "Flat",
+                    "metric_type": "Cosine"
+                }
+            }
+        ]
+    }
+    response = requests.post("http://" + config.MASTER + "/collection/create",
+                             headers=headers, data=json.dumps(data))
+    print("cosine_create---\n" + response.text)
+    assert response.status_code == 200
+    time.sleep(5)  # TODO: FIX ME wait raft ok
+
+    for i, v in enumerate([[3, 4, 0], [0, 0, 5], [-3, -4, 0]]):
+        response = requests.post("http://" + config.ROUTER + "/put/t_cosine/" + str(i),
+                                 headers=headers, data=json.dumps({"name": "n" + str(i), "photo": v}))
+        assert response.status_code == 200
+
+    # zero vector can not be normalized
+    response = requests.post("http://" + config.ROUTER + "/put/t_cosine/zero",
+                             headers=headers, data=json.dumps({"name": "zero", "photo": [0, 0, 0]}))
+    assert response.status_code != 200
+
+    time.sleep(5)
+    data = {"vector_query": {"field": "photo", "vector": [6, 8, 0]}, "size": 3}
+    response = requests.post("http://" + config.ROUTER + "/search/t_cosine",
+                             headers=headers, data=json.dumps(data))
+    print("cosine_search---\n" + response.text)
+    assert response.status_code == 200
+    hits = json.loads(response.text)["hits"]
+    assert hits[0]["doc"]["_id"] == "0"
+    assert abs(hits[0]["score"] - 1) < 1e-5
+    assert abs(hits[1]["score"]) < 1e-5
+    assert abs(hits[2]["score"] + 1) < 1e-5
+
+    data["vector_query"]["vector"] = [0, 0, 0]
+    response = requests.post("http://" + config.ROUTER + "/search/t_cosine",
+                             headers=headers, data=json.dumps(data))
+    assert response.status_code != 200
+
+
+def test_projection():
+    response = requests.get("http://"+config.ROUTER+"/get/t1/1")
+    assert response.status_code == 200
+    source = json.loads(response.text)["doc"]["_source"]
+    assert source["name"] == "name_1"
+    assert "photo" not in source
+
+    response = requests.get(
+        "http://"+config.ROUTER+"/get/t1/1?includes=name,photo&vectors=true")
+    print("projection---\n" + response.text)
+    assert response.status_code == 200
+    source = json.loads(response.text)["doc"]["_source"]
+    assert sorted(source.keys()) == ["name", "photo"]
+    assert len(source["photo"]) == 128
+
+    headers = {"content-type": "application/json"}
+    data = {
+        "query": "hello tig",
+        "def_fields": "content",
+        "excludes": ["content", "age"],
+        "size": 3
+    }
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    for hit in json.loads(response.text)["hits"]:
+        assert list(hit["doc"]["_source"].keys()) == ["name"]
+
+
+def test_highlight():
+    headers = {"content-type": "application/json"}
+    data = {
+        "query": "tig",
+        "def_fields": "content",
+        "highlight": {"fields": ["content"], "pre_tag": "[", "post_tag": "]"},
+        "size": 3
+    }
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    print("highlight---\n" + response.text)
+    assert response.status_code == 200
+    hits = json.loads(response.text)["hits"]
+    assert len(hits) == 3
+    for hit in hits:
+        fragments = hit["highlight"]["content"]
+        assert len(fragments) == 1
+        assert "[tig]" in fragments[0]
+
+    data["highlight"] = {"fields": ["name"]}
+    response = requests.post(
+        "http://"+config.ROUTER+"/search/t1", headers=headers, data=json.dumps(data))
+    assert response.status_code != 200
@@ -35,6 +35,7 @@ def test_create_collection():
 
 
 def test_put():
+    test_delete()
     url = "http://" + config.ROUTER + "/put/t1/1"
     headers = {"content-type": "application/json"}
     data = {
@@ -46,18 +47,20 @@ def test_put():
     response = requests.post(url, headers=headers, data=json.dumps(data))
     print("doc put ---\n" + response.text)
     assert response.status_code == 200
+    version = json.loads(response.text)["version"]
 
     response = requests.get("http://"+config.ROUTER+"/get/t1/1")
     print("get---" + response.text)
     assert response.status_code == 200
     v = json.loads(response.text)
     assert v["code"] == 200
-    assert v["doc"]["_version"] == 1
+    assert v["doc"]["_version"] == version
     assert v["doc"]["_source"]["name"] == ["ansj", "sun"]
+    return version
 
 
 def test_update():
-    test_put()
+    version = test_put()
     url = "http://" + config.ROUTER + "/update/t1/1"
     headers = {"content-type": "application/json"}
     data = {
@@ -77,7 +80,7 @@ def test_update():
     assert v["code"] == 200
     assert v["doc"]["_source"]["name"] == ["ansj", "ansj"]
     assert v["doc"]["_source"]["age"] == 35
-    assert v["doc"]["_version"] == 2
+    assert v["doc"]["_version"] == version + 1
     # diff update
     url = "http://" + config.ROUTER + "/update/t1/1"
     headers = {"content-type": "application/json"}
@@ -93,7 +96,7 @@ def test_update():
     print("get--" + response.text)
     assert response.status_code == 200
     v = json.loads(response.text)
-    assert v["doc"]["_version"] == 3
+    assert v["doc"]["_version"] == version + 2
     assert v["doc"]["_source"]["age"] == 33
 
 
@@ -123,6 +126,7 @@ def test_create():
     response = requests.post(url, headers=headers, data=json.dumps(data))
     print("create---\n" + response.text)
     assert response.status_code == 200
+    version = json.loads(response.text)["version"]
 
     # second create
     url = "http://" + config.ROUTER + "/create/t1/1"
@@ -135,13 +139,13 @@ def test_create():
     print(url + "---" + json.dumps(data))
     response = requests.post(url, headers=headers, data=json.dumps(data))
     print("create---\n" + response.text)
-    assert response.status_code == 550
+    assert response.status_code == 556
     # get doc
     response = requests.get("http://"+config.ROUTER+"/get/t1/1")
     print("get--" + response.text)
     assert response.status_code == 200
     v = json.loads(response.text)
-    assert v["doc"]["_version"] == 1
+    assert v["doc"]["_version"] == version
 
 
 def test_upsert():
@@ -159,6 +163,7 @@ def test_upsert():
     response = requests.post(url, headers=headers, data=json.dumps(data))
     print("upsert---" + response.text)
     assert response.status_code == 200
+    version = json.loads(response.text)["version"]
     # find by id
     response = requests.get("http://"+config.ROUTER+"/get/t1/1")
     print("get---" + response.text)
@@ -166,7 +171,7 @@ def test_upsert():
     v = json.loads(response.text)
     assert v["code"] == 200
     assert v["doc"]["_source"]["name"] == ["ansj", "sun"]
-    assert v["doc"]["_version"] == 1
+    assert v["doc"]["_version"] == version
     # same upsert
     url = "http://" + config.ROUTER + "/upsert/t1/1"
     headers = {"content-type": "application/json"}
@@ -184,7 +189,7 @@ def test_upsert():
     print("get ---" + response.text)
     assert response.status_code == 200
     v = json.loads(response.text)
-    assert v["doc"]["_version"] == 2
+    assert v["doc"]["_version"] == version + 1
     assert v["doc"]["_source"]["name"] == ["ansj", "ansj"]
 
     # diff upsert
@@ -203,7 +208,7 @@ def test_upsert():
     print("get--" + response.text)
     assert response.status_code == 200
     v = json.loads(response.text)
-    assert v["doc"]["_version"] == 3
+    assert v["doc"]["_version"] == version + 2
     assert v["doc"]["_source"]["name"] == ["ansj", "sun"]
     assert v["doc"]["_source"]["age"] == 36
     assert v["doc"]["_source"]["content"] == "hello tig"
@@ -218,3 +223,173 @@ def test_search():
     v = json.loads(response.text)
     assert v["code"] == 200
     assert v["hits"][0]["doc"]["_source"]["name"] == ["ansj", "sun"]
+
+
+def test_version():
+    headers = {"content-type": "application/json"}
+    url = "http://" + config.ROUTER
+    requests.delete(url + "/delete/t1/v1")
+    data = {"name": ["ansj"], "age": 1, "content": "hello version"}
+
+    response = requests.post(
+        url + "/put/t1/v1?if_absent=true", headers=headers, data=json.dumps(data))
+    print("put---" + response.text)
+    assert response.status_code == 200
+    assert response.headers["X-Version"] == "1"
+    response = requests.post(
+        url + "/put/t1/v1?if_absent=true", headers=headers, data=json.dumps(data))
+    assert response.status_code == 556
+
+    response = requests.post(
+        url + "/put/t1/v1", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    assert json.loads(response.text)["version"] == 2
+
+    response = requests.post(
+        url + "/update/t1/v1?if_version=1", headers=headers, data=json.dumps({"age": 2}))
+    assert response.status_code == 557
+    response = requests.post(
+        url + "/update/t1/v1?if_version=2", headers=headers, data=json.dumps({"age": 2}))
+    assert response.status_code == 200
+    assert response.headers["X-Version"] == "3"
+
+    response = requests.post(
+        url + "/upsert/t1/v1?if_version=2", headers=headers, data=json.dumps({"age": 3}))
+    assert response.status_code == 557
+    response = requests.post(
+        url + "/upsert/t1/v1?if_version=3", headers=headers, data=json.dumps({"age": 3}))
+    assert response.status_code == 200
+    assert response.headers["X-Version"] == "4"
+
+    response = requests.get(url + "/get/t1/v1")
+    v = json.loads(response.text)
+    assert v["doc"]["_version"] == 4
+    assert v["doc"]["_source"]["age"] == 3
+
+    response = requests.delete(url + "/delete/t1/v1?if_version=3")
+    assert response.status_code == 557
+    response = requests.delete(url + "/delete/t1/v1?if_version=4")
+    assert response.status_code == 200
+    assert response.headers["X-Version"] == "5"
+
+    response = requests.post(
+        url + "/put/t1/v1?if_version=1", headers=headers, data=json.dumps(data))
+    assert response.status_code == 557
+    # the version continues from the deleted one
+    response = requests.post(
+        url + "/create/t1/v1", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    assert response.headers["X-Version"] == "6"
+
+
+def test_update_operators():
+    headers = {"content-type": "application/json"}
+    url = "http://" + config.ROUTER
+    requests.delete(url + "/delete/t1/op1")
+    data = {"name": ["a", "b"], "age": 1, "content": "hello operator"}
+    response = requests.post(
+        url + "/put/t1/op1", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+
+    data = {
+        "$inc": {"age": 2},
+        "$append": {"name": ["c"]},
+        "$remove": {"name": "a"},
+        "$set_if_missing": {"content": "not set"}
+    }
+    response = requests.post(
+        url + "/update/t1/op1", headers=headers, data=json.dumps(data))
+    print("update---" + response.text)
+    assert response.status_code == 200
+
+    response = requests.get(url + "/get/t1/op1")
+    v = json.loads(response.text)
+    assert v["doc"]["_version"] == 2
+    assert v["doc"]["_source"]["age"] == 3
+    assert v["doc"]["_source"]["name"] == ["b", "c"]
+    assert v["doc"]["_source"]["content"] == "hello operator"
+
+    response = requests.post(
+        url + "/update/t1/op1", headers=headers, data=json.dumps({"$inc": {"content": 1}}))
+    assert response.status_code != 200
+
+    # the concurrent increments are not lost
+    from concurrent.futures import ThreadPoolExecutor
+
+    def inc(_):
+        return requests.post(url + "/update/t1/op1", headers=headers,
+                             data=json.dumps({"$inc": {"age": 1}})).status_code
+
+    with ThreadPoolExecutor(max_workers=10) as pool:
+        codes = list(pool.map(inc, range(50)))
+    assert codes == [200] * 50
+
+    response = requests.get(url + "/get/t1/op1")
+    assert json.loads(response.text)["doc"]["_source"]["age"] == 53
+
+
+def test_ttl():
+    headers = {"content-type": "application/json"}
+    url = "http://" + config.ROUTER
+    requests.delete("http://" + config.MASTER + "/collection/delete/t_ttl")
+    data = {
+        "name": "t_ttl",
+        "partition_num": 1,
+        "partition_replica_num": 1,
+        "ttl": 2,
+        "fields": [
+            {"string": {"name": "name"}},
+            {"text": {"name": "content"}}
+        ]
+    }
+    response = requests.post("http://" + config.MASTER + "/collection/create",
+                             headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    time.sleep(5)
+
+    data = {"name": "a", "content": "hello ttl"}
+    for id in ["1", "2"]:
+        response = requests.post(
+            url + "/put/t_ttl/" + id, headers=headers, data=json.dumps(data))
+        assert response.status_code == 200
+    response = requests.post(
+        url + "/put/t_ttl/3?ttl=3600", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    expire_at = int(time.time() * 1000) + 3600 * 1000
+    response = requests.post(
+        url + "/put/t_ttl/4?expire_at=" + str(expire_at), headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    response = requests.post(
+        url + "/put/t_ttl/5?expire_at=1000", headers=headers, data=json.dumps(data))
+    assert response.status_code == 552
+
+    # update keeps the expire time
+    response = requests.post(
+        url + "/update/t_ttl/3", headers=headers, data=json.dumps({"name": "b"}))
+    assert response.status_code == 200
+    v = json.loads(requests.get(url + "/get/t_ttl/3").text)
+    assert v["doc"]["_expire_at"] > expire_at - 60 * 1000
+    v = json.loads(requests.get(url + "/get/t_ttl/4").text)
+    assert v["doc"]["_expire_at"] == expire_at
+
+    time.sleep(5)
+
+    for id in ["1", "2"]:
+        response = requests.get(url + "/get/t_ttl/" + id)
+        assert response.status_code == 555
+    response = requests.get(url + "/search/t_ttl?query=hello&def_fields=content&size=10")
+    v = json.loads(response.text)
+    assert v["total"] == 2
+    assert sorted([h["doc"]["_id"] for h in v["hits"]]) == ["3", "4"]
+
+    # the expired document is taken as not found
+    response = requests.post(
+        url + "/update/t_ttl/1", headers=headers, data=json.dumps({"name": "c"}))
+    assert response.status_code == 555
+    response = requests.post(
+        url + "/create/t_ttl/1?ttl=3600", headers=headers, data=json.dumps(data))
+    assert response.status_code == 200
+    assert response.headers["X-Version"] == "3"
+    time.sleep(5)
+    response = requests.get(url + "/search/t_ttl?query=hello&def_fields=content&size=10")
+    assert json.loads(response.text)["total"] == 3